@@ -0,0 +1,114 @@
+use crate::index::canonical_crate_name;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Number of records returned by [`AuditSink::query`] if the query has no limit.
+pub const DEFAULT_AUDIT_QUERY_LIMIT: usize = 100;
+/// Most records that [`AuditSink::query`] returns at once.
+pub const MAX_AUDIT_QUERY_LIMIT: usize = 1000;
+
+/// A registry operation that changes something, as it's kept in the audit log.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub operation: AuditOperation,
+    /// Login of the user who made the request, if it could be determined.
+    pub actor: Option<String>,
+    /// Identifies the token used, without revealing it.
+    pub token_id: Option<String>,
+    #[serde(rename = "crate")]
+    pub crate_name: Option<String>,
+    pub version: Option<Version>,
+    pub source_ip: Option<IpAddr>,
+    pub outcome: AuditOutcome,
+    /// HTTP status of the response.
+    pub status: u16,
+    /// Operation-specific information, like the users added as owners.
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    feature = "postgres",
+    derive(postgres_types::ToSql, postgres_types::FromSql),
+    postgres(name = "audit_operation")
+)]
+pub enum AuditOperation {
+    #[cfg_attr(feature = "postgres", postgres(name = "publish"))]
+    Publish,
+    #[cfg_attr(feature = "postgres", postgres(name = "yank"))]
+    Yank,
+    #[cfg_attr(feature = "postgres", postgres(name = "unyank"))]
+    Unyank,
+    #[cfg_attr(feature = "postgres", postgres(name = "add_owners"))]
+    AddOwners,
+    #[cfg_attr(feature = "postgres", postgres(name = "remove_owners"))]
+    RemoveOwners,
+    #[cfg_attr(feature = "postgres", postgres(name = "register"))]
+    Register,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    feature = "postgres",
+    derive(postgres_types::ToSql, postgres_types::FromSql),
+    postgres(name = "audit_outcome")
+)]
+pub enum AuditOutcome {
+    #[cfg_attr(feature = "postgres", postgres(name = "success"))]
+    Success,
+    #[cfg_attr(feature = "postgres", postgres(name = "failure"))]
+    Failure,
+}
+
+/// Filters for [`AuditSink::query`]. Fields that are `None` match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    /// Crate names are matched ignoring case and `-`/`_` differences.
+    #[serde(rename = "crate")]
+    pub crate_name: Option<String>,
+    pub version: Option<Version>,
+    pub actor: Option<String>,
+    pub operation: Option<AuditOperation>,
+    /// Maximum number of records to return, [`DEFAULT_AUDIT_QUERY_LIMIT`] by default.
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    /// Number of records to return, at most [`MAX_AUDIT_QUERY_LIMIT`].
+    #[must_use]
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_AUDIT_QUERY_LIMIT).min(MAX_AUDIT_QUERY_LIMIT)
+    }
+
+    /// Whether the record passes the filters, for sinks that can't filter natively.
+    #[must_use]
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        self.crate_name.as_deref().is_none_or(|name| {
+            record.crate_name.as_deref().is_some_and(|r| canonical_crate_name(r) == canonical_crate_name(name))
+        }) && self.version.as_ref().is_none_or(|v| record.version.as_ref() == Some(v))
+            && self.actor.as_ref().is_none_or(|a| record.actor.as_ref() == Some(a))
+            && self.operation.is_none_or(|o| record.operation == o)
+    }
+}
+
+/// Durable log of operations that change the registry, like publishes, yanks and owner changes.
+#[async_trait]
+pub trait AuditSink {
+    /// Save a record. The operation has already happened, so failures can't undo it.
+    async fn record(&self, record: &AuditRecord) -> anyhow::Result<()>;
+
+    /// Get the records matching the query, newest first.
+    async fn query(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditRecord>>;
+}
+
+/// Response of the audit log endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecords {
+    pub records: Vec<AuditRecord>,
+}
@@ -0,0 +1,28 @@
+//! Comparisons of crate names, which ignore case and `-`/`_` differences like crates.io
+
+/// Crate names that are equal after this normalization are names of the same crate,
+/// like on crates.io: case is ignored, and `-` is the same as `_`.
+#[must_use]
+pub fn canonical_crate_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('-', "_")
+}
+
+/// Whether the crate name matches the pattern, where `*` matches any number of characters,
+/// and `?` matches one character. Both are compared like [`canonical_crate_name`]s.
+#[must_use]
+pub fn crate_name_matches(pattern: &str, name: &str) -> bool {
+    glob_match(
+        canonical_crate_name(pattern).as_bytes(),
+        canonical_crate_name(name).as_bytes(),
+    )
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| glob_match(rest, &name[skip..])),
+        Some((&p, rest)) => name
+            .split_first()
+            .is_some_and(|(&n, name_rest)| (p == b'?' || p == n) && glob_match(rest, name_rest)),
+    }
+}
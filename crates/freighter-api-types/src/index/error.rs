@@ -8,8 +8,8 @@ use thiserror::Error;
 pub enum IndexError {
     #[error("A resource conflict occurred while attempting an operation: {0}")]
     Conflict(String),
-    #[error("Requested a crate with a name that is too long (64) or contains non-ASCII characters or punctuation")]
-    CrateNameNotAllowed,
+    #[error("Crate name is not allowed: {0}")]
+    CrateNameNotAllowed(String),
     #[error("Failed to find the resource")]
     NotFound,
     #[error("Encountered uncategorized error")]
@@ -42,7 +42,7 @@ impl IntoResponse for IndexError {
                 StatusCode::CONFLICT
             }
             Self::NotFound => StatusCode::NOT_FOUND,
-            Self::CrateNameNotAllowed => StatusCode::BAD_REQUEST,
+            Self::CrateNameNotAllowed(_) => StatusCode::BAD_REQUEST,
             Self::ServiceError(error) => {
                 tracing::error!(?error, "Encountered service error in index operation");
 
@@ -1,11 +1,14 @@
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use semver::Version;
 use std::future::Future;
 use std::pin::Pin;
 
 use request::{ListQuery, Publish, PublishDependency};
-use response::{CompletedPublication, CrateVersion, ListAll, SearchResults};
+use response::{
+    CompletedPublication, CrateDetails, CrateDownloads, CrateVersion, ListAll, ReverseDependencies,
+    SearchResults, VersionDetails,
+};
 
 #[cfg(any(feature = "index", feature = "server", feature = "client"))]
 use serde::{Deserialize, Serialize};
@@ -18,6 +21,8 @@ pub use error::*;
 
 pub type IndexResult<T> = Result<T, IndexError>;
 
+pub use crate::crate_name::canonical_crate_name;
+
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 #[cfg_attr(
     any(feature = "index", feature = "server", feature = "client"),
@@ -67,7 +72,8 @@ impl From<response::CrateVersion> for request::Publish {
                     }
                 })
                 .collect(),
-            features: value.features,
+            // Publish has no separate field for the extended feature syntax
+            features: value.features.into_iter().chain(value.features2).collect(),
             // Note: We do not carry over authors since its not in index
             authors: Vec::new(),
             description: None,
@@ -81,7 +87,7 @@ impl From<response::CrateVersion> for request::Publish {
             license_file: None,
             repository: None,
             badges: None,
-            links: None,
+            links: value.links,
         }
     }
 }
@@ -91,6 +97,15 @@ pub struct SparseEntries {
     pub last_modified: Option<DateTime<Utc>>,
 }
 
+/// Details of a publish that aren't part of its [`Publish`] metadata
+#[derive(Debug, Clone, Default)]
+pub struct PublishContext {
+    /// Login of the user who has published the version, if the auth backend knows it
+    pub publisher: Option<String>,
+    /// Size of the `.crate` file in bytes
+    pub tarball_size: u64,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct CrateVersionExists {
     pub yanked: bool,
@@ -98,6 +113,18 @@ pub struct CrateVersionExists {
     pub tarball_checksum: [u8; 32],
 }
 
+/// Number of downloads of a crate version on a day (in UTC)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DownloadCount {
+    pub name: String,
+    pub version: Version,
+    pub date: NaiveDate,
+    pub downloads: u64,
+}
+
+/// How many days of download counts are returned by [`IndexProvider::get_downloads`], like on crates.io
+pub const DOWNLOADS_HISTORY_DAYS: u64 = 90;
+
 /// A client for talking with a backing index database or storage medium.
 ///
 /// Operations performed via this client MUST be atomic.
@@ -123,6 +150,18 @@ pub trait IndexProvider: Sync {
     /// If an error occurs while trying to generate the sparse entry, [`IndexError::ServiceError`]
     /// will be returned.
     async fn get_sparse_entry(&self, crate_name: &str) -> IndexResult<SparseEntries>;
+    /// Get the metadata of a crate, and a list of its versions.
+    ///
+    /// If the crate could not be found in the index, [`IndexError::NotFound`] will be returned.
+    async fn get_crate_details(&self, crate_name: &str) -> IndexResult<CrateDetails>;
+    /// Get the metadata of one version of a crate, as it was when that version was published.
+    ///
+    /// If the version could not be found in the index, [`IndexError::NotFound`] will be returned.
+    async fn get_version_details(
+        &self,
+        crate_name: &str,
+        version: &Version,
+    ) -> IndexResult<VersionDetails>;
     /// Confirm that a particular crate and version pair exists, and return its yank status
     async fn confirm_existence(
         &self,
@@ -146,10 +185,23 @@ pub trait IndexProvider: Sync {
         &self,
         version: &Publish,
         tarball_checksum: [u8; 32],
+        context: &PublishContext,
         end_step: Pin<&mut (dyn Future<Output = IndexResult<()>> + Send)>,
     ) -> IndexResult<CompletedPublication>;
     /// List crates in the index, optionally specifying pagination.
     ///
     /// If no pagination is provided, all crates should be returned.
     async fn list(&self, pagination: &ListQuery) -> IndexResult<ListAll>;
+    /// Add to the download counts of crate versions.
+    ///
+    /// Counts of crates or versions that don't exist are ignored.
+    async fn record_downloads(&self, downloads: &[DownloadCount]) -> IndexResult<()>;
+    /// Daily download counts of all versions of a crate, from the last
+    /// [`DOWNLOADS_HISTORY_DAYS`] days.
+    async fn get_downloads(&self, crate_name: &str) -> IndexResult<CrateDownloads>;
+    /// Get all versions of crates in this registry that depend on a crate, sorted by their name
+    /// and version.
+    ///
+    /// If the crate could not be found in the index, [`IndexError::NotFound`] will be returned.
+    async fn get_reverse_dependencies(&self, crate_name: &str) -> IndexResult<ReverseDependencies>;
 }
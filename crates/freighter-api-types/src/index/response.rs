@@ -1,11 +1,11 @@
 use super::DependencyKind;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use semver::{Version, VersionReq};
 #[cfg(any(feature = "index", feature = "client"))]
 use serde::Deserialize;
 #[cfg(any(feature = "index", feature = "server"))]
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 #[cfg_attr(feature = "client", derive(Deserialize))]
 #[cfg_attr(feature = "server", derive(Serialize))]
@@ -61,7 +61,7 @@ pub struct CrateVersion {
     /// Set of features defined for the package.
     ///
     /// Each feature maps to an array of features or dependencies it enables.
-    pub features: HashMap<String, Vec<String>>,
+    pub features: BTreeMap<String, Vec<String>>,
     /// Boolean of whether or not this version has been yanked.
     #[cfg_attr(any(feature = "index", feature = "client"), serde(default))]
     #[cfg_attr(any(feature = "index", feature = "server"), serde(skip_serializing_if = "is_false"))]
@@ -103,9 +103,9 @@ pub struct CrateVersion {
     /// to include those in the "features" field. Using this is only necessary if the registry
     /// wants to support cargo versions older than 1.19, which in practice is only crates.io since
     /// those older versions do not support other registries.
-    #[cfg_attr(any(feature = "index", feature = "server"), serde(skip_serializing_if = "HashMap::is_empty"))]
+    #[cfg_attr(any(feature = "index", feature = "server"), serde(skip_serializing_if = "BTreeMap::is_empty"))]
     #[cfg_attr(any(feature = "index", feature = "client"), serde(default))]
-    pub features2: HashMap<String, Vec<String>>,
+    pub features2: BTreeMap<String, Vec<String>>,
 }
 
 #[cfg_attr(any(feature = "index", feature = "client"), derive(Deserialize))]
@@ -190,6 +190,9 @@ pub struct ListAllCrateEntry {
     pub keywords: Vec<String>,
     /// List of categories for the crate.
     pub categories: Vec<String>,
+    /// Total number of downloads of all versions.
+    #[cfg_attr(feature = "client", serde(default))]
+    pub downloads: u64,
 }
 
 #[cfg_attr(feature = "client", derive(Deserialize))]
@@ -222,6 +225,180 @@ pub struct SearchResultsEntry {
     pub max_version: Version,
     /// Textual description of the crate.
     pub description: String,
+    /// Total number of downloads of all versions.
+    #[cfg_attr(feature = "client", serde(default))]
+    pub downloads: u64,
+}
+
+/// Metadata of a crate, in the format of crates.io's `/crates/:name`
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct CrateDetails {
+    #[cfg_attr(any(feature = "client", feature = "server"), serde(rename = "crate"))]
+    pub krate: CrateDetailsEntry,
+    /// All published versions, newest first.
+    pub versions: Vec<CrateDetailsVersion>,
+}
+
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct CrateDetailsEntry {
+    /// Same as the name. crates.io uses it as the crate's id.
+    pub id: String,
+    /// Name of the crate.
+    pub name: String,
+    /// Textual description of the crate.
+    pub description: Option<String>,
+    /// Optional homepage for the crate.
+    pub homepage: Option<String>,
+    /// Optional documentation link.
+    pub documentation: Option<String>,
+    /// Optional repository link.
+    pub repository: Option<String>,
+    /// List of keywords for the crate.
+    pub keywords: Vec<String>,
+    /// List of categories for the crate.
+    pub categories: Vec<String>,
+    /// Date and time that this crate was created.
+    pub created_at: DateTime<Utc>,
+    /// Date and time that this crate was last updated.
+    pub updated_at: DateTime<Utc>,
+    /// The highest version that isn't yanked, see [`CrateDetailsVersion::max_version`].
+    pub max_version: Version,
+    /// Total number of downloads of all versions.
+    pub downloads: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct CrateDetailsVersion {
+    /// The version number.
+    pub num: Version,
+    /// Whether this version has been yanked.
+    pub yanked: bool,
+    /// Total number of downloads of this version.
+    pub downloads: u64,
+}
+
+impl CrateDetailsVersion {
+    /// The highest version that isn't yanked, or the highest version if all of them are yanked.
+    #[must_use]
+    pub fn max_version(versions: &[Self]) -> Option<Version> {
+        let max = |yanked_too| {
+            versions
+                .iter()
+                .filter(|v| yanked_too || !v.yanked)
+                .map(|v| &v.num)
+                .max()
+                .cloned()
+        };
+        max(false).or_else(|| max(true))
+    }
+}
+
+/// Metadata of one version of a crate, in the format of crates.io's `/crates/:name/:version`
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct VersionDetails {
+    pub version: VersionDetailsEntry,
+}
+
+/// Fields that weren't recorded by older versions of freighter may be missing.
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct VersionDetailsEntry {
+    /// Name of the crate.
+    #[cfg_attr(any(feature = "client", feature = "server"), serde(rename = "crate"))]
+    pub krate: String,
+    /// The version number.
+    pub num: Version,
+    /// A SHA256 checksum of the `.crate` file.
+    #[cfg_attr(any(feature = "client", feature = "server"), serde(with = "hex"))]
+    pub checksum: [u8; 32],
+    /// Whether this version has been yanked.
+    pub yanked: bool,
+    /// Features defined by this version.
+    pub features: BTreeMap<String, Vec<String>>,
+    /// Dependencies of this version, in the format of the index.
+    pub dependencies: Vec<Dependency>,
+    /// The `links` value from the package's manifest.
+    pub links: Option<String>,
+    /// Textual description of this version.
+    pub description: Option<String>,
+    /// License of this version, as an SPDX 2.1 expression.
+    pub license: Option<String>,
+    /// Path to a license file included in this version.
+    pub license_file: Option<String>,
+    /// Optional homepage of this version.
+    pub homepage: Option<String>,
+    /// Optional documentation link of this version.
+    pub documentation: Option<String>,
+    /// Optional repository link of this version.
+    pub repository: Option<String>,
+    /// Date and time that this version was published.
+    pub created_at: Option<DateTime<Utc>>,
+    /// The user who has published this version.
+    pub published_by: Option<VersionPublisher>,
+    /// Size of the `.crate` file in bytes.
+    pub crate_size: Option<u64>,
+    /// Total number of downloads of this version.
+    pub downloads: u64,
+}
+
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct VersionPublisher {
+    pub login: String,
+}
+
+/// Daily download counts, in the format of crates.io's `/crates/:name/downloads`.
+///
+/// Unlike crates.io, versions are identified by their version number instead of an id.
+#[derive(Default)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct CrateDownloads {
+    pub version_downloads: Vec<VersionDownloads>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct VersionDownloads {
+    pub version: Version,
+    pub downloads: u64,
+    pub date: NaiveDate,
+}
+
+/// Crate versions that depend on a crate, like crates.io's `/crates/:name/reverse_dependencies`.
+///
+/// Unlike crates.io, dependents are identified by their name and version number instead of an id.
+#[derive(Default)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct ReverseDependencies {
+    pub dependencies: Vec<ReverseDependency>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
+#[cfg_attr(feature = "server", derive(Serialize))]
+pub struct ReverseDependency {
+    /// Name of the dependent crate.
+    #[cfg_attr(any(feature = "client", feature = "server"), serde(rename = "crate"))]
+    pub krate: String,
+    /// Version of the dependent crate.
+    pub num: Version,
+    /// Whether the dependent version has been yanked.
+    pub yanked: bool,
+    /// The SemVer requirement the dependent version has on the crate.
+    pub req: VersionReq,
+    pub features: Vec<String>,
+    pub optional: bool,
+    pub default_features: bool,
+    pub target: Option<String>,
+    pub kind: DependencyKind,
 }
 
 #[cfg(any(feature = "index", feature = "client"))]
@@ -254,7 +431,7 @@ impl CrateVersion {
     }
 
     fn normalize_features(&mut self) {
-        let mut features_2 = HashMap::new();
+        let mut features_2 = BTreeMap::new();
 
         std::mem::swap(&mut features_2, &mut self.features2);
 
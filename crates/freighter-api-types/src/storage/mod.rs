@@ -2,20 +2,53 @@ pub use bytes::Bytes;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use futures_util::TryStreamExt;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
 
 pub use error::{StorageError, StorageResult};
 
 mod error;
 
+/// Chunks of a file body, for transfers that shouldn't be buffered in memory whole.
+pub type BodyStream = BoxStream<'static, io::Result<Bytes>>;
+
 #[async_trait]
-pub trait StorageProvider {
+pub trait StorageProvider: Sync {
     async fn pull_crate(
         &self,
         name: &str,
         version: &str,
         tarball_checksum: [u8; 32],
     ) -> StorageResult<FileResponse>;
+    /// Like [`StorageProvider::pull_crate`], but the body can be sent as it arrives.
+    ///
+    /// The default implementation buffers the whole crate using `pull_crate`.
+    async fn stream_crate(
+        &self,
+        name: &str,
+        version: &str,
+        tarball_checksum: [u8; 32],
+    ) -> StorageResult<StreamedFileResponse> {
+        let res = self.pull_crate(name, version, tarball_checksum).await?;
+        Ok(res.into())
+    }
+    /// A URL that clients can download the crate from directly, valid for `expires_in`.
+    ///
+    /// Returns `None` if the backend can't make such URLs, and the crate has to be served
+    /// by freighter. This is the default.
+    async fn presigned_crate_url(
+        &self,
+        _name: &str,
+        _version: &str,
+        _tarball_checksum: [u8; 32],
+        _expires_in: Duration,
+    ) -> StorageResult<Option<String>> {
+        Ok(None)
+    }
     async fn put_crate(
         &self,
         name: &str,
@@ -23,6 +56,34 @@ pub trait StorageProvider {
         crate_bytes: Bytes,
         sha256: [u8; 32],
     ) -> StorageResult<()>;
+    /// Like [`StorageProvider::put_crate`], but the tarball arrives as a stream of exactly
+    /// `crate_len` bytes, and the checksum is computed while it's being stored.
+    ///
+    /// Returns the SHA-256 of the tarball.
+    ///
+    /// The default implementation buffers the whole crate and then calls `put_crate`.
+    async fn put_crate_stream(
+        &self,
+        name: &str,
+        version: &str,
+        crate_len: u64,
+        crate_body: BodyStream,
+    ) -> StorageResult<[u8; 32]> {
+        let crate_bytes: Vec<u8> = crate_body
+            .try_fold(
+                Vec::with_capacity(usize::try_from(crate_len).unwrap_or(0)),
+                |mut buf, chunk| async move {
+                    buf.extend_from_slice(&chunk);
+                    Ok(buf)
+                },
+            )
+            .await
+            .map_err(anyhow::Error::from)?;
+        let tarball_checksum: [u8; 32] = Sha256::digest(&crate_bytes).into();
+        self.put_crate(name, version, crate_bytes.into(), tarball_checksum)
+            .await?;
+        Ok(tarball_checksum)
+    }
     /// Called to undo a put after a failed index transaction
     async fn delete_crate(
         &self,
@@ -49,6 +110,24 @@ pub struct FileResponse {
     pub data: Bytes,
 }
 
+pub struct StreamedFileResponse {
+    pub last_modified: Option<DateTime<Utc>>,
+    /// Length of the whole body, if the backend knows it upfront
+    pub content_length: Option<u64>,
+    pub body: BodyStream,
+}
+
+impl From<FileResponse> for StreamedFileResponse {
+    fn from(res: FileResponse) -> Self {
+        let data = res.data;
+        Self {
+            last_modified: res.last_modified,
+            content_length: Some(data.len() as u64),
+            body: Box::pin(futures_util::stream::once(async move { Ok(data) })),
+        }
+    }
+}
+
 #[async_trait]
 pub trait MetadataStorageProvider {
     async fn pull_file(&self, path: &str) -> StorageResult<FileResponse>;
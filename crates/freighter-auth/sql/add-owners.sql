@@ -0,0 +1,3 @@
+insert into freighter_crate_owners (user_id, crate)
+select unnest($1::integer[]), $2
+on conflict (user_id, lower(replace(crate, '-', '_'))) do nothing
@@ -0,0 +1,3 @@
+select o.crate, u.username
+from freighter_crate_owners o
+         join freighter_users u on u.id = o.user_id
@@ -0,0 +1,4 @@
+select u.username, t.token_hash
+from freighter_tokens t
+         join freighter_users u on u.id = t.user_id
+order by u.username, t.id
@@ -0,0 +1,4 @@
+select u.id, u.username, k.public_key
+from freighter_public_keys k
+         join freighter_users u on u.id = k.user_id
+where k.key_id = $1
@@ -0,0 +1,4 @@
+select u.id, u.username
+from freighter_tokens t
+         join freighter_users u on u.id = t.user_id
+where t.token_hash = $1
@@ -0,0 +1,3 @@
+select id, username
+from freighter_users
+where username = any ($1)
@@ -0,0 +1,5 @@
+insert into freighter_public_keys (user_id, key_id, public_key)
+values ($1, $2, $3)
+on conflict (key_id) do update set public_key = excluded.public_key
+where freighter_public_keys.user_id = excluded.user_id
+returning id
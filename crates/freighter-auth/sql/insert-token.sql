@@ -0,0 +1,3 @@
+insert into freighter_tokens (user_id, token_hash)
+values ($1, $2)
+on conflict (token_hash) do nothing
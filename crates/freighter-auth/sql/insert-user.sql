@@ -0,0 +1,4 @@
+insert into freighter_users (username)
+values ($1)
+on conflict (username) do nothing
+returning id
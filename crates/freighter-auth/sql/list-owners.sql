@@ -0,0 +1,5 @@
+select u.id, u.username
+from freighter_crate_owners o
+         join freighter_users u on u.id = o.user_id
+where lower(replace(o.crate, '-', '_')) = lower(replace($1, '-', '_'))
+order by u.username
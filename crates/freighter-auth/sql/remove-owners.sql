@@ -0,0 +1,4 @@
+delete
+from freighter_crate_owners
+where lower(replace(crate, '-', '_')) = lower(replace($2, '-', '_'))
+  and user_id = any ($1)
@@ -0,0 +1,220 @@
+//! Cargo's asymmetric tokens ([RFC 3231]): instead of a secret, cargo sends a PASETO `v3.public` token,
+//! signed with the user's private key for a single request.
+//!
+//! [RFC 3231]: https://rust-lang.github.io/rfcs/3231-cargo-asymmetric-tokens.html
+
+use crate::{AuthError, AuthResult};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use p384::ecdsa::signature::Verifier;
+use p384::ecdsa::{Signature, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha384};
+
+const TOKEN_HEADER: &str = "v3.public.";
+const PUBLIC_KEY_PREFIX: &str = "k3.public.";
+const KEY_ID_PREFIX: &str = "k3.pid.";
+/// Compressed P-384 point
+const PUBLIC_KEY_LEN: usize = 49;
+const SIGNATURE_LEN: usize = 96;
+
+/// A user's key, as a `k3.public.` PASERK, which `cargo login` prints
+pub struct PublicKey {
+    key: VerifyingKey,
+    paserk: String,
+}
+
+impl PublicKey {
+    pub fn parse(paserk: &str) -> AuthResult<Self> {
+        let bytes = paserk
+            .trim()
+            .strip_prefix(PUBLIC_KEY_PREFIX)
+            .and_then(|k| URL_SAFE_NO_PAD.decode(k).ok())
+            .filter(|k| k.len() == PUBLIC_KEY_LEN)
+            .ok_or(AuthError::InvalidCredentials)?;
+        let key = VerifyingKey::from_sec1_bytes(&bytes).map_err(|_| AuthError::InvalidCredentials)?;
+        Ok(Self { key, paserk: paserk.trim().to_owned() })
+    }
+
+    /// The `k3.public.` PASERK
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.paserk
+    }
+
+    /// `k3.pid.` PASERK, which tokens have in the `kip` of their footer
+    #[must_use]
+    pub fn id(&self) -> String {
+        let hash = Sha384::new()
+            .chain_update(KEY_ID_PREFIX)
+            .chain_update(&self.paserk)
+            .finalize();
+        format!("{KEY_ID_PREFIX}{}", URL_SAFE_NO_PAD.encode(&hash[..33]))
+    }
+}
+
+/// What the token allows. Claims of mutations must match the request.
+#[derive(Deserialize, Debug)]
+pub struct Claims {
+    /// RFC 3339 time when the token was made
+    pub iat: String,
+    /// `publish`, `yank`, `unyank` or `owners`. Not set for reads.
+    #[serde(default)]
+    pub mutation: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub vers: Option<String>,
+    /// Hex SHA-256 of the published tarball
+    #[serde(default)]
+    pub cksum: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Footer {
+    /// Index URL of the registry the token is for
+    pub url: String,
+    /// Id of the public key
+    pub kip: String,
+}
+
+/// Token parsed without checking the signature, so the claims can't be trusted before [`AsymmetricToken::verify`]
+pub struct AsymmetricToken {
+    pub claims: Claims,
+    pub footer: Footer,
+    message: Vec<u8>,
+    signature: Signature,
+    footer_bytes: Vec<u8>,
+}
+
+impl AsymmetricToken {
+    /// Tells asymmetric tokens from the secret tokens of the backends
+    #[must_use]
+    pub fn is_asymmetric(token: &str) -> bool {
+        token.starts_with(TOKEN_HEADER)
+    }
+
+    pub fn parse(token: &str) -> AuthResult<Self> {
+        let rest = token.strip_prefix(TOKEN_HEADER).ok_or(AuthError::InvalidCredentials)?;
+        let (payload, footer) = rest.split_once('.').ok_or(AuthError::InvalidCredentials)?;
+        let mut message = URL_SAFE_NO_PAD.decode(payload).map_err(|_| AuthError::InvalidCredentials)?;
+        let footer_bytes = URL_SAFE_NO_PAD.decode(footer).map_err(|_| AuthError::InvalidCredentials)?;
+        if message.len() < SIGNATURE_LEN {
+            return Err(AuthError::InvalidCredentials);
+        }
+        let signature = Signature::from_slice(&message.split_off(message.len() - SIGNATURE_LEN))
+            .map_err(|_| AuthError::InvalidCredentials)?;
+
+        let claims = serde_json::from_slice(&message).map_err(|e| {
+            tracing::warn!("bad asymmetric token claims: {e}");
+            AuthError::InvalidCredentials
+        })?;
+        let footer = serde_json::from_slice(&footer_bytes).map_err(|e| {
+            tracing::warn!("bad asymmetric token footer: {e}");
+            AuthError::InvalidCredentials
+        })?;
+        Ok(Self { claims, footer, message, signature, footer_bytes })
+    }
+
+    /// Checks that the token has been signed with the key
+    pub fn verify(&self, key: &PublicKey) -> AuthResult<()> {
+        if self.footer.kip != key.id() {
+            return Err(AuthError::InvalidCredentials);
+        }
+        let public_key = key.key.to_encoded_point(true);
+        let signed = pre_auth_encode(&[
+            public_key.as_bytes(),
+            TOKEN_HEADER.as_bytes(),
+            &self.message,
+            &self.footer_bytes,
+            b"",
+        ]);
+        key.key.verify(&signed, &self.signature).map_err(|_| {
+            tracing::warn!("invalid signature of asymmetric token for {}", self.footer.kip);
+            AuthError::Unauthorized
+        })
+    }
+}
+
+/// PASETO's PAE
+fn pre_auth_encode(pieces: &[&[u8]]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + pieces.iter().map(|p| 8 + p.len()).sum::<usize>());
+    out.extend_from_slice(&(pieces.len() as u64).to_le_bytes());
+    for piece in pieces {
+        out.extend_from_slice(&(piece.len() as u64).to_le_bytes());
+        out.extend_from_slice(piece);
+    }
+    out
+}
+
+/// Makes tokens like cargo does
+#[cfg(test)]
+pub(crate) fn sign_token(key: &p384::ecdsa::SigningKey, claims: &serde_json::Value, url: &str) -> String {
+    use p384::ecdsa::signature::Signer;
+
+    let public_key = key.verifying_key().to_encoded_point(true);
+    let kip = PublicKey::parse(&public_key_paserk(key)).unwrap().id();
+    let footer = serde_json::json!({ "url": url, "kip": kip }).to_string();
+    let mut message = claims.to_string().into_bytes();
+    let signed = pre_auth_encode(&[
+        public_key.as_bytes(),
+        TOKEN_HEADER.as_bytes(),
+        &message,
+        footer.as_bytes(),
+        b"",
+    ]);
+    let signature: Signature = key.sign(&signed);
+    message.extend_from_slice(&signature.to_bytes());
+    format!(
+        "{TOKEN_HEADER}{}.{}",
+        URL_SAFE_NO_PAD.encode(message),
+        URL_SAFE_NO_PAD.encode(footer)
+    )
+}
+
+/// `k3.public.` PASERK of the key, like `cargo login` prints
+#[cfg(test)]
+pub(crate) fn public_key_paserk(key: &p384::ecdsa::SigningKey) -> String {
+    let public_key = key.verifying_key().to_encoded_point(true);
+    format!("{PUBLIC_KEY_PREFIX}{}", URL_SAFE_NO_PAD.encode(public_key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p384::ecdsa::SigningKey;
+    use serde_json::json;
+
+    #[test]
+    fn signed_tokens() {
+        let key = SigningKey::from_slice(&[7; 48]).unwrap();
+        let public_key = PublicKey::parse(&public_key_paserk(&key)).unwrap();
+        let claims = json!({"iat": "2024-01-01T00:00:00Z", "mutation": "yank", "name": "foo", "vers": "1.0.0", "v": 1});
+        let token = sign_token(&key, &claims, "sparse+https://registry.example.com/index/");
+        assert!(AsymmetricToken::is_asymmetric(&token));
+
+        let parsed = AsymmetricToken::parse(&token).unwrap();
+        parsed.verify(&public_key).unwrap();
+        assert_eq!(parsed.claims.mutation.as_deref(), Some("yank"));
+        assert_eq!(parsed.footer.kip, public_key.id());
+        assert!(parsed.footer.kip.starts_with("k3.pid."));
+
+        let other_key = SigningKey::from_slice(&[8; 48]).unwrap();
+        let other_public_key = PublicKey::parse(&public_key_paserk(&other_key)).unwrap();
+        assert!(parsed.verify(&other_public_key).is_err());
+
+        // a changed claim breaks the signature
+        let mut forged = AsymmetricToken::parse(&token).unwrap();
+        let len = forged.message.len();
+        forged.message[len - 3] ^= 1;
+        assert!(matches!(forged.verify(&public_key), Err(AuthError::Unauthorized)));
+    }
+
+    #[test]
+    fn invalid_keys_and_tokens() {
+        assert!(PublicKey::parse("k3.public.AAAA").is_err());
+        assert!(PublicKey::parse("fr1_abc").is_err());
+        assert!(AsymmetricToken::parse("v3.public.AAAA.AAAA").is_err());
+        assert!(!AsymmetricToken::is_asymmetric("fr1_abc"));
+    }
+}
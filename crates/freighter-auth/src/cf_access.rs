@@ -1,25 +1,11 @@
-use crate::{AuthError, AuthResult};
+use crate::jwks::{Jwks, KeySource};
+use crate::AuthResult;
 use anyhow::bail;
-use jsonwebtoken::jwk::{JwkSet, PublicKeyUse};
-use jsonwebtoken::{DecodingKey, Validation};
-use std::collections::HashMap;
-use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
-
-/// Check for new keys (Cloudflare gives them 4h max-age)
-const REFRESH_DURATION: Duration = Duration::from_hours(1);
+use jsonwebtoken::Validation;
 
 /// Cloudflare Access JWT verifier
 pub struct CfAccess {
-    jwks_url: String,
-    validation: Validation,
-    key_set: RwLock<KeySet>,
-}
-
-/// Allowed keys
-struct KeySet {
-    next_fetch: Instant,
-    keys: HashMap<String, DecodingKey>,
+    jwks: Jwks,
 }
 
 /// Claims in the token
@@ -57,93 +43,18 @@ impl CfAccess {
         validation.set_audience(&[audience]);
 
         Ok(Self {
-            jwks_url,
-            validation,
-            key_set: RwLock::new(KeySet {
-                next_fetch: Instant::now(),
-                keys: HashMap::default(),
-            }),
+            jwks: Jwks::new(KeySource::Jwks(jwks_url), validation),
         })
     }
 
     /// Download new keys
     pub async fn refresh(&self) -> Result<(), anyhow::Error> {
-        let mut locked_keys = self.key_set.write().await;
-        let now = Instant::now();
-        if locked_keys.next_fetch > now {
-            if locked_keys.keys.is_empty() {
-                anyhow::bail!("no usable keys");
-            }
-            return Ok(());
-        }
-
-        locked_keys.next_fetch = now + Duration::from_secs(1); // in case of failure, retry 1/s
-        let set: JwkSet = async {
-            reqwest::get(&self.jwks_url)
-                .await?
-                .error_for_status()?
-                .json()
-                .await
-        }
-        .await
-        .inspect_err(|e| tracing::error!("{}: {e}", self.jwks_url))?;
-        locked_keys.keys = set
-            .keys
-            .into_iter()
-            .filter(|k| {
-                k.common
-                    .public_key_use
-                    .as_ref()
-                    .is_some_and(|s| *s == PublicKeyUse::Signature)
-            })
-            .filter_map(|k| {
-                let key = DecodingKey::from_jwk(&k)
-                    .inspect_err(|e| tracing::error!("{k:?}: {e}"))
-                    .ok()?;
-                let kid = k.common.key_id?;
-                Some((kid, key))
-            })
-            .collect();
-        if locked_keys.keys.is_empty() {
-            tracing::error!("no usable keys");
-            anyhow::bail!("no usable keys");
-        }
-        locked_keys.next_fetch = Instant::now() + REFRESH_DURATION;
-        Ok(())
+        self.jwks.refresh().await
     }
 
     /// Returns a user ID
     pub async fn validated_user_id(&self, token: &str) -> AuthResult<UserId> {
-        let key_id = jsonwebtoken::decode_header(token)
-            .map_err(|e| {
-                tracing::warn!("bad token: {token}: {e}");
-                AuthError::InvalidCredentials
-            })?
-            .kid
-            .ok_or(AuthError::InvalidCredentials)?;
-
-        let locked_keys = loop {
-            let tmp = self.key_set.read().await;
-            if tmp.next_fetch < Instant::now() {
-                drop(tmp);
-                self.refresh().await?;
-                continue;
-            }
-            break tmp;
-        };
-
-        let Some(key) = locked_keys.keys.get(key_id.as_str()) else {
-            tracing::warn!("token for an unknown key: {token}: {key_id}");
-            return Err(AuthError::InvalidCredentials);
-        };
-
-        let claims = jsonwebtoken::decode::<Claims>(token, key, &self.validation)
-            .map_err(|e| {
-                tracing::warn!("unauthorized: {token}: {e}");
-                AuthError::Unauthorized
-            })?
-            .claims;
-        drop(locked_keys);
+        let claims: Claims = self.jwks.verify(token).await?;
 
         let sub = claims.sub.filter(|s| !s.is_empty());
         let sub_was_empty = sub.is_none();
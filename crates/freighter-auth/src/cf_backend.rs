@@ -89,6 +89,10 @@ cloudflared access login <var>hostname of the registry</var> | fgrep . | cargo l
 </pre>")
     }
 
+    async fn token_login(&self, token: &str) -> AuthResult<String> {
+        Ok(self.validated_user_id(token).await?.0)
+    }
+
     async fn list_owners(&self, token: &str, _crate_name: &str) -> AuthResult<Vec<ListedOwner>> {
         self.validated_user_id(token).await?;
 
@@ -139,7 +143,7 @@ cloudflared access login <var>hostname of the registry</var> | fgrep . | cargo l
     async fn auth_index_fetch(
         &self,
         token: &str,
-        _all_users_can_read_crates: &str,
+        _crate_name: &str,
     ) -> AuthResult<()> {
         self.validated_user_id(token).await?;
         Ok(())
@@ -148,7 +152,7 @@ cloudflared access login <var>hostname of the registry</var> | fgrep . | cargo l
     async fn auth_crate_download(
         &self,
         token: &str,
-        _all_users_can_read_crates: &str,
+        _crate_name: &str,
     ) -> AuthResult<()> {
         self.validated_user_id(token).await?;
         Ok(())
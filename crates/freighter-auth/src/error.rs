@@ -17,6 +17,10 @@ pub enum AuthError {
     Unimplemented,
     #[error("The requested crate does not exist")]
     CrateNotFound,
+    #[error("The requested user does not exist")]
+    UserNotFound,
+    #[error("The requested token does not exist")]
+    TokenNotFound,
     #[error("Internal error ({})", error_id(_0))]
     ServiceError(#[from] anyhow::Error),
 }
@@ -27,6 +31,8 @@ impl IntoResponse for AuthError {
             Self::Forbidden => StatusCode::FORBIDDEN,
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
             Self::CrateNotFound => StatusCode::NOT_FOUND,
+            Self::UserNotFound => StatusCode::NOT_FOUND,
+            Self::TokenNotFound => StatusCode::NOT_FOUND,
             Self::InvalidCredentials => StatusCode::UNAUTHORIZED,
             Self::Unimplemented => StatusCode::NOT_IMPLEMENTED,
             Self::ServiceError(error) => {
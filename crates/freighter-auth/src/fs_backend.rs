@@ -1,7 +1,12 @@
+use crate::asymmetric::{AsymmetricToken, PublicKey};
 use crate::base64_serde;
-use crate::{AuthError, AuthProvider, AuthResult};
+use crate::scopes::EndpointScope;
+use crate::tokens::{CreatedToken, NewToken, TokenInfo};
+use crate::{AuthBackup, AuthError, AuthProvider, AuthResult, BackupToken};
 use anyhow::Context;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use freighter_api_types::crate_name::canonical_crate_name;
 use freighter_api_types::ownership::response::ListedOwner;
 use parking_lot::MappedRwLockWriteGuard;
 use parking_lot::RwLockWriteGuard;
@@ -9,6 +14,7 @@ use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
 use serde::{Deserialize, Serialize};
 use sha2::Sha224; // FIPS 180-4
 use std::collections::BTreeSet;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt;
 use std::io;
@@ -21,6 +27,8 @@ use tempfile::NamedTempFile;
 /// 28 base64 chars
 pub type BareToken = [u8; 21];
 const TOKEN_PREFIX: &str = "fr1_";
+/// Hashed tokens are only valid with the same pepper
+const BACKUP_TOKEN_FORMAT: &str = "fs-hmac-sha224";
 
 pub struct FsAuthProvider {
     owners_file_path: PathBuf,
@@ -60,12 +68,24 @@ impl FsAuthProvider {
     }
 
     fn token_from_str(&self, token_str: &str) -> AuthResult<HashedToken> {
+        if AsymmetricToken::is_asymmetric(token_str) {
+            return self.asymmetric_token_owner(token_str);
+        }
         let rest = token_str
             .strip_prefix(TOKEN_PREFIX)
             .ok_or(AuthError::InvalidCredentials)?;
         Ok(self.hash_token(&base64_serde::decode(rest).ok_or(AuthError::InvalidCredentials)?))
     }
 
+    /// Asymmetric tokens act as the token of the user who registered the key
+    fn asymmetric_token_owner(&self, token_str: &str) -> AuthResult<HashedToken> {
+        let token = AsymmetricToken::parse(token_str)?;
+        let owners = self.owners()?;
+        let key = owners.public_keys.get(token.footer.kip.as_str()).ok_or(AuthError::InvalidCredentials)?;
+        token.verify(&PublicKey::parse(&key.public_key)?)?;
+        Ok(key.token.clone())
+    }
+
     fn hash_token(&self, bare_token: &BareToken) -> HashedToken {
         use hmac::{Hmac, Mac};
         let mut mac = Hmac::<Sha224>::new_from_slice(&self.pepper).unwrap();
@@ -79,12 +99,15 @@ impl FsAuthProvider {
             std::fs::File::open(&self.owners_file_path)
                 .map(BufReader::new).context("read owners")
                 .and_then(|r| serde_json::from_reader(r).context("parse owners"))
+                .map(Owners::with_canonical_crate_names)
                 .map_err(AuthError::ServiceError)
         } else {
             Ok(Owners {
                 crate_owners: HashMap::default(),
                 owner_tokens: HashMap::default(),
                 token_owners: HashMap::default(),
+                public_keys: HashMap::default(),
+                token_info: HashMap::default(),
             })
         }
     }
@@ -154,14 +177,97 @@ impl AuthProvider for FsAuthProvider {
         let hashed_token = self.hash_token(&bare_token);
         let token_str = self.token_to_str(&bare_token);
         owners.register(username, &hashed_token)?;
+        let info = StoredToken { created_at: Utc::now(), token: NewToken { name: "default".into(), ..NewToken::default() } };
+        owners.token_info.insert(hashed_token, info);
         self.sync_owners(owners)?;
         tracing::info!("Registered {username}");
         Ok(token_str)
     }
 
+    async fn token_login(&self, token_str: &str) -> AuthResult<String> {
+        let hashed_token = self.token_from_str(token_str)?;
+        Ok(self.owners()?.login_for_token(&hashed_token)?.to_owned())
+    }
+
+    async fn create_token(&self, token_str: &str, new_token: &NewToken) -> AuthResult<CreatedToken> {
+        let hashed_token = self.token_from_str(token_str)?;
+        let owners = &mut *self.owners_mut()?;
+        let login: Box<str> = owners.login_for_token(&hashed_token)?.into();
+        if owners.is_scoped(&hashed_token) {
+            return Err(AuthError::Forbidden);
+        }
+        // ids are short, so they could collide
+        let (bare_token, hashed_token) = loop {
+            let bare_token = self.random_token();
+            let hashed_token = self.hash_token(&bare_token);
+            if !owners.token_owners.keys().any(|t| t.id() == hashed_token.id()) {
+                break (bare_token, hashed_token);
+            }
+        };
+        owners.token_info.insert(hashed_token.clone(), StoredToken { created_at: Utc::now(), token: new_token.clone() });
+        owners.token_owners.insert(hashed_token.clone(), login.clone());
+        self.sync_owners(owners)?;
+        tracing::info!("Created token {} of {login}", hashed_token.id());
+        Ok(CreatedToken { info: owners.token_info(&hashed_token), token: self.token_to_str(&bare_token) })
+    }
+
+    async fn list_tokens(&self, token_str: &str) -> AuthResult<Vec<TokenInfo>> {
+        let hashed_token = self.token_from_str(token_str)?;
+        let owners = &*self.owners()?;
+        let login = owners.login_for_token(&hashed_token)?;
+        let mut tokens: Vec<_> = owners.token_owners.iter()
+            .filter(|&(_, l)| **l == *login)
+            .map(|(t, _)| owners.token_info(t))
+            .collect();
+        tokens.sort_by_key(|t| (t.created_at, t.id));
+        Ok(tokens)
+    }
+
+    async fn revoke_token(&self, token_str: &str, id: u32) -> AuthResult<()> {
+        let hashed_token = self.token_from_str(token_str)?;
+        let owners = &mut *self.owners_mut()?;
+        let login: Box<str> = owners.login_for_token(&hashed_token)?.into();
+        if owners.is_scoped(&hashed_token) {
+            return Err(AuthError::Forbidden);
+        }
+        let revoked = owners.token_owners.iter()
+            .find(|&(t, l)| *l == login && t.id() == id)
+            .map(|(t, _)| t.clone())
+            .ok_or(AuthError::TokenNotFound)?;
+        // Users must keep a token that can manage tokens, and a login without tokens could be registered again
+        let can_manage = |t: &HashedToken| *t != revoked && owners.login_for_token(t).is_ok() && !owners.is_scoped(t);
+        if !owners.token_owners.iter().any(|(t, l)| *l == login && can_manage(t)) {
+            return Err(AuthError::Forbidden);
+        }
+        owners.token_owners.remove(&revoked);
+        owners.token_info.remove(&revoked);
+        owners.public_keys.retain(|_, key| key.token != revoked);
+        // rebuilt when needed
+        owners.owner_tokens.clear();
+        self.sync_owners(owners)?;
+        tracing::info!("Revoked token {id} of {login}");
+        Ok(())
+    }
+
+    async fn register_public_key(&self, token_str: &str, public_key: &str) -> AuthResult<()> {
+        let hashed_token = self.token_from_str(token_str)?;
+        let public_key = PublicKey::parse(public_key)?;
+        let owners = &mut *self.owners_mut()?;
+        let login = owners.login_for_token(&hashed_token)?.to_owned();
+        match owners.public_keys.entry(public_key.id().into()) {
+            Entry::Occupied(registered) if registered.get().token != hashed_token => return Err(AuthError::Forbidden),
+            entry => {
+                entry.insert_entry(RegisteredKey { public_key: public_key.as_str().into(), token: hashed_token });
+            }
+        }
+        self.sync_owners(owners)?;
+        tracing::info!("Registered a public key of {login}");
+        Ok(())
+    }
+
     async fn list_owners(&self, _owner_list_is_public: &str, crate_name: &str) -> AuthResult<Vec<ListedOwner>> {
         let all_owners = &*self.owners()?;
-        let owners = all_owners.crate_owners.get(crate_name).ok_or(AuthError::CrateNotFound)?;
+        let owners = all_owners.crate_owners.get(&*canonical_crate_name(crate_name)).ok_or(AuthError::CrateNotFound)?;
         Ok(owners.iter().map(|login| ListedOwner {
             id: 0,
             login: login.to_string(),
@@ -172,8 +278,8 @@ impl AuthProvider for FsAuthProvider {
     async fn add_owners(&self, token_str: &str, users: &[&str], crate_name: &str) -> AuthResult<()> {
         let hashed_token = self.token_from_str(token_str)?;
         let owners = &mut *self.owners_mut()?;
-        owners.ensure_authorized_for_crate(&hashed_token, crate_name)?;
-        let crate_owners = owners.crate_owners.get_mut(crate_name).ok_or(AuthError::CrateNotFound)?;
+        owners.ensure_authorized_for_crate(&hashed_token, crate_name, EndpointScope::ChangeOwners)?;
+        let crate_owners = owners.crate_owners.get_mut(&*canonical_crate_name(crate_name)).ok_or(AuthError::CrateNotFound)?;
         crate_owners.extend(users.iter().map(|&login| login.into()));
         self.sync_owners(owners)?;
         Ok(())
@@ -182,8 +288,8 @@ impl AuthProvider for FsAuthProvider {
     async fn remove_owners(&self, token_str: &str, users: &[&str], crate_name: &str, ) -> AuthResult<()> {
         let hashed_token = self.token_from_str(token_str)?;
         let owners = &mut *self.owners_mut()?;
-        owners.ensure_authorized_for_crate(&hashed_token, crate_name)?;
-        let crate_owners = owners.crate_owners.get_mut(crate_name).ok_or(AuthError::CrateNotFound)?;
+        owners.ensure_authorized_for_crate(&hashed_token, crate_name, EndpointScope::ChangeOwners)?;
+        let crate_owners = owners.crate_owners.get_mut(&*canonical_crate_name(crate_name)).ok_or(AuthError::CrateNotFound)?;
         for &login in users {
             if crate_owners.len() > 1 {
                 crate_owners.remove(login);
@@ -201,18 +307,21 @@ impl AuthProvider for FsAuthProvider {
         let owners = &mut *self.owners_mut()?;
 
         // If the crate doesn't exist yet, allow anybody to publish
-        if owners.crate_owners.get_mut(crate_name).is_none() {
+        let key = canonical_crate_name(crate_name);
+        if !owners.crate_owners.contains_key(&*key) {
+            owners.ensure_scope(&hashed_token, EndpointScope::PublishNew, crate_name)?;
             let login = owners.login_for_token(&hashed_token)?.into();
-            owners.crate_owners.insert(crate_name.into(), [login].into_iter().collect());
+            owners.crate_owners.insert(key.into(), [login].into_iter().collect());
+            return Ok(());
         }
 
-        owners.ensure_authorized_for_crate(&hashed_token, crate_name)?;
+        owners.ensure_authorized_for_crate(&hashed_token, crate_name, EndpointScope::PublishUpdate)?;
         Ok(())
     }
 
     async fn auth_yank(&self, token_str: &str, crate_name: &str) -> AuthResult<()> {
         let hashed_token = self.token_from_str(token_str)?;
-        self.owners()?.ensure_authorized_for_crate(&hashed_token, crate_name).map(drop)
+        self.owners()?.ensure_authorized_for_crate(&hashed_token, crate_name, EndpointScope::Yank)
     }
 
     /// Fetch of config.json.
@@ -220,30 +329,106 @@ impl AuthProvider for FsAuthProvider {
         self.ensure_valid_token(token_str)
     }
 
-    async fn auth_index_fetch(&self, token_str: &str, _all_users_can_read_crates: &str) -> AuthResult<()> {
+    async fn auth_index_fetch(&self, token_str: &str, _crate_name: &str) -> AuthResult<()> {
         self.ensure_valid_token(token_str)
     }
 
-    async fn auth_crate_download(&self, token_str: &str, _all_users_can_read_crates: &str) -> AuthResult<()> {
+    async fn auth_crate_download(&self, token_str: &str, _crate_name: &str) -> AuthResult<()> {
         self.ensure_valid_token(token_str)
     }
 
     async fn auth_view_full_index(&self, token_str: &str) -> AuthResult<()> {
         self.ensure_valid_token(token_str)
     }
+
+    async fn export_backup(&self) -> AuthResult<AuthBackup> {
+        let owners = &*self.owners()?;
+        let mut tokens: Vec<_> = owners.token_owners.iter().map(|(hashed_token, login)| {
+            let mut token = String::new();
+            base64_serde::encode(&hashed_token.0, &mut token);
+            let stored = owners.token_info.get(hashed_token);
+            BackupToken {
+                login: login.to_string(),
+                format: BACKUP_TOKEN_FORMAT.into(),
+                token,
+                created_at: stored.map(|i| i.created_at),
+                details: stored.map(|i| i.token.clone()),
+            }
+        }).collect();
+        tokens.sort_by(|a, b| a.login.cmp(&b.login));
+        let crate_owners = owners.crate_owners.iter()
+            .map(|(crate_name, logins)| (crate_name.to_string(), logins.iter().map(|l| l.to_string()).collect()))
+            .collect();
+        Ok(AuthBackup { crate_owners, tokens })
+    }
+
+    async fn import_backup(&self, backup: &AuthBackup) -> AuthResult<()> {
+        let owners = &mut *self.owners_mut()?;
+        // only users created now get the tokens
+        let existing: BTreeSet<Box<str>> = owners.token_owners.values().cloned().collect();
+        for token in backup.tokens.iter().filter(|t| t.format == BACKUP_TOKEN_FORMAT && !existing.contains(t.login.as_str())) {
+            let hashed_token = base64_serde::decode(&token.token).map(HashedToken)
+                .with_context(|| format!("invalid token of {}", token.login))?;
+            if let Some(details) = &token.details {
+                let created_at = token.created_at.unwrap_or_else(Utc::now);
+                owners.token_info.insert(hashed_token.clone(), StoredToken { created_at, token: details.clone() });
+            }
+            owners.token_owners.insert(hashed_token, token.login.as_str().into());
+        }
+        // rebuilt when needed
+        owners.owner_tokens.clear();
+        for (crate_name, logins) in &backup.crate_owners {
+            owners.crate_owners.entry(canonical_crate_name(crate_name).into()).or_default()
+                .extend(logins.iter().map(|l| l.as_str().into()));
+        }
+        self.sync_owners(owners)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 struct Owners {
     token_owners: HashMap<HashedToken, Box<str>>,
+    /// By canonical crate name, so that other spellings of a name are the same crate
     crate_owners: HashMap<Box<str>, BTreeSet<Box<str>>>,
+    /// By key id
+    #[serde(default)]
+    public_keys: HashMap<Box<str>, RegisteredKey>,
+    /// Names, expiry and scopes of tokens in `token_owners`. Tokens from older versions don't have them.
+    #[serde(default)]
+    token_info: HashMap<HashedToken, StoredToken>,
 
     /// Reverse lookup index
     #[serde(skip, default)]
     owner_tokens: HashMap<Box<str>, HashedToken>,
 }
 
+/// Public key for asymmetric tokens
+#[derive(Serialize, Deserialize)]
+struct RegisteredKey {
+    /// `k3.public.` PASERK
+    public_key: Box<str>,
+    /// Token of the user who registered it
+    token: HashedToken,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    created_at: DateTime<Utc>,
+    #[serde(flatten)]
+    token: NewToken,
+}
+
 impl Owners {
+    /// Files of older versions have the names as published
+    fn with_canonical_crate_names(mut self) -> Self {
+        let mut crate_owners: HashMap<Box<str>, BTreeSet<Box<str>>> = HashMap::with_capacity(self.crate_owners.len());
+        for (crate_name, logins) in self.crate_owners {
+            crate_owners.entry(canonical_crate_name(&crate_name).into()).or_default().extend(logins);
+        }
+        self.crate_owners = crate_owners;
+        self
+    }
+
     pub fn register(&mut self, login: &str, token: &HashedToken) -> AuthResult<()> {
         if self.owner_tokens.is_empty() {
             self.owner_tokens = self.token_owners.iter().map(|(k, v)| (v.clone(), k.clone())).collect();
@@ -258,16 +443,39 @@ impl Owners {
     }
 
     pub fn login_for_token(&self, token: &HashedToken) -> AuthResult<&str> {
-        self.token_owners.get(token).map(|x| &**x).ok_or(AuthError::InvalidCredentials)
+        let login = self.token_owners.get(token).ok_or(AuthError::InvalidCredentials)?;
+        if self.token_info.get(token).is_some_and(|i| i.token.is_expired()) {
+            return Err(AuthError::InvalidCredentials);
+        }
+        Ok(login)
+    }
+
+    pub fn is_scoped(&self, token: &HashedToken) -> bool {
+        self.token_info.get(token).is_some_and(|i| !i.token.scopes.is_unrestricted())
     }
 
-    pub fn ensure_authorized_for_crate(&self, hashed_token: &HashedToken, crate_name: &str) -> AuthResult<()> {
-        let owners = self.crate_owners.get(crate_name).ok_or(AuthError::CrateNotFound)?;
+    pub fn token_info(&self, token: &HashedToken) -> TokenInfo {
+        let stored = self.token_info.get(token);
+        TokenInfo {
+            id: token.id(),
+            created_at: stored.map(|i| i.created_at),
+            token: stored.map(|i| i.token.clone()).unwrap_or_default(),
+        }
+    }
+
+    pub fn ensure_authorized_for_crate(&self, hashed_token: &HashedToken, crate_name: &str, scope: EndpointScope) -> AuthResult<()> {
+        let owners = self.crate_owners.get(&*canonical_crate_name(crate_name)).ok_or(AuthError::CrateNotFound)?;
         let login = self.login_for_token(hashed_token)?;
-        if owners.contains(login) {
-            Ok(())
-        } else {
-            Err(AuthError::Forbidden)
+        if !owners.contains(login) {
+            return Err(AuthError::Forbidden);
+        }
+        self.ensure_scope(hashed_token, scope, crate_name)
+    }
+
+    pub fn ensure_scope(&self, hashed_token: &HashedToken, scope: EndpointScope, crate_name: &str) -> AuthResult<()> {
+        match self.token_info.get(hashed_token) {
+            Some(info) if !info.token.scopes.allows(scope, crate_name) => Err(AuthError::Forbidden),
+            _ => Ok(()),
         }
     }
 }
@@ -279,6 +487,13 @@ impl Owners {
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
 struct HashedToken(#[serde(with = "base64_serde")] [u8; 28]);
 
+impl HashedToken {
+    /// Public id of the token, for revoking it
+    fn id(&self) -> u32 {
+        u32::from_le_bytes([self.0[0], self.0[1], self.0[2], self.0[3]])
+    }
+}
+
 /// Needed for assert
 #[cfg(any(test, debug_assertions))]
 impl fmt::Debug for HashedToken {
@@ -322,3 +537,167 @@ async fn test_fs_tokens() {
     assert!(matches!(auth.publish(&user2, "crate1").await, Err(AuthError::InvalidCredentials)));
     assert!(matches!(auth.publish(&user1, "crate1").await, Err(AuthError::InvalidCredentials)));
 }
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_owners_of_other_spellings() {
+    let dir = tempfile::tempdir().unwrap();
+    let auth = FsAuthProvider::new(Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [123; 18] }).unwrap();
+    let user1 = auth.register("user1").await.unwrap();
+    let user2 = auth.register("user2").await.unwrap();
+    auth.publish(&user1, "Foo-bar").await.unwrap();
+    assert!(matches!(auth.publish(&user2, "foo_bar").await, Err(AuthError::Forbidden)));
+    assert!(matches!(auth.auth_yank(&user2, "FOO_BAR").await, Err(AuthError::Forbidden)));
+    assert!(matches!(auth.add_owners(&user2, &["user2"], "foo_bar").await, Err(AuthError::Forbidden)));
+    auth.publish(&user1, "foo_bar").await.unwrap();
+    let owners = auth.list_owners(&user2, "foo-Bar").await.unwrap();
+    assert_eq!(owners.iter().map(|o| &*o.login).collect::<Vec<_>>(), ["user1"]);
+
+    // owners files of older versions have the names as published
+    std::fs::write(dir.path().join("owners.json"), r#"{"token_owners": {}, "crate_owners": {"Foo-bar": ["user1"], "foo_bar": ["user2"]}}"#).unwrap();
+    let auth = FsAuthProvider::new(Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [123; 18] }).unwrap();
+    let owners = auth.list_owners("", "foo-bar").await.unwrap();
+    assert_eq!(owners.iter().map(|o| &*o.login).collect::<Vec<_>>(), ["user1", "user2"]);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_backup() {
+    let dir = tempfile::tempdir().unwrap();
+    let auth = FsAuthProvider::new(Config { auth_path: dir.path().join("a"), auth_tokens_pepper: [123; 18] }).unwrap();
+    let user1 = auth.register("user1").await.unwrap();
+    auth.publish(&user1, "crate1").await.unwrap();
+    let laptop = auth.create_token(&user1, &NewToken { name: "laptop".into(), ..NewToken::default() }).await.unwrap();
+    let backup = auth.export_backup().await.unwrap();
+    assert_eq!(backup.tokens.len(), 2);
+
+    let restored = FsAuthProvider::new(Config { auth_path: dir.path().join("b"), auth_tokens_pepper: [123; 18] }).unwrap();
+    let user2 = restored.register("user2").await.unwrap();
+    restored.import_backup(&backup).await.unwrap();
+    assert_eq!(restored.token_login(&user1).await.unwrap(), "user1");
+    assert_eq!(restored.token_login(&user2).await.unwrap(), "user2");
+    restored.auth_yank(&user1, "crate1").await.unwrap();
+    restored.auth_yank(&laptop.token, "crate1").await.unwrap();
+    assert!(matches!(restored.auth_yank(&user2, "crate1").await, Err(AuthError::Forbidden)));
+    let mut restored_tokens = restored.list_tokens(&user1).await.unwrap();
+    restored_tokens.sort_by_key(|t| t.token.name.clone());
+    assert_eq!(restored_tokens.last(), Some(&laptop.info));
+    assert_eq!(restored_tokens.len(), 2);
+    assert!(matches!(restored.register("user1").await, Err(AuthError::Forbidden)));
+
+    // existing users keep their token
+    let mut other = backup.clone();
+    other.tokens[0].login = "user2".into();
+    restored.import_backup(&other).await.unwrap();
+    assert_eq!(restored.token_login(&user2).await.unwrap(), "user2");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_asymmetric_tokens() {
+    use crate::asymmetric::{public_key_paserk, sign_token};
+    use p384::ecdsa::SigningKey;
+
+    let dir = tempfile::tempdir().unwrap();
+    let auth = FsAuthProvider::new(Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [123; 18] }).unwrap();
+    let user1 = auth.register("user1").await.unwrap();
+    let user2 = auth.register("user2").await.unwrap();
+    auth.publish(&user1, "crate1").await.unwrap();
+
+    let key = SigningKey::from_slice(&[7; 48]).unwrap();
+    let claims = serde_json::json!({"iat": "2024-01-01T00:00:00Z", "mutation": "yank", "name": "crate1", "vers": "1.0.0"});
+    let token = sign_token(&key, &claims, "https://registry.example.com/index/");
+    assert!(matches!(auth.auth_yank(&token, "crate1").await, Err(AuthError::InvalidCredentials)));
+
+    auth.register_public_key(&user1, &public_key_paserk(&key)).await.unwrap();
+    assert!(matches!(auth.register_public_key(&user2, &public_key_paserk(&key)).await, Err(AuthError::Forbidden)));
+    assert!(matches!(auth.register_public_key(&user2, "k3.public.bad").await, Err(AuthError::InvalidCredentials)));
+
+    // reload
+    let auth = FsAuthProvider::new(Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [123; 18] }).unwrap();
+    auth.auth_yank(&token, "crate1").await.unwrap();
+    assert_eq!(auth.token_login(&token).await.unwrap(), "user1");
+
+    let other_key = SigningKey::from_slice(&[8; 48]).unwrap();
+    let other_token = sign_token(&other_key, &claims, "https://registry.example.com/index/");
+    assert!(matches!(auth.auth_yank(&other_token, "crate1").await, Err(AuthError::InvalidCredentials)));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_scoped_tokens() {
+    use crate::scopes::TokenScopes;
+
+    let dir = tempfile::tempdir().unwrap();
+    let auth = FsAuthProvider::new(Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [123; 18] }).unwrap();
+    let user1 = auth.register("user1").await.unwrap();
+    auth.publish(&user1, "acme-lib").await.unwrap();
+    auth.publish(&user1, "other").await.unwrap();
+
+    let scopes = TokenScopes {
+        endpoint_scopes: Some([EndpointScope::PublishUpdate, EndpointScope::Yank].into()),
+        crate_scopes: Some(vec!["acme-*".into()]),
+    };
+    let ci = auth.create_token(&user1, &NewToken { name: "ci".into(), expired_at: None, scopes }).await.unwrap().token;
+    assert_eq!(auth.token_login(&ci).await.unwrap(), "user1");
+    assert!(matches!(auth.create_token(&ci, &NewToken::default()).await, Err(AuthError::Forbidden)));
+
+    // reload
+    let auth = FsAuthProvider::new(Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [123; 18] }).unwrap();
+    auth.publish(&ci, "acme-lib").await.unwrap();
+    auth.auth_yank(&ci, "acme-lib").await.unwrap();
+    assert!(matches!(auth.publish(&ci, "other").await, Err(AuthError::Forbidden)));
+    assert!(matches!(auth.auth_yank(&ci, "other").await, Err(AuthError::Forbidden)));
+    assert!(matches!(auth.publish(&ci, "acme-new").await, Err(AuthError::Forbidden)));
+    assert!(matches!(auth.add_owners(&ci, &["user2"], "acme-lib").await, Err(AuthError::Forbidden)));
+    assert!(matches!(auth.remove_owners(&ci, &["user1"], "acme-lib").await, Err(AuthError::Forbidden)));
+    auth.auth_index_fetch(&ci, "other").await.unwrap();
+
+    // the new crate wasn't claimed by the scoped token
+    auth.publish(&user1, "acme-new").await.unwrap();
+    auth.add_owners(&user1, &["user2"], "acme-lib").await.unwrap();
+
+    let backup = auth.export_backup().await.unwrap();
+    assert_eq!(backup.tokens.len(), 2);
+    assert!(backup.tokens.iter().any(|t| t.details.as_ref().is_some_and(|d| d.name == "ci" && !d.scopes.is_unrestricted())));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_named_tokens() {
+    let dir = tempfile::tempdir().unwrap();
+    let auth = FsAuthProvider::new(Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [123; 18] }).unwrap();
+    let user1 = auth.register("user1").await.unwrap();
+    let user2 = auth.register("user2").await.unwrap();
+    auth.publish(&user1, "crate1").await.unwrap();
+
+    let laptop = auth.create_token(&user1, &NewToken { name: "laptop".into(), ..NewToken::default() }).await.unwrap();
+    let expiring = NewToken { name: "temp".into(), expired_at: Some(Utc::now() + chrono::TimeDelta::hours(1)), ..NewToken::default() };
+    let temp = auth.create_token(&user1, &expiring).await.unwrap();
+    let already_expired = NewToken { name: "old".into(), expired_at: Some(Utc::now() - chrono::TimeDelta::hours(1)), ..NewToken::default() };
+    let old = auth.create_token(&user1, &already_expired).await.unwrap();
+    auth.auth_yank(&laptop.token, "crate1").await.unwrap();
+    auth.auth_yank(&temp.token, "crate1").await.unwrap();
+    assert!(matches!(auth.auth_yank(&old.token, "crate1").await, Err(AuthError::InvalidCredentials)));
+
+    let tokens = auth.list_tokens(&laptop.token).await.unwrap();
+    let names: Vec<_> = tokens.iter().map(|t| t.token.name.as_str()).collect();
+    assert_eq!(names.len(), 4);
+    assert!(["default", "laptop", "temp", "old"].iter().all(|n| names.contains(n)));
+    assert_eq!(auth.list_tokens(&user2).await.unwrap().len(), 1);
+
+    // rotate the leaked token
+    let first_id = tokens.iter().find(|t| t.token.name == "default").unwrap().id;
+    assert!(matches!(auth.revoke_token(&user2, first_id).await, Err(AuthError::TokenNotFound)));
+    auth.revoke_token(&laptop.token, first_id).await.unwrap();
+    assert!(matches!(auth.auth_yank(&user1, "crate1").await, Err(AuthError::InvalidCredentials)));
+
+    // reload
+    let auth = FsAuthProvider::new(Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [123; 18] }).unwrap();
+    assert!(matches!(auth.auth_yank(&user1, "crate1").await, Err(AuthError::InvalidCredentials)));
+    auth.revoke_token(&laptop.token, temp.info.id).await.unwrap();
+    auth.revoke_token(&laptop.token, old.info.id).await.unwrap();
+    assert!(matches!(auth.revoke_token(&laptop.token, laptop.info.id).await, Err(AuthError::Forbidden)));
+    assert_eq!(auth.list_tokens(&laptop.token).await.unwrap(), vec![laptop.info]);
+    assert!(matches!(auth.register("user1").await, Err(AuthError::Forbidden)));
+}
@@ -0,0 +1,232 @@
+use crate::{AuthError, AuthResult};
+use jsonwebtoken::jwk::{JwkSet, PublicKeyUse};
+use jsonwebtoken::{DecodingKey, Validation};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Check for new keys (Cloudflare gives them 4h max-age)
+const REFRESH_DURATION: Duration = Duration::from_hours(1);
+/// Tokens signed with an unknown key make the keys refresh, but not more often than this
+const MIN_REFRESH_DURATION: Duration = Duration::from_mins(1);
+
+/// Where the keys come from
+pub enum KeySource {
+    /// URL of the JWKS, or a `file://` path
+    Jwks(String),
+    /// Issuer URL, whose OpenID configuration has the JWKS URL
+    #[cfg(any(feature = "oidc-backend", feature = "trusted-publishing"))]
+    Issuer(String),
+}
+
+/// JWT verifier, with keys from a JWKS that are refreshed periodically
+pub struct Jwks {
+    source: KeySource,
+    validation: Validation,
+    /// Accept keys without `use`, and tokens and keys without `kid`
+    lenient: bool,
+    key_set: RwLock<KeySet>,
+}
+
+/// Allowed keys
+struct KeySet {
+    next_fetch: Instant,
+    last_fetch: Option<Instant>,
+    /// By key id. Keys without one have an empty id if lenient.
+    keys: HashMap<String, DecodingKey>,
+}
+
+#[cfg(any(feature = "oidc-backend", feature = "trusted-publishing"))]
+#[derive(serde::Deserialize)]
+struct OpenIdConfiguration {
+    jwks_uri: String,
+}
+
+impl Jwks {
+    /// Only accepts signing keys with ids, and tokens with key ids
+    pub fn new(source: KeySource, validation: Validation) -> Self {
+        Self {
+            source,
+            validation,
+            lenient: false,
+            key_set: RwLock::new(KeySet {
+                next_fetch: Instant::now(),
+                last_fetch: None,
+                keys: HashMap::default(),
+            }),
+        }
+    }
+
+    /// Also accepts keys without `use` or `kid`, which some OpenID providers publish, and tokens without `kid`
+    #[cfg(any(feature = "oidc-backend", feature = "trusted-publishing"))]
+    #[must_use]
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    async fn fetch<T: DeserializeOwned>(url: &str) -> anyhow::Result<T> {
+        if let Some(path) = url.strip_prefix("file://") {
+            return Ok(serde_json::from_slice(&std::fs::read(path)?)?);
+        }
+        Ok(reqwest::get(url).await?.error_for_status()?.json().await?)
+    }
+
+    async fn fetch_keys(&self) -> anyhow::Result<JwkSet> {
+        match &self.source {
+            KeySource::Jwks(url) => Self::fetch(url).await,
+            #[cfg(any(feature = "oidc-backend", feature = "trusted-publishing"))]
+            KeySource::Issuer(issuer) => {
+                let url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+                let config: OpenIdConfiguration = Self::fetch(&url).await?;
+                Self::fetch(&config.jwks_uri).await
+            }
+        }
+    }
+
+    /// Download new keys
+    pub async fn refresh(&self) -> Result<(), anyhow::Error> {
+        let mut locked_keys = self.key_set.write().await;
+        let now = Instant::now();
+        if locked_keys.next_fetch > now {
+            if locked_keys.keys.is_empty() {
+                anyhow::bail!("no usable keys");
+            }
+            return Ok(());
+        }
+
+        locked_keys.next_fetch = now + Duration::from_secs(1); // in case of failure, retry 1/s
+        let set = self
+            .fetch_keys()
+            .await
+            .inspect_err(|e| tracing::error!("Failed to fetch JWKS: {e}"))?;
+        locked_keys.keys = set
+            .keys
+            .into_iter()
+            .filter(|k| match &k.common.public_key_use {
+                Some(key_use) => *key_use == PublicKeyUse::Signature,
+                None => self.lenient,
+            })
+            .filter_map(|k| {
+                let key = DecodingKey::from_jwk(&k)
+                    .inspect_err(|e| tracing::error!("{:?}: {e}", k.common.key_id))
+                    .ok()?;
+                let kid = match k.common.key_id {
+                    Some(kid) => kid,
+                    None if self.lenient => String::new(),
+                    None => return None,
+                };
+                Some((kid, key))
+            })
+            .collect();
+        if locked_keys.keys.is_empty() {
+            tracing::error!("no usable keys");
+            anyhow::bail!("no usable keys");
+        }
+        locked_keys.last_fetch = Some(now);
+        locked_keys.next_fetch = Instant::now() + REFRESH_DURATION;
+        Ok(())
+    }
+
+    /// Makes the next verification refresh the keys, unless they've just been refreshed
+    async fn expire_keys(&self) -> bool {
+        let mut locked_keys = self.key_set.write().await;
+        if locked_keys.last_fetch.is_some_and(|t| t.elapsed() < MIN_REFRESH_DURATION) {
+            return false;
+        }
+        locked_keys.next_fetch = Instant::now();
+        true
+    }
+
+    /// Returns the claims of a valid token
+    pub async fn verify<C: DeserializeOwned>(&self, token: &str) -> AuthResult<C> {
+        let key_id = jsonwebtoken::decode_header(token)
+            .map_err(|e| {
+                tracing::warn!("bad token: {e}");
+                AuthError::InvalidCredentials
+            })?
+            .kid;
+        let key_id = match key_id {
+            Some(kid) => kid,
+            None if self.lenient => String::new(),
+            None => return Err(AuthError::InvalidCredentials),
+        };
+
+        let mut expired = false;
+        let locked_keys = loop {
+            let tmp = self.key_set.read().await;
+            if tmp.next_fetch < Instant::now() {
+                drop(tmp);
+                self.refresh().await?;
+                continue;
+            }
+            // the keys may have been rotated
+            if !tmp.keys.contains_key(&key_id) && !expired {
+                drop(tmp);
+                expired = true;
+                if self.expire_keys().await {
+                    continue;
+                }
+                break self.key_set.read().await;
+            }
+            break tmp;
+        };
+
+        let Some(key) = locked_keys.keys.get(&key_id) else {
+            tracing::warn!("token for an unknown key: {key_id}");
+            return Err(AuthError::InvalidCredentials);
+        };
+
+        let claims = jsonwebtoken::decode::<C>(token, key, &self.validation)
+            .map_err(|e| {
+                tracing::warn!("unauthorized: {e}");
+                AuthError::Unauthorized
+            })?
+            .claims;
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+
+    const SECRET: &[u8] = b"very secret key for tests";
+
+    fn token(kid: Option<&str>) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = kid.map(From::from);
+        let claims = serde_json::json!({"exp": jsonwebtoken::get_current_timestamp() + 60});
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn strict_key_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jwks.json");
+        let k = "dmVyeSBzZWNyZXQga2V5IGZvciB0ZXN0cw";
+        let keys = serde_json::json!({"keys": [
+            {"kty": "oct", "kid": "signing", "use": "sig", "k": k},
+            {"kty": "oct", "kid": "no-use", "k": k},
+            {"kty": "oct", "use": "sig", "k": k},
+        ]});
+        std::fs::write(&path, keys.to_string()).unwrap();
+        let source = || KeySource::Jwks(format!("file://{}", path.display()));
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.required_spec_claims.clear();
+
+        let strict = Jwks::new(source(), validation.clone());
+        strict.verify::<serde_json::Value>(&token(Some("signing"))).await.unwrap();
+        assert!(matches!(strict.verify::<serde_json::Value>(&token(Some("no-use"))).await, Err(AuthError::InvalidCredentials)));
+        assert!(matches!(strict.verify::<serde_json::Value>(&token(None)).await, Err(AuthError::InvalidCredentials)));
+
+        #[cfg(any(feature = "oidc-backend", feature = "trusted-publishing"))]
+        {
+            let lenient = Jwks::new(source(), validation).lenient();
+            lenient.verify::<serde_json::Value>(&token(Some("no-use"))).await.unwrap();
+            lenient.verify::<serde_json::Value>(&token(None)).await.unwrap();
+        }
+    }
+}
@@ -14,11 +14,28 @@ pub mod no_backend;
 #[cfg_attr(docsrs, doc(cfg(feature = "fs-backend")))]
 pub mod fs_backend;
 
+#[cfg(feature = "pg-backend")]
+#[cfg_attr(docsrs, doc(cfg(feature = "pg-backend")))]
+pub mod pg_backend;
+
+#[cfg(feature = "asymmetric-tokens")]
+#[cfg_attr(docsrs, doc(cfg(feature = "asymmetric-tokens")))]
+pub mod asymmetric;
+
 #[cfg(feature = "fs-backend")]
 mod base64_serde;
 
 mod error;
 
+pub mod scopes;
+
+pub mod tokens;
+
+pub mod read_acl;
+
+#[cfg(any(feature = "cf-backend", feature = "oidc-backend", feature = "trusted-publishing"))]
+mod jwks;
+
 #[cfg(feature = "cf-backend")]
 mod cf_access;
 
@@ -26,8 +43,19 @@ mod cf_access;
 #[cfg_attr(docsrs, doc(cfg(feature = "cf-backend")))]
 pub mod cf_backend;
 
+#[cfg(feature = "oidc-backend")]
+#[cfg_attr(docsrs, doc(cfg(feature = "oidc-backend")))]
+pub mod oidc_backend;
+
+#[cfg(feature = "trusted-publishing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "trusted-publishing")))]
+pub mod trusted_publishing;
+
 pub use error::*;
 use freighter_api_types::ownership::response::ListedOwner;
+use tokens::{CreatedToken, NewToken, TokenInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
 #[async_trait]
 pub trait AuthProvider {
@@ -52,6 +80,41 @@ pub trait AuthProvider {
     /// Remove an owner from a crate.
     async fn remove_owners(&self, token: &str, users: &[&str], crate_name: &str) -> AuthResult<()>;
 
+    /// Get the login of the user that owns the token.
+    async fn token_login(&self, token: &str) -> AuthResult<String> {
+        let _ = token;
+        Err(AuthError::Unimplemented)
+    }
+
+    /// Create another token of the user of the token, which can only do what its scopes allow.
+    ///
+    /// Tokens that are scoped themselves can't create or revoke tokens.
+    async fn create_token(&self, token: &str, new_token: &NewToken) -> AuthResult<CreatedToken> {
+        let _ = (token, new_token);
+        Err(AuthError::Unimplemented)
+    }
+
+    /// All tokens of the user of the token, including expired ones.
+    async fn list_tokens(&self, token: &str) -> AuthResult<Vec<TokenInfo>> {
+        let _ = token;
+        Err(AuthError::Unimplemented)
+    }
+
+    /// Revoke a token of the user of the token, by its [`TokenInfo::id`].
+    async fn revoke_token(&self, token: &str, id: u32) -> AuthResult<()> {
+        let _ = (token, id);
+        Err(AuthError::Unimplemented)
+    }
+
+    /// Let the user of the token authenticate with cargo's asymmetric tokens signed by the key,
+    /// given as a `k3.public.` PASERK.
+    ///
+    /// The backend checks the signatures of asymmetric tokens. Claims about the request are checked by the server.
+    async fn register_public_key(&self, token: &str, public_key: &str) -> AuthResult<()> {
+        let _ = (token, public_key);
+        Err(AuthError::Unimplemented)
+    }
+
     /// Verify that a user has permission to publish new versions of a crate.
     ///
     /// If the crate has never been published before to the registry, the user should be given
@@ -96,11 +159,47 @@ pub trait AuthProvider {
         Err(AuthError::Unimplemented)
     }
 
+    /// Owners of all crates, and tokens in the form the backend stores them, for backups.
+    async fn export_backup(&self) -> AuthResult<AuthBackup> {
+        Err(AuthError::Unimplemented)
+    }
+
+    /// Adds owners and tokens from [`AuthProvider::export_backup`], possibly of another backend.
+    ///
+    /// Tokens in a format the backend doesn't use are skipped. Users that already exist keep
+    /// their current tokens.
+    async fn import_backup(&self, backup: &AuthBackup) -> AuthResult<()> {
+        let _ = backup;
+        Err(AuthError::Unimplemented)
+    }
+
     fn token_from_headers<'h>(&self, headers: &'h HeaderMap) -> Result<Option<&'h str>, StatusCode> {
         default_token_from_headers(headers)
     }
 }
 
+/// Everything an auth backend stores, for backups
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AuthBackup {
+    /// Logins of the owners of each crate
+    pub crate_owners: BTreeMap<String, BTreeSet<String>>,
+    pub tokens: Vec<BackupToken>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupToken {
+    pub login: String,
+    /// Identifies the backend and hashing scheme of the token, since backends don't store tokens
+    /// in plain text
+    pub format: String,
+    pub token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Name, expiry and scopes, for backends that have them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<NewToken>,
+}
+
 pub(crate) fn default_token_from_headers(headers: &HeaderMap) -> Result<Option<&str>, StatusCode> {
     match headers.get(AUTHORIZATION) {
         Some(auth) => auth.to_str().map_err(|_| StatusCode::BAD_REQUEST).map(Some),
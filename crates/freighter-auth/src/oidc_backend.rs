@@ -0,0 +1,314 @@
+use crate::jwks::{Jwks, KeySource};
+use crate::{AuthError, AuthProvider, AuthResult};
+use async_trait::async_trait;
+use freighter_api_types::ownership::response::ListedOwner;
+use http::{HeaderMap, StatusCode};
+use jsonwebtoken::{Algorithm, Validation};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Registry auth with JWTs from any OpenID Connect provider (Okta, Keycloak, Dex, etc.)
+///
+/// Tokens aren't issued by Freighter. Users log in to cargo with an ID or access token obtained from the provider.
+/// Everyone with a valid token can read crates. Publishing and yanking can be limited to members of groups,
+/// which are read from a claim in the token.
+pub struct OidcAuthProvider {
+    jwks: Jwks,
+    owner: String,
+    login_claim: String,
+    groups_claim: String,
+    publish_groups: Option<Vec<String>>,
+    yank_groups: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Config {
+    /// `iss` of the tokens. Keys are found via its `/.well-known/openid-configuration`,
+    /// unless `auth_jwks_url` is set.
+    #[serde(default)]
+    pub auth_issuer: Option<String>,
+    /// URL of the keys, or `file://` path of a local JWKS file
+    #[serde(default)]
+    pub auth_jwks_url: Option<String>,
+    /// `aud` of the tokens, usually the client ID
+    pub auth_audience: String,
+    /// Signing algorithms accepted, `RS256` by default
+    #[serde(default = "default_auth_algorithms")]
+    pub auth_algorithms: Vec<Algorithm>,
+    /// Claim used as the login of users. Nested claims can be separated with `.`.
+    #[serde(default = "default_auth_login_claim")]
+    pub auth_login_claim: String,
+    /// Claim with a list of groups or roles, e.g. `realm_access.roles` for Keycloak
+    #[serde(default = "default_auth_groups_claim")]
+    pub auth_groups_claim: String,
+    /// Groups allowed to publish crates. Anyone with a valid token if not set.
+    #[serde(default)]
+    pub auth_publish_groups: Option<Vec<String>>,
+    /// Groups allowed to yank crates. Same as `auth_publish_groups` if not set.
+    #[serde(default)]
+    pub auth_yank_groups: Option<Vec<String>>,
+}
+
+fn default_auth_algorithms() -> Vec<Algorithm> {
+    vec![Algorithm::RS256]
+}
+
+fn default_auth_login_claim() -> String {
+    "sub".into()
+}
+
+fn default_auth_groups_claim() -> String {
+    "groups".into()
+}
+
+impl OidcAuthProvider {
+    pub fn new(config: Config) -> AuthResult<Self> {
+        if config.auth_audience.is_empty() || config.auth_algorithms.is_empty() {
+            return Err(anyhow::anyhow!("invalid oidc config: audience and algorithms are required").into());
+        }
+        let source = match (config.auth_jwks_url, &config.auth_issuer) {
+            (Some(url), _) => KeySource::Jwks(url),
+            (None, Some(issuer)) => KeySource::Issuer(issuer.clone()),
+            (None, None) => {
+                return Err(anyhow::anyhow!("invalid oidc config: auth_issuer or auth_jwks_url is required").into());
+            }
+        };
+        let owner = match &source {
+            KeySource::Jwks(url) | KeySource::Issuer(url) => url.clone(),
+        };
+
+        let mut validation = Validation::new(config.auth_algorithms[0]);
+        validation.algorithms = config.auth_algorithms;
+        validation.set_audience(&[config.auth_audience]);
+        if let Some(issuer) = &config.auth_issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        let yank_groups = config.auth_yank_groups.or_else(|| config.auth_publish_groups.clone());
+        Ok(Self {
+            jwks: Jwks::new(source, validation).lenient(),
+            owner,
+            login_claim: config.auth_login_claim,
+            groups_claim: config.auth_groups_claim,
+            publish_groups: config.auth_publish_groups,
+            yank_groups,
+        })
+    }
+
+    async fn validated_claims(&self, token: &str) -> AuthResult<Value> {
+        self.jwks.verify(token).await
+    }
+
+    async fn validated_login(&self, token: &str) -> AuthResult<String> {
+        let claims = self.validated_claims(token).await?;
+        match claim(&claims, &self.login_claim) {
+            Some(Value::String(login)) if !login.is_empty() => Ok(login.clone()),
+            _ => {
+                tracing::warn!("token without {} claim", self.login_claim);
+                Err(AuthError::Unauthorized)
+            }
+        }
+    }
+
+    /// Checks the token, and that the user is in one of the groups, if there are any
+    async fn check_groups(&self, token: &str, groups: Option<&[String]>) -> AuthResult<()> {
+        let claims = self.validated_claims(token).await?;
+        let Some(groups) = groups else {
+            return Ok(());
+        };
+        let is_member = match claim(&claims, &self.groups_claim) {
+            Some(Value::Array(user_groups)) => user_groups
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|g| groups.iter().any(|allowed| allowed == g)),
+            Some(Value::String(user_group)) => groups.contains(user_group),
+            _ => false,
+        };
+        if is_member {
+            Ok(())
+        } else {
+            Err(AuthError::Forbidden)
+        }
+    }
+}
+
+/// Claim by name, or by a path of nested claims separated with `.`
+fn claim<'a>(claims: &'a Value, name: &str) -> Option<&'a Value> {
+    if let Some(value) = claims.get(name) {
+        return Some(value);
+    }
+    name.split('.').try_fold(claims, |value, key| value.get(key))
+}
+
+#[async_trait]
+impl AuthProvider for OidcAuthProvider {
+    type Config = Config;
+
+    async fn healthcheck(&self) -> anyhow::Result<()> {
+        self.jwks.refresh().await
+    }
+
+    async fn register(&self, _username: &str) -> AuthResult<String> {
+        Err(AuthError::Unimplemented)
+    }
+
+    fn register_supported(&self) -> Result<(), &'static str> {
+        Err("<h1>Registration is via the identity provider</h1>
+<p>Log in with a token issued by the identity provider:</p>
+<pre>
+cargo login --registry=<var>name of the registry</var>
+</pre>")
+    }
+
+    async fn token_login(&self, token: &str) -> AuthResult<String> {
+        self.validated_login(token).await
+    }
+
+    async fn list_owners(&self, token: &str, _crate_name: &str) -> AuthResult<Vec<ListedOwner>> {
+        self.validated_claims(token).await?;
+
+        // the groups own all crates
+        let owners = match &self.publish_groups {
+            Some(groups) => groups.clone(),
+            None => vec![self.owner.clone()],
+        };
+        Ok(owners
+            .into_iter()
+            .map(|login| ListedOwner { id: 0, login, name: None })
+            .collect())
+    }
+
+    async fn add_owners(&self, token: &str, _users: &[&str], _crate_name: &str) -> AuthResult<()> {
+        self.validated_claims(token).await?;
+        Err(AuthError::Unimplemented)
+    }
+
+    async fn remove_owners(&self, token: &str, _users: &[&str], _crate_name: &str) -> AuthResult<()> {
+        self.validated_claims(token).await?;
+        Err(AuthError::Unimplemented)
+    }
+
+    async fn publish(&self, token: &str, _crate_name: &str) -> AuthResult<()> {
+        self.check_groups(token, self.publish_groups.as_deref()).await
+    }
+
+    async fn auth_yank(&self, token: &str, _crate_name: &str) -> AuthResult<()> {
+        self.check_groups(token, self.yank_groups.as_deref()).await
+    }
+
+    async fn auth_config(&self, token: &str) -> AuthResult<()> {
+        self.validated_claims(token).await?;
+        Ok(())
+    }
+
+    async fn auth_index_fetch(&self, token: &str, _crate_name: &str) -> AuthResult<()> {
+        self.validated_claims(token).await?;
+        Ok(())
+    }
+
+    async fn auth_crate_download(&self, token: &str, _crate_name: &str) -> AuthResult<()> {
+        self.validated_claims(token).await?;
+        Ok(())
+    }
+
+    async fn auth_view_full_index(&self, token: &str) -> AuthResult<()> {
+        self.validated_claims(token).await?;
+        Ok(())
+    }
+
+    fn token_from_headers<'h>(&self, headers: &'h HeaderMap) -> Result<Option<&'h str>, StatusCode> {
+        Ok(crate::default_token_from_headers(headers)?.map(|t| t.strip_prefix("Bearer ").unwrap_or(t)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"very secret key for tests";
+
+    fn provider(dir: &tempfile::TempDir, publish_groups: Option<Vec<String>>) -> OidcAuthProvider {
+        let path = dir.path().join("jwks.json");
+        let jwks = json!({"keys": [{
+            "kty": "oct",
+            "kid": "test-key",
+            "alg": "HS256",
+            "k": "dmVyeSBzZWNyZXQga2V5IGZvciB0ZXN0cw",
+        }]});
+        std::fs::write(&path, jwks.to_string()).unwrap();
+
+        OidcAuthProvider::new(Config {
+            auth_issuer: Some("https://idp.example.com".into()),
+            auth_jwks_url: Some(format!("file://{}", path.display())),
+            auth_audience: "freighter".into(),
+            auth_algorithms: vec![Algorithm::HS256],
+            auth_login_claim: "email".into(),
+            auth_groups_claim: "realm_access.roles".into(),
+            auth_publish_groups: publish_groups,
+            auth_yank_groups: None,
+        })
+        .unwrap()
+    }
+
+    fn token(aud: &str, roles: &[&str]) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test-key".into());
+        let claims = json!({
+            "iss": "https://idp.example.com",
+            "aud": aud,
+            "exp": 4_000_000_000_u64,
+            "sub": "1234",
+            "email": "alice@example.com",
+            "realm_access": {"roles": roles},
+        });
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn login_and_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        let auth = provider(&dir, Some(vec!["publishers".into()]));
+
+        let publisher = token("freighter", &["users", "publishers"]);
+        let reader = token("freighter", &["users"]);
+        assert_eq!(auth.token_login(&publisher).await.unwrap(), "alice@example.com");
+        auth.publish(&publisher, "foo").await.unwrap();
+        auth.auth_yank(&publisher, "foo").await.unwrap();
+
+        auth.auth_crate_download(&reader, "foo").await.unwrap();
+        assert!(matches!(auth.publish(&reader, "foo").await, Err(AuthError::Forbidden)));
+        assert!(matches!(auth.auth_yank(&reader, "foo").await, Err(AuthError::Forbidden)));
+
+        let owners = auth.list_owners(&reader, "foo").await.unwrap();
+        assert_eq!(owners[0].login, "publishers");
+    }
+
+    #[tokio::test]
+    async fn invalid_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let auth = provider(&dir, None);
+
+        let other_audience = token("other", &[]);
+        assert!(matches!(auth.auth_config(&other_audience).await, Err(AuthError::Unauthorized)));
+        assert!(matches!(auth.auth_config("not a jwt").await, Err(AuthError::InvalidCredentials)));
+
+        let mut forged = token("freighter", &[]);
+        forged.pop();
+        assert!(matches!(auth.auth_config(&forged).await, Err(AuthError::Unauthorized)));
+
+        // anyone can publish without publish groups
+        auth.publish(&token("freighter", &[]), "foo").await.unwrap();
+    }
+
+    #[test]
+    fn bearer_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let auth = provider(&dir, None);
+
+        let mut h = HeaderMap::new();
+        h.insert("authorization", http::HeaderValue::from_static("Bearer aaaa.bbbb.cccc"));
+        assert_eq!(auth.token_from_headers(&h).unwrap(), Some("aaaa.bbbb.cccc"));
+    }
+}
@@ -0,0 +1,463 @@
+use crate::asymmetric::{AsymmetricToken, PublicKey};
+use crate::{AuthBackup, AuthError, AuthProvider, AuthResult, BackupToken};
+use anyhow::Context;
+use async_trait::async_trait;
+use deadpool_postgres::tokio_postgres::NoTls;
+use deadpool_postgres::{GenericClient, Pool, Runtime};
+use freighter_api_types::ownership::response::ListedOwner;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+
+const TOKEN_PREFIX: &str = "frp_";
+const TOKEN_LEN: usize = 32;
+const BACKUP_TOKEN_FORMAT: &str = "pg-sha256";
+
+/// Keeps users, their tokens and crate owners in the tables of `sql/init-auth-db.sql`,
+/// so that it can be shared by several instances.
+pub struct PgAuthProvider {
+    pool: Pool,
+}
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub auth_db: deadpool_postgres::Config,
+}
+
+/// Id and login
+type User = (i32, String);
+
+impl PgAuthProvider {
+    pub fn new(config: Config) -> AuthResult<Self> {
+        let pool = config
+            .auth_db
+            .create_pool(Some(Runtime::Tokio1), NoTls)
+            .context("Failed to create auth db pool")?;
+
+        Ok(Self { pool })
+    }
+
+    async fn client(&self) -> AuthResult<deadpool_postgres::Client> {
+        Ok(self.pool.get().await.context("Failed to get auth db connection")?)
+    }
+
+    fn random_token() -> String {
+        use rand::distr::{Alphanumeric, SampleString};
+        let mut token = String::with_capacity(TOKEN_PREFIX.len() + TOKEN_LEN);
+        token.push_str(TOKEN_PREFIX);
+        Alphanumeric.append_string(&mut rand::rng(), &mut token, TOKEN_LEN);
+        token
+    }
+
+    /// Tokens are random, so they don't need a salted or slow hash, and can be looked up by hash
+    fn hash_token(token: &str) -> String {
+        format!("{:x}", Sha256::digest(token.as_bytes()))
+    }
+
+    async fn token_user(client: &impl GenericClient, token: &str) -> AuthResult<User> {
+        if AsymmetricToken::is_asymmetric(token) {
+            return Self::asymmetric_token_user(client, token).await;
+        }
+        let statement = client
+            .prepare_cached(include_str!("../sql/get-token-user.sql"))
+            .await
+            .context("Failed to prepare token statement")?;
+
+        let row = client
+            .query_opt(&statement, &[&Self::hash_token(token)])
+            .await
+            .context("Failed to query token")?
+            .ok_or(AuthError::InvalidCredentials)?;
+
+        Ok((row.get("id"), row.get("username")))
+    }
+
+    /// Asymmetric tokens act as the tokens of the user who registered the key
+    async fn asymmetric_token_user(client: &impl GenericClient, token: &str) -> AuthResult<User> {
+        let token = AsymmetricToken::parse(token)?;
+        let statement = client
+            .prepare_cached(include_str!("../sql/get-key-user.sql"))
+            .await
+            .context("Failed to prepare key statement")?;
+
+        let row = client
+            .query_opt(&statement, &[&token.footer.kip])
+            .await
+            .context("Failed to query key")?
+            .ok_or(AuthError::InvalidCredentials)?;
+
+        token.verify(&PublicKey::parse(row.get("public_key"))?)?;
+        Ok((row.get("id"), row.get("username")))
+    }
+
+    async fn crate_owners(client: &impl GenericClient, crate_name: &str) -> AuthResult<Vec<User>> {
+        let statement = client
+            .prepare_cached(include_str!("../sql/list-owners.sql"))
+            .await
+            .context("Failed to prepare owners statement")?;
+
+        let rows = client
+            .query(&statement, &[&crate_name])
+            .await
+            .context("Failed to query owners")?;
+
+        Ok(rows.iter().map(|row| (row.get("id"), row.get("username"))).collect())
+    }
+
+    async fn ensure_owner(client: &impl GenericClient, user_id: i32, crate_name: &str) -> AuthResult<()> {
+        let owners = Self::crate_owners(client, crate_name).await?;
+        if owners.is_empty() {
+            Err(AuthError::CrateNotFound)
+        } else if owners.iter().any(|(id, _)| *id == user_id) {
+            Ok(())
+        } else {
+            Err(AuthError::Forbidden)
+        }
+    }
+
+    /// Serializes changes of the owners of a crate, until the end of the transaction
+    async fn lock_crate(client: &impl GenericClient, crate_name: &str) -> AuthResult<()> {
+        let statement = client
+            .prepare_cached(include_str!("../sql/lock-crate.sql"))
+            .await
+            .context("Failed to prepare lock statement")?;
+
+        client
+            .execute(&statement, &[&crate_name])
+            .await
+            .context("Failed to lock crate owners")?;
+        Ok(())
+    }
+
+    async fn users(client: &impl GenericClient, logins: &[&str]) -> AuthResult<Vec<User>> {
+        let statement = client
+            .prepare_cached(include_str!("../sql/get-users.sql"))
+            .await
+            .context("Failed to prepare users statement")?;
+
+        let rows = client
+            .query(&statement, &[&logins])
+            .await
+            .context("Failed to query users")?;
+
+        Ok(rows.iter().map(|row| (row.get("id"), row.get("username"))).collect())
+    }
+
+    /// Returns the id of the user if it's been created, or `None` if it already exists
+    async fn insert_user(client: &impl GenericClient, login: &str) -> AuthResult<Option<i32>> {
+        let statement = client
+            .prepare_cached(include_str!("../sql/insert-user.sql"))
+            .await
+            .context("Failed to prepare user statement")?;
+
+        let row = client
+            .query_opt(&statement, &[&login])
+            .await
+            .context("Failed to insert user")?;
+
+        Ok(row.map(|row| row.get("id")))
+    }
+
+    async fn insert_token(client: &impl GenericClient, user_id: i32, token_hash: &str) -> AuthResult<()> {
+        let statement = client
+            .prepare_cached(include_str!("../sql/insert-token.sql"))
+            .await
+            .context("Failed to prepare token statement")?;
+
+        client
+            .execute(&statement, &[&user_id, &token_hash])
+            .await
+            .context("Failed to insert token")?;
+        Ok(())
+    }
+
+    async fn add_owner_ids(client: &impl GenericClient, user_ids: &[i32], crate_name: &str) -> AuthResult<()> {
+        let statement = client
+            .prepare_cached(include_str!("../sql/add-owners.sql"))
+            .await
+            .context("Failed to prepare owners statement")?;
+
+        client
+            .execute(&statement, &[&user_ids, &crate_name])
+            .await
+            .context("Failed to add owners")?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuthProvider for PgAuthProvider {
+    type Config = Config;
+
+    async fn healthcheck(&self) -> anyhow::Result<()> {
+        let _ = self.pool.get().await?;
+        Ok(())
+    }
+
+    async fn register(&self, username: &str) -> AuthResult<String> {
+        let mut client = self.client().await?;
+        let transaction = client
+            .transaction()
+            .await
+            .context("Failed to create registration transaction")?;
+
+        // the username is taken
+        let user_id = Self::insert_user(&transaction, username)
+            .await?
+            .ok_or(AuthError::Forbidden)?;
+
+        let token = Self::random_token();
+        Self::insert_token(&transaction, user_id, &Self::hash_token(&token)).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit registration")?;
+
+        tracing::info!("Registered {username}");
+        Ok(token)
+    }
+
+    async fn token_login(&self, token: &str) -> AuthResult<String> {
+        let client = self.client().await?;
+        Ok(Self::token_user(&client, token).await?.1)
+    }
+
+    async fn register_public_key(&self, token: &str, public_key: &str) -> AuthResult<()> {
+        let public_key = PublicKey::parse(public_key)?;
+        let client = self.client().await?;
+        let (user_id, login) = Self::token_user(&client, token).await?;
+
+        let statement = client
+            .prepare_cached(include_str!("../sql/insert-public-key.sql"))
+            .await
+            .context("Failed to prepare key statement")?;
+        client
+            .query_opt(&statement, &[&user_id, &public_key.id(), &public_key.as_str()])
+            .await
+            .context("Failed to insert key")?
+            // registered by another user
+            .ok_or(AuthError::Forbidden)?;
+
+        tracing::info!("Registered a public key of {login}");
+        Ok(())
+    }
+
+    async fn list_owners(&self, _owner_list_is_public: &str, crate_name: &str) -> AuthResult<Vec<ListedOwner>> {
+        let client = self.client().await?;
+        let owners = Self::crate_owners(&client, crate_name).await?;
+        if owners.is_empty() {
+            return Err(AuthError::CrateNotFound);
+        }
+
+        Ok(owners
+            .into_iter()
+            .map(|(id, login)| ListedOwner {
+                id: id.try_into().unwrap_or_default(),
+                login,
+                name: None,
+            })
+            .collect())
+    }
+
+    async fn add_owners(&self, token: &str, users: &[&str], crate_name: &str) -> AuthResult<()> {
+        let mut client = self.client().await?;
+        let transaction = client
+            .transaction()
+            .await
+            .context("Failed to create owners transaction")?;
+
+        let (user_id, _) = Self::token_user(&transaction, token).await?;
+        Self::lock_crate(&transaction, crate_name).await?;
+        Self::ensure_owner(&transaction, user_id, crate_name).await?;
+
+        let new_owners = Self::users(&transaction, users).await?;
+        if let Some(&missing) = users
+            .iter()
+            .find(|&&login| !new_owners.iter().any(|(_, l)| l == login))
+        {
+            tracing::debug!(missing, "Can't add unknown user as an owner");
+            return Err(AuthError::UserNotFound);
+        }
+
+        let ids: Vec<i32> = new_owners.iter().map(|(id, _)| *id).collect();
+        Self::add_owner_ids(&transaction, &ids, crate_name).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit owners")?;
+        Ok(())
+    }
+
+    async fn remove_owners(&self, token: &str, users: &[&str], crate_name: &str) -> AuthResult<()> {
+        let mut client = self.client().await?;
+        let transaction = client
+            .transaction()
+            .await
+            .context("Failed to create owners transaction")?;
+
+        let (user_id, _) = Self::token_user(&transaction, token).await?;
+        Self::lock_crate(&transaction, crate_name).await?;
+        Self::ensure_owner(&transaction, user_id, crate_name).await?;
+
+        let ids: Vec<i32> = Self::users(&transaction, users)
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+
+        let statement = transaction
+            .prepare_cached(include_str!("../sql/remove-owners.sql"))
+            .await
+            .context("Failed to prepare owners statement")?;
+        transaction
+            .execute(&statement, &[&ids, &crate_name])
+            .await
+            .context("Failed to remove owners")?;
+
+        // Can't remove all owners
+        if Self::crate_owners(&transaction, crate_name).await?.is_empty() {
+            return Err(AuthError::Forbidden);
+        }
+
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit owners")?;
+        Ok(())
+    }
+
+    async fn publish(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        let mut client = self.client().await?;
+        let transaction = client
+            .transaction()
+            .await
+            .context("Failed to create publish transaction")?;
+
+        let (user_id, _) = Self::token_user(&transaction, token).await?;
+        Self::lock_crate(&transaction, crate_name).await?;
+
+        let owners = Self::crate_owners(&transaction, crate_name).await?;
+        // If the crate doesn't exist yet, allow anybody to publish
+        if owners.is_empty() {
+            Self::add_owner_ids(&transaction, &[user_id], crate_name).await?;
+        } else if !owners.iter().any(|(id, _)| *id == user_id) {
+            return Err(AuthError::Forbidden);
+        }
+
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit publish")?;
+        Ok(())
+    }
+
+    async fn auth_yank(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        let client = self.client().await?;
+        let (user_id, _) = Self::token_user(&client, token).await?;
+        Self::ensure_owner(&client, user_id, crate_name).await
+    }
+
+    async fn auth_config(&self, token: &str) -> AuthResult<()> {
+        let client = self.client().await?;
+        Self::token_user(&client, token).await.map(drop)
+    }
+
+    async fn auth_index_fetch(&self, token: &str, _crate_name: &str) -> AuthResult<()> {
+        self.auth_config(token).await
+    }
+
+    async fn auth_crate_download(&self, token: &str, _crate_name: &str) -> AuthResult<()> {
+        self.auth_config(token).await
+    }
+
+    async fn auth_view_full_index(&self, token: &str) -> AuthResult<()> {
+        self.auth_config(token).await
+    }
+
+    async fn export_backup(&self) -> AuthResult<AuthBackup> {
+        let client = self.client().await?;
+        let tokens_statement = client
+            .prepare_cached(include_str!("../sql/export-tokens.sql"))
+            .await
+            .context("Failed to prepare tokens statement")?;
+        let owners_statement = client
+            .prepare_cached(include_str!("../sql/export-owners.sql"))
+            .await
+            .context("Failed to prepare owners statement")?;
+
+        let tokens = client
+            .query(&tokens_statement, &[])
+            .await
+            .context("Failed to query tokens")?
+            .iter()
+            .map(|row| BackupToken {
+                login: row.get("username"),
+                format: BACKUP_TOKEN_FORMAT.into(),
+                token: row.get("token_hash"),
+                created_at: None,
+                details: None,
+            })
+            .collect();
+
+        let mut backup = AuthBackup {
+            tokens,
+            ..AuthBackup::default()
+        };
+        for row in client
+            .query(&owners_statement, &[])
+            .await
+            .context("Failed to query owners")?
+        {
+            backup
+                .crate_owners
+                .entry(row.get("crate"))
+                .or_default()
+                .insert(row.get("username"));
+        }
+        Ok(backup)
+    }
+
+    async fn import_backup(&self, backup: &AuthBackup) -> AuthResult<()> {
+        let mut client = self.client().await?;
+        let transaction = client
+            .transaction()
+            .await
+            .context("Failed to create import transaction")?;
+
+        let logins: BTreeSet<&str> = backup
+            .tokens
+            .iter()
+            .map(|t| t.login.as_str())
+            .chain(backup.crate_owners.values().flatten().map(String::as_str))
+            .collect();
+
+        // only users created now get the tokens
+        let mut created = HashMap::new();
+        for login in logins {
+            if let Some(id) = Self::insert_user(&transaction, login).await? {
+                created.insert(login, id);
+            }
+        }
+        for token in backup.tokens.iter().filter(|t| t.format == BACKUP_TOKEN_FORMAT) {
+            if let Some(&user_id) = created.get(token.login.as_str()) {
+                Self::insert_token(&transaction, user_id, &token.token).await?;
+            }
+        }
+
+        for (crate_name, owners) in &backup.crate_owners {
+            let owners: Vec<&str> = owners.iter().map(String::as_str).collect();
+            let ids: Vec<i32> = Self::users(&transaction, &owners)
+                .await?
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            Self::add_owner_ids(&transaction, &ids, crate_name).await?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit import")?;
+        Ok(())
+    }
+}
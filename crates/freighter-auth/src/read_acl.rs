@@ -0,0 +1,214 @@
+//! Private crates, which only some users can see.
+
+use crate::tokens::{CreatedToken, NewToken, TokenInfo};
+use crate::{AuthBackup, AuthError, AuthProvider, AuthResult};
+use async_trait::async_trait;
+use freighter_api_types::crate_name::crate_name_matches;
+use freighter_api_types::ownership::response::ListedOwner;
+use http::{HeaderMap, StatusCode};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Who can read crates, in addition to the usual checks of the backend.
+///
+/// Crates not matched by any rule can be read by everyone who can read the registry.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Config {
+    /// Logins of the members of each group
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>,
+    /// The first rule matching the crate name is used
+    #[serde(default)]
+    pub rules: Vec<ReadRule>,
+}
+
+/// Owners of a crate can always read it
+#[derive(Deserialize, Clone, Debug)]
+pub struct ReadRule {
+    /// Crate names, where `*` matches any characters, e.g. `partner-*`
+    pub crates: Vec<String>,
+    #[serde(default)]
+    pub users: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Anyone who can read the registry, for exceptions from broader rules
+    #[serde(default)]
+    pub everyone: bool,
+}
+
+impl Config {
+    fn rule_for(&self, crate_name: &str) -> Option<&ReadRule> {
+        self.rules.iter().find(|rule| {
+            rule.crates.iter().any(|p| crate_name_matches(p, crate_name))
+        })
+    }
+
+    fn in_group(&self, group: &str, login: &str) -> bool {
+        self.groups.get(group).is_some_and(|members| members.iter().any(|m| m == login))
+    }
+}
+
+/// Checks the read rules for index fetches, downloads and owner lists of crates, and passes everything else to the wrapped backend
+///
+/// Crates the user can't read are reported as not found, so that their names don't leak.
+pub struct ReadAclAuth {
+    inner: Box<dyn AuthProvider + Send + Sync + 'static>,
+    config: Config,
+}
+
+impl ReadAclAuth {
+    #[must_use]
+    pub fn new(inner: Box<dyn AuthProvider + Send + Sync + 'static>, config: Config) -> Self {
+        Self { inner, config }
+    }
+
+    /// The token must have been checked by the backend already
+    async fn ensure_can_read(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        let Some(rule) = self.config.rule_for(crate_name) else {
+            return Ok(());
+        };
+        if rule.everyone {
+            return Ok(());
+        }
+        let login = self.inner.token_login(token).await?;
+        if rule.users.contains(&login) || rule.groups.iter().any(|g| self.config.in_group(g, &login)) {
+            return Ok(());
+        }
+        match self.inner.list_owners(token, crate_name).await {
+            Ok(owners) if owners.iter().any(|o| o.login == login) => Ok(()),
+            Ok(_) | Err(AuthError::CrateNotFound | AuthError::Forbidden) => {
+                tracing::debug!("{login} can't read {crate_name}");
+                Err(AuthError::CrateNotFound)
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for ReadAclAuth {
+    type Config = Config;
+
+    async fn healthcheck(&self) -> anyhow::Result<()> {
+        self.inner.healthcheck().await
+    }
+
+    async fn register(&self, username: &str) -> AuthResult<String> {
+        self.inner.register(username).await
+    }
+
+    fn register_supported(&self) -> Result<(), &'static str> {
+        self.inner.register_supported()
+    }
+
+    async fn list_owners(&self, token: &str, crate_name: &str) -> AuthResult<Vec<ListedOwner>> {
+        self.ensure_can_read(token, crate_name).await?;
+        self.inner.list_owners(token, crate_name).await
+    }
+
+    async fn add_owners(&self, token: &str, users: &[&str], crate_name: &str) -> AuthResult<()> {
+        self.inner.add_owners(token, users, crate_name).await
+    }
+
+    async fn remove_owners(&self, token: &str, users: &[&str], crate_name: &str) -> AuthResult<()> {
+        self.inner.remove_owners(token, users, crate_name).await
+    }
+
+    async fn token_login(&self, token: &str) -> AuthResult<String> {
+        self.inner.token_login(token).await
+    }
+
+    async fn create_token(&self, token: &str, new_token: &NewToken) -> AuthResult<CreatedToken> {
+        self.inner.create_token(token, new_token).await
+    }
+
+    async fn list_tokens(&self, token: &str) -> AuthResult<Vec<TokenInfo>> {
+        self.inner.list_tokens(token).await
+    }
+
+    async fn revoke_token(&self, token: &str, id: u32) -> AuthResult<()> {
+        self.inner.revoke_token(token, id).await
+    }
+
+    async fn register_public_key(&self, token: &str, public_key: &str) -> AuthResult<()> {
+        self.inner.register_public_key(token, public_key).await
+    }
+
+    async fn publish(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        self.inner.publish(token, crate_name).await
+    }
+
+    async fn auth_yank(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        self.inner.auth_yank(token, crate_name).await
+    }
+
+    async fn auth_index_fetch(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        self.inner.auth_index_fetch(token, crate_name).await?;
+        self.ensure_can_read(token, crate_name).await
+    }
+
+    async fn auth_crate_download(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        self.inner.auth_crate_download(token, crate_name).await?;
+        self.ensure_can_read(token, crate_name).await
+    }
+
+    async fn auth_view_full_index(&self, token: &str) -> AuthResult<()> {
+        self.inner.auth_view_full_index(token).await
+    }
+
+    async fn auth_config(&self, token: &str) -> AuthResult<()> {
+        self.inner.auth_config(token).await
+    }
+
+    async fn export_backup(&self) -> AuthResult<AuthBackup> {
+        self.inner.export_backup().await
+    }
+
+    async fn import_backup(&self, backup: &AuthBackup) -> AuthResult<()> {
+        self.inner.import_backup(backup).await
+    }
+
+    fn token_from_headers<'h>(&self, headers: &'h HeaderMap) -> Result<Option<&'h str>, StatusCode> {
+        self.inner.token_from_headers(headers)
+    }
+}
+
+#[cfg(all(test, feature = "fs-backend"))]
+mod tests {
+    use super::*;
+    use crate::fs_backend::{self, FsAuthProvider};
+
+    #[tokio::test]
+    async fn read_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let fs = FsAuthProvider::new(fs_backend::Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [1; 18] }).unwrap();
+        let owner = fs.register("owner").await.unwrap();
+        let partner = fs.register("partner").await.unwrap();
+        let other = fs.register("other").await.unwrap();
+        fs.publish(&owner, "partner-lib").await.unwrap();
+        fs.publish(&owner, "partner-public").await.unwrap();
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "groups": {"partners": ["partner"]},
+            "rules": [
+                {"crates": ["partner-public"], "everyone": true},
+                {"crates": ["partner-*"], "groups": ["partners"]},
+            ],
+        }))
+        .unwrap();
+        let auth = ReadAclAuth::new(Box::new(fs), config);
+
+        auth.auth_index_fetch(&owner, "partner-lib").await.unwrap();
+        auth.auth_crate_download(&partner, "partner-lib").await.unwrap();
+        assert!(matches!(auth.auth_index_fetch(&other, "partner-lib").await, Err(AuthError::CrateNotFound)));
+        assert!(matches!(auth.auth_crate_download(&other, "partner_lib").await, Err(AuthError::CrateNotFound)));
+        assert!(matches!(auth.auth_index_fetch(&other, "partner-unpublished").await, Err(AuthError::CrateNotFound)));
+        assert!(matches!(auth.auth_index_fetch("bad", "partner-lib").await, Err(AuthError::InvalidCredentials)));
+        auth.auth_index_fetch(&other, "partner-public").await.unwrap();
+        auth.auth_crate_download(&other, "serde").await.unwrap();
+
+        assert_eq!(auth.list_owners(&partner, "partner-lib").await.unwrap()[0].login, "owner");
+        assert!(matches!(auth.list_owners(&other, "partner-lib").await, Err(AuthError::CrateNotFound)));
+        auth.list_owners(&other, "partner-public").await.unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+//! Restrictions of tokens, like the scoped tokens of crates.io
+
+use freighter_api_types::crate_name::crate_name_matches;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Operation a scoped token may do. Tokens can always read the registry.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum EndpointScope {
+    /// Publish the first version of a crate
+    PublishNew,
+    /// Publish a new version of an existing crate
+    PublishUpdate,
+    /// Yank and unyank
+    Yank,
+    /// Add and remove owners
+    ChangeOwners,
+}
+
+/// What a token may do. Unset fields don't restrict the token.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenScopes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint_scopes: Option<BTreeSet<EndpointScope>>,
+    /// Crate names, where `*` matches any characters, e.g. `acme-*`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crate_scopes: Option<Vec<String>>,
+}
+
+impl TokenScopes {
+    /// Allows everything
+    #[must_use]
+    pub fn is_unrestricted(&self) -> bool {
+        self.endpoint_scopes.is_none() && self.crate_scopes.is_none()
+    }
+
+    #[must_use]
+    pub fn allows(&self, scope: EndpointScope, crate_name: &str) -> bool {
+        self.endpoint_scopes.as_ref().is_none_or(|s| s.contains(&scope))
+            && self.crate_scopes.as_ref().is_none_or(|patterns| {
+                patterns.iter().any(|p| crate_name_matches(p, crate_name))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_patterns() {
+        assert!(crate_name_matches("foo", "foo"));
+        assert!(crate_name_matches("foo_bar", "Foo-Bar"));
+        assert!(!crate_name_matches("foo", "foobar"));
+        assert!(crate_name_matches("foo*", "foo"));
+        assert!(crate_name_matches("foo-*", "foo_bar"));
+        assert!(crate_name_matches("*-macros", "foo-macros"));
+        assert!(crate_name_matches("*", "anything"));
+        assert!(!crate_name_matches("acme-*", "other-acme-lib"));
+    }
+
+    #[test]
+    fn scopes() {
+        assert!(TokenScopes::default().is_unrestricted());
+        assert!(TokenScopes::default().allows(EndpointScope::ChangeOwners, "foo"));
+
+        let scopes: TokenScopes = serde_json::from_str(r#"{"endpoint_scopes": ["publish-update", "yank"], "crate_scopes": ["acme-*"]}"#).unwrap();
+        assert!(!scopes.is_unrestricted());
+        assert!(scopes.allows(EndpointScope::PublishUpdate, "acme-lib"));
+        assert!(scopes.allows(EndpointScope::Yank, "acme-lib"));
+        assert!(!scopes.allows(EndpointScope::PublishNew, "acme-lib"));
+        assert!(!scopes.allows(EndpointScope::Yank, "other"));
+
+        let any_crate = TokenScopes { endpoint_scopes: Some([EndpointScope::PublishNew].into()), crate_scopes: None };
+        assert!(any_crate.allows(EndpointScope::PublishNew, "other"));
+    }
+}
@@ -0,0 +1,42 @@
+//! Extra tokens of users, in the format of the crates.io API
+
+use crate::scopes::TokenScopes;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Request for another token of the user
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NewToken {
+    pub name: String,
+    /// The token is rejected after this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expired_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub scopes: TokenScopes,
+}
+
+impl NewToken {
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expired_at.is_some_and(|t| t <= Utc::now())
+    }
+}
+
+/// What users can see about their tokens, without the token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenInfo {
+    /// For revoking the token
+    pub id: u32,
+    /// Not known for tokens from before they had names
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub token: NewToken,
+}
+
+/// Shown only once, when the token is created
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    pub info: TokenInfo,
+    pub token: String,
+}
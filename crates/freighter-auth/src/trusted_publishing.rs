@@ -0,0 +1,460 @@
+use crate::jwks::{Jwks, KeySource};
+use crate::tokens::{CreatedToken, NewToken, TokenInfo};
+use crate::{AuthBackup, AuthError, AuthProvider, AuthResult};
+use async_trait::async_trait;
+use freighter_api_types::crate_name::canonical_crate_name;
+use freighter_api_types::ownership::response::ListedOwner;
+use http::{HeaderMap, StatusCode};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Prefix of the short-lived publish tokens, which are JWTs signed by Freighter
+const TOKEN_PREFIX: &str = "frtp_";
+const TOKEN_ISSUER: &str = "freighter-trusted-publishing";
+
+/// Lets CI jobs publish crates without long-lived tokens.
+///
+/// A job exchanges an OIDC ID token from its CI provider for a short-lived token, which can only publish the crates
+/// whose trust policies match the claims of the ID token.
+#[derive(Deserialize, Clone)]
+pub struct Config {
+    /// `aud` that the ID tokens must have, e.g. the URL of the registry
+    pub audience: String,
+    /// How long the publish tokens are valid
+    #[serde(default = "default_token_ttl_secs")]
+    pub token_ttl_secs: u64,
+    /// Key for signing the publish tokens. Must be the same for all instances of Freighter behind a load balancer.
+    /// A random key is used if not set.
+    #[serde(default)]
+    pub signing_key: Option<String>,
+    /// Issuers that don't publish their keys at `/.well-known/openid-configuration`,
+    /// or use other algorithms than RS256
+    #[serde(default)]
+    pub issuers: Vec<IssuerConfig>,
+    pub policies: Vec<TrustPolicy>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct IssuerConfig {
+    /// `iss` of the ID tokens
+    pub url: String,
+    /// URL of the keys, or `file://` path of a local JWKS file
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    #[serde(default = "default_algorithms")]
+    pub algorithms: Vec<Algorithm>,
+}
+
+/// ID tokens matching all of the fields can publish the crate
+#[derive(Deserialize, Clone)]
+pub struct TrustPolicy {
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    /// `iss` of the ID token, e.g. `https://token.actions.githubusercontent.com`
+    pub issuer: String,
+    /// `repository` claim, like `owner/repo`
+    pub repository: String,
+    /// File name of the workflow, from the `workflow_ref` claim. Any workflow if not set.
+    #[serde(default)]
+    pub workflow: Option<String>,
+    /// `ref` claim, like `refs/heads/main`. Any ref if not set.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+}
+
+fn default_token_ttl_secs() -> u64 {
+    30 * 60
+}
+
+fn default_algorithms() -> Vec<Algorithm> {
+    vec![Algorithm::RS256]
+}
+
+/// Claims of ID tokens used by the policies
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    #[serde(default)]
+    repository: Option<String>,
+    #[serde(default)]
+    workflow_ref: Option<String>,
+    #[serde(default, rename = "ref")]
+    git_ref: Option<String>,
+}
+
+impl IdTokenClaims {
+    /// `owner/repo/.github/workflows/release.yml@refs/heads/main` → `release.yml`
+    fn workflow(&self) -> Option<&str> {
+        let path = self.workflow_ref.as_deref()?.split('@').next()?;
+        path.rsplit('/').next()
+    }
+}
+
+impl TrustPolicy {
+    fn matches(&self, claims: &IdTokenClaims) -> bool {
+        self.issuer == claims.iss
+            && claims.repository.as_deref() == Some(self.repository.as_str())
+            && self.workflow.as_deref().is_none_or(|w| claims.workflow() == Some(w))
+            && self.git_ref.as_ref().is_none_or(|r| claims.git_ref.as_ref() == Some(r))
+    }
+}
+
+/// Claims of the publish tokens
+#[derive(Serialize, Deserialize)]
+struct PublishClaims {
+    iss: String,
+    /// The repository of the CI job
+    sub: String,
+    exp: u64,
+    crates: Vec<String>,
+}
+
+pub struct TrustedPublishing {
+    policies: Vec<TrustPolicy>,
+    issuers: HashMap<String, Jwks>,
+    token_ttl_secs: u64,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    validation: Validation,
+}
+
+impl TrustedPublishing {
+    #[must_use]
+    pub fn new(config: Config) -> Self {
+        let mut issuers = HashMap::new();
+        let configured = config.issuers.into_iter().map(|i| (i.url.clone(), i.jwks_url, i.algorithms));
+        let discovered = config.policies.iter().map(|p| (p.issuer.clone(), None, default_algorithms()));
+        for (url, jwks_url, algorithms) in configured.chain(discovered) {
+            issuers.entry(url.clone()).or_insert_with(|| {
+                let mut validation = Validation::new(algorithms.first().copied().unwrap_or(Algorithm::RS256));
+                validation.algorithms = algorithms;
+                validation.set_audience(&[&config.audience]);
+                validation.set_issuer(&[&url]);
+                let source = match jwks_url {
+                    Some(jwks_url) => KeySource::Jwks(jwks_url),
+                    None => KeySource::Issuer(url),
+                };
+                Jwks::new(source, validation).lenient()
+            });
+        }
+
+        let key = config.signing_key.map_or_else(
+            || {
+                let mut key = vec![0; 32];
+                rand::rng().fill(&mut key[..]);
+                key
+            },
+            String::into_bytes,
+        );
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[TOKEN_ISSUER]);
+
+        Self {
+            policies: config.policies,
+            issuers,
+            token_ttl_secs: config.token_ttl_secs,
+            encoding_key: EncodingKey::from_secret(&key),
+            decoding_key: DecodingKey::from_secret(&key),
+            validation,
+        }
+    }
+
+    /// Returns a publish token for the crates whose policies match the ID token
+    pub async fn exchange(&self, id_token: &str) -> AuthResult<String> {
+        let iss = jsonwebtoken::dangerous::insecure_decode::<IdTokenClaims>(id_token)
+            .map_err(|_| AuthError::InvalidCredentials)?
+            .claims
+            .iss;
+        let Some(jwks) = self.issuers.get(&iss) else {
+            tracing::warn!("ID token from an unknown issuer: {iss}");
+            return Err(AuthError::Unauthorized);
+        };
+        let claims: IdTokenClaims = jwks.verify(id_token).await?;
+
+        let crates: Vec<_> = self
+            .policies
+            .iter()
+            .filter(|p| p.matches(&claims))
+            .map(|p| p.crate_name.clone())
+            .collect();
+        let Some(repository) = claims.repository.filter(|_| !crates.is_empty()) else {
+            return Err(AuthError::Forbidden);
+        };
+
+        let claims = PublishClaims {
+            iss: TOKEN_ISSUER.into(),
+            sub: repository,
+            exp: jsonwebtoken::get_current_timestamp() + self.token_ttl_secs,
+            crates,
+        };
+        let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &self.encoding_key)
+            .map_err(|e| AuthError::ServiceError(e.into()))?;
+        Ok(format!("{TOKEN_PREFIX}{token}"))
+    }
+
+    /// `None` if it's not a publish token
+    fn verify(&self, token: &str) -> Option<AuthResult<PublishClaims>> {
+        let token = token.strip_prefix(TOKEN_PREFIX)?;
+        Some(
+            jsonwebtoken::decode(token, &self.decoding_key, &self.validation)
+                .map(|t| t.claims)
+                .map_err(|e| {
+                    tracing::warn!("invalid publish token: {e}");
+                    AuthError::Unauthorized
+                }),
+        )
+    }
+}
+
+/// Accepts publish tokens from [`TrustedPublishing`], and passes other tokens to the wrapped backend
+///
+/// Publish tokens can read the registry and publish the crates they're for, but nothing else.
+pub struct TrustedPublishingAuth {
+    inner: Box<dyn AuthProvider + Send + Sync + 'static>,
+    trusted: Arc<TrustedPublishing>,
+}
+
+impl TrustedPublishingAuth {
+    #[must_use]
+    pub fn new(inner: Box<dyn AuthProvider + Send + Sync + 'static>, trusted: Arc<TrustedPublishing>) -> Self {
+        Self { inner, trusted }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for TrustedPublishingAuth {
+    type Config = Config;
+
+    async fn healthcheck(&self) -> anyhow::Result<()> {
+        self.inner.healthcheck().await
+    }
+
+    async fn register(&self, username: &str) -> AuthResult<String> {
+        self.inner.register(username).await
+    }
+
+    fn register_supported(&self) -> Result<(), &'static str> {
+        self.inner.register_supported()
+    }
+
+    async fn list_owners(&self, token: &str, crate_name: &str) -> AuthResult<Vec<ListedOwner>> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.and(Err(AuthError::Forbidden)),
+            None => self.inner.list_owners(token, crate_name).await,
+        }
+    }
+
+    async fn add_owners(&self, token: &str, users: &[&str], crate_name: &str) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.and(Err(AuthError::Forbidden)),
+            None => self.inner.add_owners(token, users, crate_name).await,
+        }
+    }
+
+    async fn remove_owners(&self, token: &str, users: &[&str], crate_name: &str) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.and(Err(AuthError::Forbidden)),
+            None => self.inner.remove_owners(token, users, crate_name).await,
+        }
+    }
+
+    async fn token_login(&self, token: &str) -> AuthResult<String> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.map(|c| c.sub),
+            None => self.inner.token_login(token).await,
+        }
+    }
+
+    async fn create_token(&self, token: &str, new_token: &NewToken) -> AuthResult<CreatedToken> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.and(Err(AuthError::Forbidden)),
+            None => self.inner.create_token(token, new_token).await,
+        }
+    }
+
+    async fn list_tokens(&self, token: &str) -> AuthResult<Vec<TokenInfo>> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.and(Err(AuthError::Forbidden)),
+            None => self.inner.list_tokens(token).await,
+        }
+    }
+
+    async fn revoke_token(&self, token: &str, id: u32) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.and(Err(AuthError::Forbidden)),
+            None => self.inner.revoke_token(token, id).await,
+        }
+    }
+
+    async fn register_public_key(&self, token: &str, public_key: &str) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.and(Err(AuthError::Forbidden)),
+            None => self.inner.register_public_key(token, public_key).await,
+        }
+    }
+
+    async fn publish(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => {
+                if !claims?.crates.iter().any(|c| canonical_crate_name(c) == canonical_crate_name(crate_name)) {
+                    return Err(AuthError::Forbidden);
+                }
+                // backends give crates without owners to whoever publishes them next,
+                // so the first version has to be published by a user
+                match self.inner.list_owners(token, crate_name).await {
+                    Ok(owners) if !owners.is_empty() => Ok(()),
+                    Ok(_) | Err(AuthError::CrateNotFound) => {
+                        tracing::warn!("{crate_name} has no owners, and can't be published with a trusted publishing token");
+                        Err(AuthError::Forbidden)
+                    },
+                    Err(e) => Err(e),
+                }
+            }
+            None => self.inner.publish(token, crate_name).await,
+        }
+    }
+
+    async fn auth_yank(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.and(Err(AuthError::Forbidden)),
+            None => self.inner.auth_yank(token, crate_name).await,
+        }
+    }
+
+    async fn auth_index_fetch(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.map(drop),
+            None => self.inner.auth_index_fetch(token, crate_name).await,
+        }
+    }
+
+    async fn auth_crate_download(&self, token: &str, crate_name: &str) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.map(drop),
+            None => self.inner.auth_crate_download(token, crate_name).await,
+        }
+    }
+
+    async fn auth_view_full_index(&self, token: &str) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.map(drop),
+            None => self.inner.auth_view_full_index(token).await,
+        }
+    }
+
+    async fn auth_config(&self, token: &str) -> AuthResult<()> {
+        match self.trusted.verify(token) {
+            Some(claims) => claims.map(drop),
+            None => self.inner.auth_config(token).await,
+        }
+    }
+
+    async fn export_backup(&self) -> AuthResult<AuthBackup> {
+        self.inner.export_backup().await
+    }
+
+    async fn import_backup(&self, backup: &AuthBackup) -> AuthResult<()> {
+        self.inner.import_backup(backup).await
+    }
+
+    fn token_from_headers<'h>(&self, headers: &'h HeaderMap) -> Result<Option<&'h str>, StatusCode> {
+        self.inner.token_from_headers(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"very secret key for tests";
+    const ISSUER: &str = "https://ci.example.com";
+
+    fn trusted_publishing(dir: &tempfile::TempDir) -> TrustedPublishing {
+        let path = dir.path().join("jwks.json");
+        let jwks = json!({"keys": [{"kty": "oct", "kid": "ci", "k": "dmVyeSBzZWNyZXQga2V5IGZvciB0ZXN0cw"}]});
+        std::fs::write(&path, jwks.to_string()).unwrap();
+
+        TrustedPublishing::new(Config {
+            audience: "https://registry.example.com".into(),
+            token_ttl_secs: 60,
+            signing_key: None,
+            issuers: vec![IssuerConfig {
+                url: ISSUER.into(),
+                jwks_url: Some(format!("file://{}", path.display())),
+                algorithms: vec![Algorithm::HS256],
+            }],
+            policies: vec![TrustPolicy {
+                crate_name: "example-lib".into(),
+                issuer: ISSUER.into(),
+                repository: "acme/example".into(),
+                workflow: Some("release.yml".into()),
+                git_ref: Some("refs/heads/main".into()),
+            }],
+        })
+    }
+
+    fn id_token(repository: &str, git_ref: &str) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("ci".into());
+        let claims = json!({
+            "iss": ISSUER,
+            "aud": "https://registry.example.com",
+            "exp": jsonwebtoken::get_current_timestamp() + 60,
+            "repository": repository,
+            "workflow_ref": format!("{repository}/.github/workflows/release.yml@{git_ref}"),
+            "ref": git_ref,
+        });
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    #[cfg(feature = "fs-backend")]
+    #[tokio::test]
+    async fn exchange_and_publish() {
+        use crate::fs_backend::{self, FsAuthProvider};
+
+        let dir = tempfile::tempdir().unwrap();
+        let fs = FsAuthProvider::new(fs_backend::Config { auth_path: dir.path().to_path_buf(), auth_tokens_pepper: [1; 18] }).unwrap();
+        let alice = fs.register("alice").await.unwrap();
+        let bob = fs.register("bob").await.unwrap();
+        let trusted = Arc::new(trusted_publishing(&dir));
+        let auth = TrustedPublishingAuth::new(Box::new(fs), trusted.clone());
+
+        let token = trusted.exchange(&id_token("acme/example", "refs/heads/main")).await.unwrap();
+        // the crate has no owners yet
+        assert!(matches!(auth.publish(&token, "example-lib").await, Err(AuthError::Forbidden)));
+        auth.publish(&alice, "example-lib").await.unwrap();
+
+        auth.publish(&token, "example-lib").await.unwrap();
+        auth.auth_crate_download(&token, "other-lib").await.unwrap();
+        assert_eq!(auth.token_login(&token).await.unwrap(), "acme/example");
+        assert!(matches!(auth.publish(&bob, "example-lib").await, Err(AuthError::Forbidden)));
+
+        assert!(matches!(auth.publish(&token, "other-lib").await, Err(AuthError::Forbidden)));
+        assert!(matches!(auth.auth_yank(&token, "example-lib").await, Err(AuthError::Forbidden)));
+
+        let mut forged = token.clone();
+        forged.pop();
+        assert!(matches!(auth.publish(&forged, "example-lib").await, Err(AuthError::Unauthorized)));
+    }
+
+    #[tokio::test]
+    async fn untrusted_id_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let trusted = trusted_publishing(&dir);
+
+        let other_repository = id_token("mallory/example", "refs/heads/main");
+        assert!(matches!(trusted.exchange(&other_repository).await, Err(AuthError::Forbidden)));
+        let other_ref = id_token("acme/example", "refs/heads/dev");
+        assert!(matches!(trusted.exchange(&other_ref).await, Err(AuthError::Forbidden)));
+        assert!(trusted.exchange("not a jwt").await.is_err());
+
+        // tokens from other instances are rejected
+        let other = trusted_publishing(&dir);
+        let token = other.exchange(&id_token("acme/example", "refs/heads/main")).await.unwrap();
+        assert!(matches!(trusted.verify(&token), Some(Err(AuthError::Unauthorized))));
+    }
+}
@@ -66,11 +66,11 @@ impl Client {
         let mut resp = client.get(&config_url).send().await?;
 
         if resp.status() == StatusCode::UNAUTHORIZED {
-            let token = token.as_ref().expect("registry requires auth, no token given");
+            let token = token.as_ref().ok_or(Error::Unauthorized)?;
             auth_required = true;
             resp = client.get(&config_url)
                 .header(AUTHORIZATION, HeaderValue::from_str(token).map_err(anyhow::Error::from)?)
-                .send().await.unwrap();
+                .send().await?;
         }
 
         if resp.status() != StatusCode::OK {
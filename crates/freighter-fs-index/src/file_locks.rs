@@ -8,7 +8,7 @@ use std::sync::{Arc, Mutex, Weak};
 use tokio::sync::RwLock as AsyncRwLock;
 use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
 
-/// Lowercase crate name -> lock for file access.
+/// Canonical crate name -> lock for file access.
 ///
 /// It holds a path to the json-lines metadata file to emphasise only locked access is allowed.
 /// flock is not used here, because the directory is meant to be handled exclusively by
@@ -127,6 +127,15 @@ impl LockedMetaFile<'_, RwLockWriteGuard<'_, String>> {
         }, publish))
     }
 
+    pub fn rel_path(&self) -> &str {
+        &self.rel_path
+    }
+
+    /// For new crates, which may have been locked using a different spelling of their name
+    pub fn set_rel_path(&mut self, rel_path: String) {
+        *self.rel_path = rel_path;
+    }
+
     pub async fn replace(
         &self,
         data: &[CrateVersion],
@@ -1,26 +1,30 @@
 #![allow(clippy::type_complexity)]
 use anyhow::Context;
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Days, NaiveDate, Utc};
 use freighter_api_types::index::request::{ListQuery, Publish};
 use freighter_api_types::index::response::{
-    CompletedPublication, CrateVersion, Dependency, ListAll, ListAllCrateEntry,
-    ListAllCrateVersion, SearchResults, SearchResultsEntry, SearchResultsMeta,
+    CompletedPublication, CrateDetails, CrateDetailsEntry, CrateDetailsVersion, CrateDownloads,
+    CrateVersion, Dependency, ListAll, ListAllCrateEntry, ListAllCrateVersion, ReverseDependencies,
+    ReverseDependency, SearchResults, SearchResultsEntry, SearchResultsMeta, VersionDetails, VersionDetailsEntry, VersionDownloads,
+    VersionPublisher,
 };
 use freighter_api_types::index::{
-    CrateVersionExists, IndexError, IndexProvider, IndexResult, SparseEntries,
+    canonical_crate_name, CrateVersionExists, DownloadCount, IndexError, IndexProvider,
+    IndexResult, PublishContext, SparseEntries, DOWNLOADS_HISTORY_DAYS,
 };
-use freighter_api_types::storage::MetadataStorageProvider;
+use freighter_api_types::storage::{Metadata, MetadataStorageProvider, StorageError};
 use freighter_storage::fs::FsStorageProvider;
 use freighter_storage::s3_client::S3StorageProvider;
 use semver::Version;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::cmp::Reverse;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
 use tokio::task::JoinSet;
 
 mod file_locks;
@@ -31,6 +35,33 @@ use crate::file_locks::deserialize_data;
 pub struct FsIndexProvider {
     meta_file_locks: AccessLocks<String>,
     fs: Arc<dyn MetadataStorageProvider + Send + Sync>,
+    /// Canonical crate name -> lowercase name of its index file.
+    ///
+    /// Loaded from the index on first use, since the index directory is exclusive to this instance.
+    stored_names: OnceCell<Mutex<HashMap<String, String>>>,
+    /// Held while updating download count files
+    downloads_lock: tokio::sync::Mutex<()>,
+}
+
+/// Version -> date -> number of downloads. Stored next to the index, in `downloads/`.
+type DownloadsFile = BTreeMap<Version, BTreeMap<NaiveDate, u64>>;
+
+/// Metadata of every published version. Stored next to the index, in `versions/`.
+///
+/// The index file only keeps the `Publish` of the latest version.
+type VersionsFile = BTreeMap<Version, VersionMetadata>;
+
+#[derive(Serialize, Deserialize)]
+struct VersionMetadata {
+    description: Option<String>,
+    license: Option<String>,
+    license_file: Option<String>,
+    homepage: Option<String>,
+    documentation: Option<String>,
+    repository: Option<String>,
+    created_at: DateTime<Utc>,
+    published_by: Option<String>,
+    crate_size: u64,
 }
 
 impl FsIndexProvider {
@@ -57,24 +88,72 @@ impl FsIndexProvider {
         Ok(Self {
             fs,
             meta_file_locks: AccessLocks::new(),
+            stored_names: OnceCell::new(),
+            downloads_lock: tokio::sync::Mutex::new(()),
         })
     }
 
-    pub(crate) fn access_crate(&self, crate_name: &str) -> IndexResult<CrateMetaPath<'_>> {
-        let lowercase_name = crate_name.to_ascii_lowercase();
-        let meta_file_rel_path = self
-            .crate_meta_file_rel_path(&lowercase_name)
-            .ok_or(IndexError::CrateNameNotAllowed)?;
+    /// Crates are locked by their canonical name, so all spellings of a name share the index file
+    /// of the crate that has been published first.
+    pub(crate) async fn access_crate(&self, crate_name: &str) -> IndexResult<CrateMetaPath<'_>> {
+        let (canonical_name, meta_file_rel_path) = self.stored_rel_path(crate_name).await?;
         Ok(CrateMetaPath::new(
             &*self.fs,
             &self.meta_file_locks,
-            lowercase_name,
+            canonical_name,
             meta_file_rel_path,
         ))
     }
 
+    /// Canonical name and path of the index file of the crate
+    async fn stored_rel_path(&self, crate_name: &str) -> IndexResult<(String, String)> {
+        let lowercase_name = crate_name.to_ascii_lowercase();
+        if self.crate_meta_file_rel_path(&lowercase_name).is_none() {
+            return Err(invalid_crate_name());
+        }
+
+        let canonical_name = canonical_crate_name(&lowercase_name);
+        let stored_name = self
+            .stored_names()
+            .await?
+            .lock()
+            .unwrap()
+            .get(&canonical_name)
+            .cloned()
+            .unwrap_or(lowercase_name);
+
+        let meta_file_rel_path = self
+            .crate_meta_file_rel_path(&stored_name)
+            .ok_or_else(invalid_crate_name)?;
+        Ok((canonical_name, meta_file_rel_path))
+    }
+
+    async fn stored_names(&self) -> IndexResult<&Mutex<HashMap<String, String>>> {
+        self.stored_names
+            .get_or_try_init(|| async {
+                let index_keys = match self.fs.list_prefix("index/").await {
+                    Ok(keys) => keys,
+                    Err(StorageError::NotFound) => vec![],
+                    Err(e) => return Err(IndexError::from(e)),
+                };
+
+                let names = index_keys
+                    .iter()
+                    .filter_map(|key| {
+                        let name = key.rsplit('/').next()?;
+                        // skips directories
+                        let rel_path = self.crate_meta_file_rel_path(name)?;
+                        key.ends_with(&rel_path)
+                            .then(|| (canonical_crate_name(name), name.to_owned()))
+                    })
+                    .collect();
+                Ok(Mutex::new(names))
+            })
+            .await
+    }
+
     async fn yank_inner(&self, crate_name: &str, version: &Version, yank: bool) -> IndexResult<()> {
-        let lock = self.access_crate(crate_name)?;
+        let lock = self.access_crate(crate_name).await?;
         let meta = lock.exclusive().await;
 
         let (mut releases, publish) = meta.deserialized().await?;
@@ -86,6 +165,39 @@ impl FsIndexProvider {
         meta.replace(&releases.entries, publish.as_ref()).await
     }
 
+    async fn add_downloads(&self, crate_name: &str, counts: &[&DownloadCount]) -> IndexResult<()> {
+        let (_, rel_path) = self.stored_rel_path(crate_name).await?;
+        let (versions, _) = self
+            .access_crate(crate_name)
+            .await?
+            .shared()
+            .await
+            .deserialized()
+            .await?;
+
+        let _lock = self.downloads_lock.lock().await;
+        let downloads_path = downloads_file_rel_path(&rel_path);
+        let mut downloads = pull_downloads_file(&*self.fs, &downloads_path).await?;
+        for count in counts {
+            if versions.entries.iter().any(|v| v.vers == count.version) {
+                *downloads
+                    .entry(count.version.clone())
+                    .or_default()
+                    .entry(count.date)
+                    .or_default() += count.downloads;
+            }
+        }
+
+        let bytes = serde_json::to_vec(&downloads).context("serializing download counts")?;
+        let meta = Metadata {
+            content_type: Some("application/json"),
+            content_length: Some(bytes.len()),
+            ..Metadata::default()
+        };
+        self.fs.put_file(&downloads_path, bytes.into(), meta).await?;
+        Ok(())
+    }
+
     const fn is_valid_crate_file_name_char(c: u8) -> bool {
         (c.is_ascii_alphabetic() && c.is_ascii_lowercase())
             || c.is_ascii_digit()
@@ -157,7 +269,7 @@ impl IndexProvider for FsIndexProvider {
     }
 
     async fn get_sparse_entry(&self, crate_name: &str) -> IndexResult<SparseEntries> {
-        self.access_crate(crate_name)?
+        self.access_crate(crate_name).await?
             .shared()
             .await
             .deserialized()
@@ -165,13 +277,132 @@ impl IndexProvider for FsIndexProvider {
             .map(|(versions, _)| versions)
     }
 
+    async fn get_crate_details(&self, crate_name: &str) -> IndexResult<CrateDetails> {
+        let (_, rel_path) = self.stored_rel_path(crate_name).await?;
+        let (entries, publish) = self
+            .access_crate(crate_name)
+            .await?
+            .shared()
+            .await
+            .deserialized()
+            .await?;
+        let downloads = pull_downloads_file(&*self.fs, &downloads_file_rel_path(&rel_path)).await?;
+        let versions_metadata = pull_versions_file(&*self.fs, &versions_file_rel_path(&rel_path)).await?;
+
+        // versions published before the versions file existed only have the index file's mtime
+        let last_modified = entries.last_modified.unwrap_or_else(Utc::now);
+        let published_at = entries.entries.iter().map(|v| {
+            versions_metadata.get(&v.vers).map_or(last_modified, |m| m.created_at)
+        });
+        let created_at = published_at.clone().min().unwrap_or(last_modified);
+        let updated_at = published_at.max().unwrap_or(last_modified);
+
+        let mut versions: Vec<_> = entries
+            .entries
+            .iter()
+            .map(|v| CrateDetailsVersion {
+                num: v.vers.clone(),
+                yanked: v.yanked,
+                downloads: downloads.get(&v.vers).map_or(0, |days| days.values().sum()),
+            })
+            .collect();
+        versions.sort_unstable_by(|a, b| b.num.cmp(&a.num));
+        let max_version = CrateDetailsVersion::max_version(&versions).ok_or(IndexError::NotFound)?;
+
+        let name = entries.entries[0].name.clone();
+        let publish = publish.unwrap_or_else(Publish::empty);
+
+        Ok(CrateDetails {
+            krate: CrateDetailsEntry {
+                id: name.clone(),
+                name,
+                description: publish.description,
+                homepage: publish.homepage,
+                documentation: publish.documentation,
+                repository: publish.repository,
+                keywords: publish.keywords,
+                categories: publish.categories,
+                created_at,
+                updated_at,
+                max_version,
+                downloads: versions.iter().map(|v| v.downloads).sum(),
+            },
+            versions,
+        })
+    }
+
+    async fn get_version_details(
+        &self,
+        crate_name: &str,
+        version: &Version,
+    ) -> IndexResult<VersionDetails> {
+        let (_, rel_path) = self.stored_rel_path(crate_name).await?;
+        let (mut entries, publish) = self
+            .access_crate(crate_name)
+            .await?
+            .shared()
+            .await
+            .deserialized()
+            .await?;
+        let entry_index = entries
+            .entries
+            .iter()
+            .rposition(|e| &e.vers == version)
+            .ok_or(IndexError::NotFound)?;
+        let entry = entries.entries.swap_remove(entry_index);
+
+        let downloads = pull_downloads_file(&*self.fs, &downloads_file_rel_path(&rel_path)).await?;
+        let mut versions_metadata = pull_versions_file(&*self.fs, &versions_file_rel_path(&rel_path)).await?;
+
+        let mut details = VersionDetailsEntry {
+            krate: entry.name,
+            num: entry.vers,
+            checksum: entry.cksum,
+            yanked: entry.yanked,
+            features: entry.features,
+            dependencies: entry.deps,
+            links: entry.links,
+            description: None,
+            license: None,
+            license_file: None,
+            homepage: None,
+            documentation: None,
+            repository: None,
+            created_at: None,
+            published_by: None,
+            crate_size: None,
+            downloads: downloads.get(version).map_or(0, |days| days.values().sum()),
+        };
+        if let Some(metadata) = versions_metadata.remove(version) {
+            details.description = metadata.description;
+            details.license = metadata.license;
+            details.license_file = metadata.license_file;
+            details.homepage = metadata.homepage;
+            details.documentation = metadata.documentation;
+            details.repository = metadata.repository;
+            details.created_at = Some(metadata.created_at);
+            details.published_by = metadata.published_by.map(|login| VersionPublisher { login });
+            details.crate_size = Some(metadata.crate_size);
+        } else if let Some(publish) = publish.filter(|p| &p.vers == version) {
+            // published before the versions file existed
+            details.description = publish.description;
+            details.license = publish.license;
+            details.license_file = publish.license_file;
+            details.homepage = publish.homepage;
+            details.documentation = publish.documentation;
+            details.repository = publish.repository;
+        }
+
+        Ok(VersionDetails { version: details })
+    }
+
     async fn confirm_existence(
         &self,
         crate_name: &str,
         version: &Version,
     ) -> IndexResult<CrateVersionExists> {
         let (versions, _) = self
-            .access_crate(crate_name)?
+            .access_crate(crate_name).await?
             .shared()
             .await
             .deserialized()
@@ -200,6 +431,7 @@ impl IndexProvider for FsIndexProvider {
         &self,
         publish: &Publish,
         tarball_checksum: [u8; 32],
+        context: &PublishContext,
         end_step: Pin<&mut (dyn Future<Output = IndexResult<()>> + Send)>,
     ) -> IndexResult<CompletedPublication> {
         let release = CrateVersion {
@@ -228,18 +460,27 @@ impl IndexProvider for FsIndexProvider {
                 })
                 .collect(),
             cksum: tarball_checksum,
-            features: publish.features.clone(),
+            features: publish.features.clone().into_iter().collect(),
             yanked: false,
             links: publish.links.clone(),
             v: 2,
-            features2: HashMap::default(),
+            features2: BTreeMap::default(),
         };
 
-        let lock = self.access_crate(&release.name)?;
-        let meta = lock.exclusive().await;
+        let lock = self.access_crate(&release.name).await?;
+        let mut meta = lock.exclusive().await;
 
         let mut versions = match meta.deserialized().await {
             Ok((existing_releases, _)) => {
+                if let Some(first) = existing_releases.entries.first()
+                    && first.name != release.name
+                {
+                    return Err(IndexError::Conflict(format!(
+                        "crate was previously named `{}`",
+                        first.name
+                    )));
+                }
+
                 if existing_releases.entries.iter().any(|v| v.vers == release.vers) {
                     return Err(IndexError::Conflict(format!(
                         "{}-{} aleady exists",
@@ -252,11 +493,49 @@ impl IndexProvider for FsIndexProvider {
             Err(IndexError::NotFound) => vec![],
             Err(other) => return Err(other),
         };
+        let is_new_crate = versions.is_empty();
+        let lowercase_name = publish.name.to_ascii_lowercase();
+        if is_new_crate {
+            meta.set_rel_path(
+                self.crate_meta_file_rel_path(&lowercase_name)
+                    .ok_or_else(invalid_crate_name)?,
+            );
+        }
         versions.push(release);
 
+        // written while the index file is locked, so concurrent publishes don't lose versions
+        let versions_path = versions_file_rel_path(meta.rel_path());
+        let mut versions_metadata = pull_versions_file(&*self.fs, &versions_path).await?;
+        versions_metadata.insert(publish.vers.clone(), VersionMetadata {
+            description: publish.description.clone(),
+            license: publish.license.clone(),
+            license_file: publish.license_file.clone(),
+            homepage: publish.homepage.clone(),
+            documentation: publish.documentation.clone(),
+            repository: publish.repository.clone(),
+            created_at: Utc::now(),
+            published_by: context.publisher.clone(),
+            crate_size: context.tarball_size,
+        });
+        let versions_bytes = serde_json::to_vec(&versions_metadata).context("serializing versions file")?;
+
         end_step.await?;
 
+        let versions_file_meta = Metadata {
+            content_type: Some("application/json"),
+            content_length: Some(versions_bytes.len()),
+            ..Metadata::default()
+        };
+        self.fs.put_file(&versions_path, versions_bytes.into(), versions_file_meta).await?;
         meta.put_index_file(&versions, publish).await?;
+
+        if is_new_crate {
+            self.stored_names()
+                .await?
+                .lock()
+                .unwrap()
+                .insert(canonical_crate_name(&lowercase_name), lowercase_name);
+        }
         Ok(CompletedPublication { warnings: None })
     }
 
@@ -271,8 +550,8 @@ impl IndexProvider for FsIndexProvider {
             let publish_fetch_result = handle.context("index fetch task unexpectedly failed")?;
 
             match publish_fetch_result {
-                Ok((versions, publish)) => {
-                    let entry = convert_publish_to_crate_entry(versions, publish);
+                Ok((versions, publish, downloads)) => {
+                    let entry = convert_publish_to_crate_entry(versions, publish, downloads);
                     results.push(entry);
                 }
                 Err(error) => {
@@ -284,6 +563,93 @@ impl IndexProvider for FsIndexProvider {
         Ok(ListAll { results })
     }
 
+    async fn record_downloads(&self, downloads: &[DownloadCount]) -> IndexResult<()> {
+        let mut by_crate = BTreeMap::<_, Vec<_>>::new();
+        for count in downloads {
+            by_crate.entry(canonical_crate_name(&count.name)).or_default().push(count);
+        }
+
+        for counts in by_crate.values() {
+            match self.add_downloads(&counts[0].name, counts).await {
+                Ok(()) | Err(IndexError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_downloads(&self, crate_name: &str) -> IndexResult<CrateDownloads> {
+        let (_, rel_path) = self.stored_rel_path(crate_name).await?;
+        // checks that the crate exists
+        self.access_crate(crate_name).await?.shared().await.deserialized().await?;
+
+        let since = Utc::now().date_naive() - Days::new(DOWNLOADS_HISTORY_DAYS);
+        let downloads = pull_downloads_file(&*self.fs, &downloads_file_rel_path(&rel_path)).await?;
+        let mut version_downloads: Vec<_> = downloads
+            .into_iter()
+            .flat_map(|(version, days)| {
+                days.into_iter()
+                    .filter(|&(date, _)| date > since)
+                    .map(move |(date, downloads)| VersionDownloads {
+                        version: version.clone(),
+                        downloads,
+                        date,
+                    })
+            })
+            .collect();
+        version_downloads.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.version.cmp(&b.version)));
+
+        Ok(CrateDownloads { version_downloads })
+    }
+
+    async fn get_reverse_dependencies(&self, crate_name: &str) -> IndexResult<ReverseDependencies> {
+        // checks that the crate exists
+        self.access_crate(crate_name).await?.shared().await.deserialized().await?;
+        let canonical_name = canonical_crate_name(crate_name);
+
+        // there's no reverse index, so all crates are scanned
+        let mut index_files = JoinSet::new();
+        for index_key in self.fs.list_prefix("index/").await? {
+            let fs = Arc::clone(&self.fs);
+            index_files.spawn(async move {
+                let res = fs.pull_file(&index_key).await?;
+                let (entries, _) = deserialize_data(&res.data)?;
+                IndexResult::Ok(entries)
+            });
+        }
+
+        let mut dependencies = Vec::new();
+        while let Some(handle) = index_files.join_next().await {
+            let entries = handle.context("index fetch task unexpectedly failed")??;
+            for entry in entries {
+                dependencies.extend(
+                    entry
+                        .deps
+                        .into_iter()
+                        .filter(|d| {
+                            d.registry.is_none()
+                                && canonical_crate_name(d.package.as_ref().unwrap_or(&d.name))
+                                    == canonical_name
+                        })
+                        .map(|d| ReverseDependency {
+                            krate: entry.name.clone(),
+                            num: entry.vers.clone(),
+                            yanked: entry.yanked,
+                            req: d.req,
+                            features: d.features,
+                            optional: d.optional,
+                            default_features: d.default_features,
+                            target: d.target,
+                            kind: d.kind,
+                        }),
+                );
+            }
+        }
+        dependencies.sort_by(|a, b| a.krate.cmp(&b.krate).then_with(|| a.num.cmp(&b.num)));
+
+        Ok(ReverseDependencies { dependencies })
+    }
+
     async fn search(&self, query_string: &str, limit: usize) -> IndexResult<SearchResults> {
         let mut index_keys = self.fs.list_prefix("index/").await?;
         index_keys.retain(|k| k.contains(query_string));
@@ -295,12 +661,13 @@ impl IndexProvider for FsIndexProvider {
             get_latest_crate_publishes(Arc::clone(&self.fs), index_keys);
 
         while let Some(handle) = crate_versions_with_publish.join_next().await {
-            let Ok(Ok((mut versions, publish_meta))) = handle else { continue };
+            let Ok(Ok((mut versions, publish_meta, downloads))) = handle else { continue };
             let Some(most_recent_version) = versions.entries.pop() else { continue };
             results.push(SearchResultsEntry {
                 name: most_recent_version.name,
                 max_version: most_recent_version.vers,
                 description: publish_meta.and_then(|p| p.description).unwrap_or_default(),
+                downloads,
             });
         }
 
@@ -311,10 +678,16 @@ impl IndexProvider for FsIndexProvider {
     }
 }
 
+fn invalid_crate_name() -> IndexError {
+    IndexError::CrateNameNotAllowed(
+        "it's too long (64) or contains non-ASCII characters or punctuation".into(),
+    )
+}
+
 fn get_latest_crate_publishes(
     fs: Arc<dyn MetadataStorageProvider + Send + Sync>,
     index_keys: Vec<String>,
-) -> JoinSet<IndexResult<(SparseEntries, Option<Publish>)>> {
+) -> JoinSet<IndexResult<(SparseEntries, Option<Publish>, u64)>> {
     let mut join_set = JoinSet::new();
 
     for index_key in index_keys {
@@ -322,17 +695,59 @@ fn get_latest_crate_publishes(
         join_set.spawn(async move {
             let res = fs.pull_file(&index_key).await?;
             let (entries, publish) = deserialize_data(&res.data).context("deserializing index file for list")?;
+            let downloads = pull_downloads_file(&*fs, &downloads_file_rel_path(&index_key)).await?;
+            let total_downloads = downloads.values().flat_map(|days| days.values()).sum();
 
-            Ok((SparseEntries { entries, last_modified: res.last_modified }, publish))
+            Ok((SparseEntries { entries, last_modified: res.last_modified }, publish, total_downloads))
         });
     }
 
     join_set
 }
 
+/// `index/ab/cd/abcd` -> `downloads/ab/cd/abcd.json`
+///
+/// The path may be absolute, as listed by [`FsStorageProvider`].
+fn downloads_file_rel_path(index_path: &str) -> String {
+    sidecar_file_rel_path("downloads", index_path)
+}
+
+/// `index/ab/cd/abcd` -> `versions/ab/cd/abcd.json`
+fn versions_file_rel_path(index_path: &str) -> String {
+    sidecar_file_rel_path("versions", index_path)
+}
+
+fn sidecar_file_rel_path(dir: &str, index_path: &str) -> String {
+    let (root, rel_path) = index_path.rsplit_once("index/").unwrap_or(("", index_path));
+    format!("{root}{dir}/{rel_path}.json")
+}
+
+async fn pull_downloads_file(
+    fs: &(dyn MetadataStorageProvider + Send + Sync),
+    rel_path: &str,
+) -> IndexResult<DownloadsFile> {
+    match fs.pull_file(rel_path).await {
+        Ok(res) => Ok(serde_json::from_slice(&res.data).context("invalid download counts file")?),
+        Err(StorageError::NotFound) => Ok(DownloadsFile::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn pull_versions_file(
+    fs: &(dyn MetadataStorageProvider + Send + Sync),
+    rel_path: &str,
+) -> IndexResult<VersionsFile> {
+    match fs.pull_file(rel_path).await {
+        Ok(res) => Ok(serde_json::from_slice(&res.data).context("invalid versions file")?),
+        Err(StorageError::NotFound) => Ok(VersionsFile::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
 fn convert_publish_to_crate_entry(
     mut versions: SparseEntries,
     publish: Option<Publish>,
+    downloads: u64,
 ) -> ListAllCrateEntry {
     versions.entries.sort_by_key(|v| Reverse(v.vers.clone()));
 
@@ -361,5 +776,173 @@ fn convert_publish_to_crate_entry(
         documentation: publish.documentation.clone(),
         keywords: publish.keywords.clone(),
         categories: publish.categories,
+        downloads,
     }
 }
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_canonical_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let publish = |name: &str, vers| Publish { name: name.into(), vers: Version::new(1, 0, vers), ..Publish::empty() };
+    let index = FsIndexProvider::new(Config::Path(dir.path().to_path_buf())).unwrap();
+    index.publish(&publish("Foo-bar", 0), [0; 32], &PublishContext::default(), std::pin::pin!(async { Ok(()) })).await.unwrap();
+    index.publish(&publish("Foo-bar", 1), [0; 32], &PublishContext::default(), std::pin::pin!(async { Ok(()) })).await.unwrap();
+    let res = index.publish(&publish("foo_bar", 2), [0; 32], &PublishContext::default(), std::pin::pin!(async { Ok(()) })).await;
+    assert!(matches!(res, Err(IndexError::Conflict(_))));
+    assert_eq!(index.get_sparse_entry("foo_BAR").await.unwrap().entries.len(), 2);
+    index.yank_crate("foo-Bar", &Version::new(1, 0, 1)).await.unwrap();
+
+    // reload
+    let index = FsIndexProvider::new(Config::Path(dir.path().to_path_buf())).unwrap();
+    let entries = index.get_sparse_entry("FOO_BAR").await.unwrap().entries;
+    assert_eq!(entries[0].name, "Foo-bar");
+    assert!(entries[1].yanked);
+    let res = index.publish(&publish("foo_bar", 2), [0; 32], &PublishContext::default(), std::pin::pin!(async { Ok(()) })).await;
+    assert!(matches!(res, Err(IndexError::Conflict(_))));
+    assert!(matches!(index.get_sparse_entry("foo-baz").await, Err(IndexError::NotFound)));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_downloads() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = FsIndexProvider::new(Config::Path(dir.path().to_path_buf())).unwrap();
+    let publish = Publish { name: "Foo-bar".into(), vers: Version::new(1, 0, 0), ..Publish::empty() };
+    index.publish(&publish, [0; 32], &PublishContext::default(), std::pin::pin!(async { Ok(()) })).await.unwrap();
+
+    let today = Utc::now().date_naive();
+    let count = |name: &str, version, date, downloads| DownloadCount { name: name.into(), version, date, downloads };
+    index.record_downloads(&[
+        count("foo_bar", Version::new(1, 0, 0), today, 2),
+        count("Foo-bar", Version::new(1, 0, 0), today, 3),
+        count("foo-bar", Version::new(1, 0, 0), today - Days::new(1), 1),
+        count("foo-bar", Version::new(1, 0, 0), today - Days::new(DOWNLOADS_HISTORY_DAYS), 100),
+        count("foo-bar", Version::new(2, 0, 0), today, 1),
+        count("missing", Version::new(1, 0, 0), today, 1),
+    ]).await.unwrap();
+    index.record_downloads(&[count("foo-bar", Version::new(1, 0, 0), today, 1)]).await.unwrap();
+
+    let downloads = index.get_downloads("FOO_BAR").await.unwrap().version_downloads;
+    assert_eq!(downloads, [
+        VersionDownloads { version: Version::new(1, 0, 0), downloads: 1, date: today - Days::new(1) },
+        VersionDownloads { version: Version::new(1, 0, 0), downloads: 6, date: today },
+    ]);
+    assert!(matches!(index.get_downloads("missing").await, Err(IndexError::NotFound)));
+
+    // old downloads still count towards the total
+    let list = index.list(&ListQuery { per_page: None, page: None }).await.unwrap();
+    assert_eq!(list.results[0].downloads, 107);
+    let search = index.search("foo", 10).await.unwrap();
+    assert_eq!(search.crates[0].downloads, 107);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_crate_details() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = FsIndexProvider::new(Config::Path(dir.path().to_path_buf())).unwrap();
+    for (vers, description) in [(Version::new(1, 0, 0), "old"), (Version::new(1, 1, 0), "new")] {
+        let publish = Publish {
+            name: "Foo-bar".into(),
+            vers,
+            description: Some(description.into()),
+            keywords: vec!["foo".into()],
+            ..Publish::empty()
+        };
+        index.publish(&publish, [0; 32], &PublishContext::default(), std::pin::pin!(async { Ok(()) })).await.unwrap();
+    }
+    index.yank_crate("foo-bar", &Version::new(1, 1, 0)).await.unwrap();
+
+    let details = index.get_crate_details("foo_bar").await.unwrap();
+    assert_eq!(details.krate.name, "Foo-bar");
+    assert_eq!(details.krate.description.as_deref(), Some("new"));
+    assert_eq!(details.krate.keywords, ["foo"]);
+    assert_eq!(details.krate.max_version, Version::new(1, 0, 0));
+    let versions: Vec<_> = details.versions.iter().map(|v| (v.num.to_string(), v.yanked)).collect();
+    assert_eq!(versions, [("1.1.0".to_owned(), true), ("1.0.0".to_owned(), false)]);
+    let first = index.get_version_details("foo-bar", &Version::new(1, 0, 0)).await.unwrap().version;
+    let last = index.get_version_details("foo-bar", &Version::new(1, 1, 0)).await.unwrap().version;
+    assert_eq!(Some(details.krate.created_at), first.created_at);
+    assert_eq!(Some(details.krate.updated_at), last.created_at);
+    assert!(matches!(index.get_crate_details("missing").await, Err(IndexError::NotFound)));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_version_details() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = FsIndexProvider::new(Config::Path(dir.path().to_path_buf())).unwrap();
+    for (vers, license) in [(Version::new(1, 0, 0), "MIT"), (Version::new(1, 1, 0), "Apache-2.0")] {
+        let publish = Publish {
+            name: "Foo-bar".into(),
+            vers,
+            license: Some(license.into()),
+            features: HashMap::from([("std".into(), vec![])]),
+            ..Publish::empty()
+        };
+        let context = PublishContext { publisher: Some("alice".into()), tarball_size: 123 };
+        index.publish(&publish, [1; 32], &context, std::pin::pin!(async { Ok(()) })).await.unwrap();
+    }
+    index.yank_crate("foo-bar", &Version::new(1, 0, 0)).await.unwrap();
+
+    let details = index.get_version_details("foo_bar", &Version::new(1, 0, 0)).await.unwrap().version;
+    assert_eq!(details.krate, "Foo-bar");
+    assert_eq!(details.checksum, [1; 32]);
+    assert!(details.yanked);
+    assert_eq!(details.license.as_deref(), Some("MIT"));
+    assert!(details.features.contains_key("std"));
+    assert_eq!(details.published_by.unwrap().login, "alice");
+    assert_eq!(details.crate_size, Some(123));
+    assert!(details.created_at.is_some());
+
+    let details = index.get_version_details("foo-bar", &Version::new(1, 1, 0)).await.unwrap().version;
+    assert_eq!(details.license.as_deref(), Some("Apache-2.0"));
+    assert!(!details.yanked);
+
+    let res = index.get_version_details("foo-bar", &Version::new(2, 0, 0)).await;
+    assert!(matches!(res, Err(IndexError::NotFound)));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fs_reverse_dependencies() {
+    use freighter_api_types::index::request::PublishDependency;
+    use semver::VersionReq;
+
+    let dir = tempfile::tempdir().unwrap();
+    let index = FsIndexProvider::new(Config::Path(dir.path().to_path_buf())).unwrap();
+    let dependency = |name: &str, req, registry: Option<&str>, renamed: Option<&str>| PublishDependency {
+        name: name.into(),
+        version_req: VersionReq::parse(req).unwrap(),
+        features: vec![],
+        optional: false,
+        default_features: true,
+        target: None,
+        kind: Default::default(),
+        registry: registry.map(Into::into),
+        explicit_name_in_toml: renamed.map(Into::into),
+    };
+    for (name, vers, deps) in [
+        ("Foo-bar", 0, vec![]),
+        ("b", 0, vec![dependency("foo_bar", "^1", None, None)]),
+        ("b", 1, vec![dependency("Foo-bar", "^1.0", None, Some("foo"))]),
+        ("a", 0, vec![dependency("foo-bar", "^1", None, None), dependency("other", "^1", None, None)]),
+        ("c", 0, vec![dependency("foo-bar", "^1", Some("https://github.com/rust-lang/crates.io-index"), None)]),
+    ] {
+        let publish = Publish { name: name.into(), vers: Version::new(1, 0, vers), deps, ..Publish::empty() };
+        index.publish(&publish, [0; 32], &PublishContext::default(), std::pin::pin!(async { Ok(()) })).await.unwrap();
+    }
+
+    let dependents: Vec<_> = index.get_reverse_dependencies("FOO_BAR").await.unwrap().dependencies
+        .into_iter()
+        .map(|d| (d.krate, d.num.to_string(), d.req.to_string()))
+        .collect();
+    assert_eq!(dependents, [
+        ("a".to_owned(), "1.0.0".to_owned(), "^1".to_owned()),
+        ("b".to_owned(), "1.0.0".to_owned(), "^1".to_owned()),
+        ("b".to_owned(), "1.0.1".to_owned(), "^1.0".to_owned()),
+    ]);
+    assert!(index.get_reverse_dependencies("a").await.unwrap().dependencies.is_empty());
+    assert!(matches!(index.get_reverse_dependencies("missing").await, Err(IndexError::NotFound)));
+}
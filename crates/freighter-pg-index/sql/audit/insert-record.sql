@@ -0,0 +1,2 @@
+insert into audit_log (timestamp, operation, actor, token_id, crate_name, version, source_ip, outcome, status, details)
+values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
@@ -0,0 +1,8 @@
+select *
+from audit_log
+where ($1::text is null or lower(replace(crate_name, '-', '_')) = lower(replace($1, '-', '_')))
+  and ($2::text is null or version = $2)
+  and ($3::text is null or actor = $3)
+  and ($4::audit_operation is null or operation = $4)
+order by id desc
+limit $5
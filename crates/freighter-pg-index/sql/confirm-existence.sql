@@ -1,6 +1,6 @@
 select cv.yanked, cv.cksum
 from crates
          join crate_versions cv on crates.id = cv.crate
-where crates.name = $1
+where lower(replace(crates.name, '-', '_')) = lower(replace($1, '-', '_'))
   and cv.version = $2
   and crates.registry = ''
@@ -0,0 +1,21 @@
+select c.id,
+       c.name,
+       c.description,
+       c.documentation,
+       c.homepage,
+       c.repository,
+       c.created_at,
+       c.updated_at,
+       array(select k.name
+             from crate_keywords ck
+                      join keywords k on k.id = ck.keyword
+             where ck.crate = c.id
+             order by k.name)   as keywords,
+       array(select cat.name
+             from crate_categories cc
+                      join categories cat on cat.id = cc.category
+             where cc.crate = c.id
+             order by cat.name) as categories
+from crates c
+where lower(replace(c.name, '-', '_')) = lower(replace($1, '-', '_'))
+  and c.registry = ''
@@ -0,0 +1,7 @@
+select cv.version,
+       cv.yanked,
+       coalesce(sum(vd.downloads), 0)::bigint as downloads
+from crate_versions cv
+         left join version_downloads vd on vd.crate_version = cv.id
+where cv.crate = $1
+group by cv.id, cv.version, cv.yanked
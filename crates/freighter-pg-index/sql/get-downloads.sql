@@ -0,0 +1,6 @@
+select cv.version, vd.date, vd.downloads
+from version_downloads vd
+         join crate_versions cv on cv.id = vd.crate_version
+where cv.crate = $1
+  and vd.date > $2
+order by vd.date, cv.id
@@ -0,0 +1,5 @@
+select c.name, cv.version, cv.yanked, d.req, d.features, d.optional, d.default_features, d.target, d.kind
+from dependencies d
+         join crate_versions cv on cv.id = d.dependent
+         join crates c on c.id = cv.crate
+where d.dependency = $1
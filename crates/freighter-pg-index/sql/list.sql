@@ -7,7 +7,11 @@ select c.name,
        c.updated_at,
        array_agg(distinct cv.version)                                     as versions,
        array_agg(distinct cat.name) filter ( where cat.name is not null ) as categories,
-       array_agg(distinct k.name) filter ( where k.name is not null )     as keywords
+       array_agg(distinct k.name) filter ( where k.name is not null )     as keywords,
+       (select coalesce(sum(vd.downloads), 0)::bigint
+        from version_downloads vd
+                 join crate_versions v on v.id = vd.crate_version
+        where v.crate = c.id)                                             as downloads
 from crates c
          join crate_versions cv on c.id = cv.crate
          left join crate_categories cc on c.id = cc.crate
@@ -15,5 +19,5 @@ from crates c
          left join crate_keywords ck on c.id = ck.crate
          left join keywords k on k.id = ck.keyword
 where c.registry = ''
-group by c.name, c.description, c.documentation, c.homepage, c.repository, c.created_at, c.updated_at
+group by c.id, c.name, c.description, c.documentation, c.homepage, c.repository, c.created_at, c.updated_at
 having count(cv.version) > 0
@@ -0,0 +1,6 @@
+select c.id,
+       c.name,
+       exists(select 1 from crate_versions cv where cv.crate = c.id) as has_versions
+from crates c
+where lower(replace(c.name, '-', '_')) = lower(replace($1, '-', '_'))
+  and c.registry = ''
@@ -6,7 +6,7 @@ with ins
           union all
           select id
           from crates
-          where name = $1
+          where lower(replace(name, '-', '_')) = lower(replace($1, '-', '_'))
             and (registry = $2 or registry = '' and $2 = ''))
 insert
 into dependencies
@@ -1,3 +1,4 @@
-insert into crate_versions (crate, version, cksum, yanked, links)
-values ($1, $2, $3, $4, $5)
+insert into crate_versions (crate, version, cksum, yanked, links, description, license, license_file, homepage,
+                            documentation, repository, crate_size, published_by, created_at)
+values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, current_timestamp)
 returning id
@@ -0,0 +1,3 @@
+update crates
+set name = $2
+where id = $1
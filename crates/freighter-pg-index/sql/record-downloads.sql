@@ -0,0 +1,8 @@
+insert into version_downloads (crate_version, date, downloads)
+select cv.id, $3, $4
+from crate_versions cv
+         join crates c on c.id = cv.crate
+where lower(replace(c.name, '-', '_')) = lower(replace($1, '-', '_'))
+  and c.registry = ''
+  and cv.version = $2
+on conflict (crate_version, date) do update set downloads = version_downloads.downloads + excluded.downloads
@@ -1,11 +1,15 @@
 select crates.name,
        crates.description,
        array_agg(distinct cv.version) as versions,
-       count(distinct concat(d.dependent, crates.id))
+       count(distinct concat(d.dependent, crates.id)),
+       (select coalesce(sum(vd.downloads), 0)::bigint
+        from version_downloads vd
+                 join crate_versions v on v.id = vd.crate_version
+        where v.crate = crates.id) as downloads
 from crates
          join crate_versions cv on crates.id = cv.crate
          left join dependencies d on crates.id = d.dependency
 where crates.registry = ''
   and position($1 in crates.name) > 0
-group by crates.name, crates.description
+group by crates.id, crates.name, crates.description
 having count(cv.version) > 0
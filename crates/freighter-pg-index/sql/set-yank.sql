@@ -1,8 +1,18 @@
-update crate_versions cv
-set yanked = $3
-from crates c
-where c.name = $1
-  and c.registry = ''
-  and cv.crate = c.id
-  and cv.version = $2
-returning c.name, cv.version, cv.yanked;
+with yanked as (
+    update crate_versions cv
+    set yanked = $3
+    from crates c
+    where lower(replace(c.name, '-', '_')) = lower(replace($1, '-', '_'))
+      and c.registry = ''
+      and cv.crate = c.id
+      and cv.version = $2
+    returning c.id, c.name, cv.version, cv.yanked
+),
+-- the index entry has changed
+touched as (
+    update crates
+    set updated_at = current_timestamp
+    where id in (select id from yanked)
+)
+select name, version, yanked
+from yanked;
@@ -1,4 +1,4 @@
-select id
+select id, name, updated_at
 from crates
-where name = $1
+where lower(replace(name, '-', '_')) = lower(replace($1, '-', '_'))
   and registry = ''
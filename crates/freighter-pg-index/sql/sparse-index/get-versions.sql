@@ -1,3 +1,4 @@
 select *
 from crate_versions
 where crate = $1
+order by id
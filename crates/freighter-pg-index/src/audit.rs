@@ -0,0 +1,98 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use deadpool_postgres::tokio_postgres::{NoTls, Row};
+use deadpool_postgres::{Pool, Runtime};
+use freighter_api_types::audit::{AuditQuery, AuditRecord, AuditSink};
+use semver::Version;
+
+/// Keeps the audit log in the `audit_log` table.
+pub struct PgAuditSink {
+    pool: Pool,
+}
+
+impl PgAuditSink {
+    pub fn new(db: &deadpool_postgres::Config) -> anyhow::Result<Self> {
+        let pool = db
+            .create_pool(Some(Runtime::Tokio1), NoTls)
+            .context("Failed to create audit db pool")?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl AuditSink for PgAuditSink {
+    async fn record(&self, record: &AuditRecord) -> anyhow::Result<()> {
+        let client = self.pool.get().await?;
+
+        let statement = client
+            .prepare_cached(include_str!("../sql/audit/insert-record.sql"))
+            .await
+            .context("Failed to prepare audit record statement")?;
+
+        client
+            .execute(
+                &statement,
+                &[
+                    &record.timestamp,
+                    &record.operation,
+                    &record.actor,
+                    &record.token_id,
+                    &record.crate_name,
+                    &record.version.as_ref().map(Version::to_string),
+                    &record.source_ip,
+                    &record.outcome,
+                    &i32::from(record.status),
+                    &record.details,
+                ],
+            )
+            .await
+            .context("Failed to insert audit record")?;
+
+        Ok(())
+    }
+
+    async fn query(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditRecord>> {
+        let client = self.pool.get().await?;
+
+        let statement = client
+            .prepare_cached(include_str!("../sql/audit/query-records.sql"))
+            .await
+            .context("Failed to prepare audit query statement")?;
+
+        let rows = client
+            .query(
+                &statement,
+                &[
+                    &query.crate_name,
+                    &query.version.as_ref().map(Version::to_string),
+                    &query.actor,
+                    &query.operation,
+                    &(query.limit() as i64),
+                ],
+            )
+            .await
+            .context("Failed to query audit log")?;
+
+        rows.iter().map(audit_row_to_record).collect()
+    }
+}
+
+fn audit_row_to_record(row: &Row) -> anyhow::Result<AuditRecord> {
+    Ok(AuditRecord {
+        timestamp: row.get("timestamp"),
+        operation: row.get("operation"),
+        actor: row.get("actor"),
+        token_id: row.get("token_id"),
+        crate_name: row.get("crate_name"),
+        version: row
+            .get::<_, Option<&str>>("version")
+            .map(Version::parse)
+            .transpose()
+            .context("Failed to parse audited version in db")?,
+        source_ip: row.get("source_ip"),
+        outcome: row.get("outcome"),
+        status: u16::try_from(row.get::<_, i32>("status")).context("Bad audited status")?,
+        details: row.get("details"),
+    })
+}
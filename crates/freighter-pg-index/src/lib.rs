@@ -1,14 +1,19 @@
 use anyhow::Context;
 use async_trait::async_trait;
+use chrono::{Days, Utc};
+use deadpool_postgres::tokio_postgres::error::SqlState;
 use deadpool_postgres::tokio_postgres::{IsolationLevel, NoTls, Row, Statement};
 use deadpool_postgres::{Pool, Runtime};
 use freighter_api_types::index::request::{ListQuery, Publish};
 use freighter_api_types::index::response::{
-    CompletedPublication, CrateVersion, Dependency, ListAll, ListAllCrateEntry,
-    ListAllCrateVersion, SearchResults, SearchResultsEntry, SearchResultsMeta,
+    CompletedPublication, CrateDetails, CrateDetailsEntry, CrateDetailsVersion, CrateDownloads,
+    CrateVersion, Dependency, ListAll, ListAllCrateEntry, ListAllCrateVersion, ReverseDependencies,
+    ReverseDependency, SearchResults, SearchResultsEntry, SearchResultsMeta, VersionDetails,
+    VersionDetailsEntry, VersionDownloads, VersionPublisher,
 };
 use freighter_api_types::index::{
-    CrateVersionExists, IndexError, IndexProvider, IndexResult, SparseEntries,
+    CrateVersionExists, DownloadCount, IndexError, IndexProvider, IndexResult, PublishContext,
+    SparseEntries, DOWNLOADS_HISTORY_DAYS,
 };
 use futures_util::StreamExt;
 use metrics::histogram;
@@ -16,11 +21,14 @@ use postgres_types::ToSql;
 use semver::{Version, VersionReq};
 use serde::Deserialize;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::time::Instant;
 
+mod audit;
+pub use audit::PgAuditSink;
+
 pub struct PgIndexProvider {
     pool: Pool,
 }
@@ -98,6 +106,8 @@ impl IndexProvider for PgIndexProvider {
         match existential_rows.pop() {
             Some(crate_row) => {
                 let id: i32 = crate_row.get("id");
+                // may be spelled differently than requested
+                let crate_name: String = crate_row.get("name");
 
                 // this is a major hotpath
                 let version_rows = client
@@ -107,8 +117,9 @@ impl IndexProvider for PgIndexProvider {
 
                 let mut versions = Vec::with_capacity(version_rows.len());
 
-                // drive them all concurrently to improve pipelining
-                let mut version_queries = futures_util::stream::FuturesUnordered::new();
+                // drive them all concurrently to improve pipelining, but keep the versions in order,
+                // so that the entry is the same every time for ETags
+                let mut version_queries = futures_util::stream::FuturesOrdered::new();
 
                 // using a function like this can often make rustc a bit smarter about what it captures and generates
                 async fn query_version(
@@ -133,7 +144,7 @@ impl IndexProvider for PgIndexProvider {
                 }
 
                 for version_row in version_rows {
-                    version_queries.push(query_version(
+                    version_queries.push_back(query_version(
                         version_row,
                         &client,
                         &features_statement,
@@ -144,35 +155,22 @@ impl IndexProvider for PgIndexProvider {
                 while let Some(query_res) = version_queries.next().await {
                     let (version_row, feature_rows, dependency_rows) = query_res?;
 
-                    let mut features = HashMap::with_capacity(feature_rows.len());
+                    let mut features = BTreeMap::new();
                     let mut deps = Vec::with_capacity(dependency_rows.len());
 
                     for feature_row in feature_rows {
                         features.insert(feature_row.get("name"), feature_row.get("values"));
                     }
 
-                    for deps_row in dependency_rows {
-                        let registry: Option<String> = deps_row.get("registry");
-
-                        deps.push(Dependency {
-                            name: deps_row.get("name"),
-                            req: VersionReq::parse(deps_row.get("req"))
-                                .context("Failed to parse dependency version req in db")?,
-                            features: deps_row.get("features"),
-                            optional: deps_row.get("optional"),
-                            default_features: deps_row.get("default_features"),
-                            target: deps_row.get("target"),
-                            kind: deps_row.get("kind"),
-                            registry: registry.filter(|x| !x.is_empty()),
-                            package: deps_row.get("package"),
-                        });
+                    for deps_row in &dependency_rows {
+                        deps.push(dependency_row_to_dependency(deps_row)?);
                     }
 
                     let cksum: &str = version_row.get("cksum");
 
                     versions.push(CrateVersion {
                         cksum: hex::FromHex::from_hex(cksum).context("Bad checksum")?,
-                        name: crate_name.to_string(),
+                        name: crate_name.clone(),
                         vers: Version::parse(version_row.get("version"))
                             .context("Failed to parse crate version in db")?,
                         deps,
@@ -181,13 +179,13 @@ impl IndexProvider for PgIndexProvider {
                         links: version_row.get("links"),
                         v: 2,
                         // todo maybe scrap
-                        features2: HashMap::new(),
+                        features2: BTreeMap::new(),
                     });
                 }
 
                 Ok(SparseEntries {
                     entries: versions,
-                    last_modified: None,
+                    last_modified: crate_row.get("updated_at"),
                 })
             }
             None => {
@@ -197,6 +195,137 @@ impl IndexProvider for PgIndexProvider {
         }
     }
 
+    async fn get_crate_details(&self, crate_name: &str) -> IndexResult<CrateDetails> {
+        let client = self.pool.get().await.map_err(anyhow::Error::from)?;
+
+        let (crate_statement, versions_statement) = tokio::try_join!(
+            client.prepare_cached(include_str!("../sql/crate-details/get-crate.sql")),
+            client.prepare_cached(include_str!("../sql/crate-details/get-versions.sql")),
+        )
+        .context("Failed to prepare crate details statements")?;
+
+        let crate_row = client
+            .query_opt(&crate_statement, &[&crate_name])
+            .await
+            .context("Failed to query crate")?
+            .ok_or(IndexError::NotFound)?;
+        let id: i32 = crate_row.get("id");
+
+        let version_rows = client
+            .query(&versions_statement, &[&id])
+            .await
+            .context("Failed to query versions")?;
+
+        let mut versions = version_rows
+            .iter()
+            .map(|row| {
+                Ok(CrateDetailsVersion {
+                    num: Version::parse(row.get("version"))
+                        .context("Failed to parse crate version in db")?,
+                    yanked: row.get("yanked"),
+                    downloads: row.get::<_, i64>("downloads") as u64,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        versions.sort_unstable_by(|a, b| b.num.cmp(&a.num));
+
+        // crates that are only dependencies of other crates don't have versions
+        let max_version = CrateDetailsVersion::max_version(&versions).ok_or(IndexError::NotFound)?;
+        let name: String = crate_row.get("name");
+
+        Ok(CrateDetails {
+            krate: CrateDetailsEntry {
+                id: name.clone(),
+                name,
+                description: crate_row.get("description"),
+                homepage: crate_row.get("homepage"),
+                documentation: crate_row.get("documentation"),
+                repository: crate_row.get("repository"),
+                keywords: crate_row.get("keywords"),
+                categories: crate_row.get("categories"),
+                created_at: crate_row.get("created_at"),
+                updated_at: crate_row.get("updated_at"),
+                max_version,
+                downloads: versions.iter().map(|v| v.downloads).sum(),
+            },
+            versions,
+        })
+    }
+
+    async fn get_version_details(
+        &self,
+        crate_name: &str,
+        version: &Version,
+    ) -> IndexResult<VersionDetails> {
+        let client = self.pool.get().await.map_err(anyhow::Error::from)?;
+
+        let (crate_statement, version_statement, features_statement, dependencies_statement) =
+            tokio::try_join!(
+                client.prepare_cached(include_str!("../sql/sparse-index/get-crate.sql")),
+                client.prepare_cached(include_str!("../sql/crate-details/get-version.sql")),
+                client.prepare_cached(include_str!("../sql/sparse-index/get-features.sql")),
+                client.prepare_cached(include_str!("../sql/sparse-index/get-dependencies.sql"))
+            )
+            .context("Failed to prepare version details statements")?;
+
+        let crate_row = client
+            .query_opt(&crate_statement, &[&crate_name])
+            .await
+            .context("Failed to query crate")?
+            .ok_or(IndexError::NotFound)?;
+        let id: i32 = crate_row.get("id");
+
+        let version_row = client
+            .query_opt(&version_statement, &[&id, &version.to_string()])
+            .await
+            .context("Failed to query version")?
+            .ok_or(IndexError::NotFound)?;
+        let version_id: i32 = version_row.get("id");
+        let version_id_query = [&version_id as &(dyn ToSql + Sync)];
+
+        let (feature_rows, dependency_rows) = tokio::try_join!(
+            client.query(&features_statement, &version_id_query),
+            client.query(&dependencies_statement, &version_id_query)
+        )
+        .context("Failed to query features or dependencies for version")?;
+
+        let features = feature_rows
+            .iter()
+            .map(|row| (row.get("name"), row.get("values")))
+            .collect();
+        let dependencies = dependency_rows
+            .iter()
+            .map(dependency_row_to_dependency)
+            .collect::<anyhow::Result<_>>()?;
+        let cksum: &str = version_row.get("cksum");
+
+        Ok(VersionDetails {
+            version: VersionDetailsEntry {
+                krate: crate_row.get("name"),
+                num: version.clone(),
+                checksum: hex::FromHex::from_hex(cksum).context("Bad checksum")?,
+                yanked: version_row.get("yanked"),
+                features,
+                dependencies,
+                links: version_row.get("links"),
+                description: version_row.get("description"),
+                license: version_row.get("license"),
+                license_file: version_row.get("license_file"),
+                homepage: version_row.get("homepage"),
+                documentation: version_row.get("documentation"),
+                repository: version_row.get("repository"),
+                created_at: version_row.get("created_at"),
+                published_by: version_row
+                    .get::<_, Option<String>>("published_by")
+                    .map(|login| VersionPublisher { login }),
+                crate_size: version_row
+                    .get::<_, Option<i64>>("crate_size")
+                    .map(|size| size as u64),
+                downloads: version_row.get::<_, i64>("downloads") as u64,
+            },
+        })
+    }
+
     async fn confirm_existence(
         &self,
         crate_name: &str,
@@ -278,6 +407,7 @@ impl IndexProvider for PgIndexProvider {
         &self,
         version: &Publish,
         tarball_checksum: [u8; 32],
+        context: &PublishContext,
         end_step: Pin<&mut (dyn Future<Output = IndexResult<()>> + Send)>,
     ) -> IndexResult<CompletedPublication> {
         let startup_timer = Instant::now();
@@ -296,6 +426,8 @@ impl IndexProvider for PgIndexProvider {
             .context("Failed to create publication transaction")?;
 
         let (
+            get_crate_statement,
+            rename_crate_statement,
             get_or_insert_crate_statement,
             insert_version_statement,
             insert_dependency_statement,
@@ -310,6 +442,8 @@ impl IndexProvider for PgIndexProvider {
             remove_crate_keyword_statement,
             remove_crate_category_statement,
         ) = tokio::try_join!(
+            transaction.prepare_cached(include_str!("../sql/publish/get-crate.sql")),
+            transaction.prepare_cached(include_str!("../sql/publish/rename-crate.sql")),
             transaction.prepare_cached(include_str!("../sql/publish/get-or-insert-crate.sql")),
             transaction.prepare_cached(include_str!("../sql/publish/insert-version.sql")),
             transaction.prepare_cached(include_str!("../sql/publish/insert-dependency.sql")),
@@ -334,10 +468,41 @@ impl IndexProvider for PgIndexProvider {
 
         let crate_timer = Instant::now();
 
+        // Names that differ only in case or `-`/`_` are the same crate
+        let existing_crate = transaction
+            .query_opt(&get_crate_statement, &[&version.name])
+            .await
+            .context("Failed to query for existing crate")?;
+
+        if let Some(existing_crate) = existing_crate {
+            let existing_name: &str = existing_crate.get("name");
+            if existing_name != version.name {
+                if existing_crate.get("has_versions") {
+                    return Err(IndexError::Conflict(format!(
+                        "crate was previously named `{existing_name}`"
+                    )));
+                }
+
+                // it's only been a placeholder for dependencies, so the first publish names it
+                let existing_id: i32 = existing_crate.get("id");
+                transaction
+                    .query(&rename_crate_statement, &[&existing_id, &version.name])
+                    .await
+                    .context("Failed to rename crate")?;
+            }
+        }
+
         let crate_row = transaction
             .query_one(&get_or_insert_crate_statement, &[&version.name])
             .await
-            .context("Crate get or insert failed")?;
+            .map_err(|e| {
+                // a concurrent publish of the same crate with a different spelling
+                if e.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+                    IndexError::Conflict(format!("crate {} already exists", version.name))
+                } else {
+                    IndexError::ServiceError(anyhow::Error::new(e).context("Crate get or insert failed"))
+                }
+            })?;
 
         let crate_id: i32 = crate_row.get("id");
 
@@ -472,6 +637,14 @@ impl IndexProvider for PgIndexProvider {
                     &checksum_hex,
                     &false,
                     &version.links,
+                    &version.description,
+                    &version.license,
+                    &version.license_file,
+                    &version.homepage,
+                    &version.documentation,
+                    &version.repository,
+                    &i64::try_from(context.tarball_size).ok(),
+                    &context.publisher,
                 ],
             )
             .await
@@ -599,6 +772,140 @@ impl IndexProvider for PgIndexProvider {
 
         Ok(list_all)
     }
+
+    async fn record_downloads(&self, downloads: &[DownloadCount]) -> IndexResult<()> {
+        let mut client = self.pool.get().await.map_err(anyhow::Error::from)?;
+
+        let transaction = client
+            .transaction()
+            .await
+            .context("Failed to construct transaction")?;
+
+        let statement = transaction
+            .prepare_cached(include_str!("../sql/record-downloads.sql"))
+            .await
+            .context("Failed to prepare record downloads statement")?;
+
+        for count in downloads {
+            transaction
+                .execute(
+                    &statement,
+                    &[
+                        &count.name,
+                        &count.version.to_string(),
+                        &count.date,
+                        &(count.downloads as i64),
+                    ],
+                )
+                .await
+                .context("Failed to record downloads")?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit transaction")?;
+
+        Ok(())
+    }
+
+    async fn get_downloads(&self, crate_name: &str) -> IndexResult<CrateDownloads> {
+        let client = self.pool.get().await.map_err(anyhow::Error::from)?;
+
+        let (crate_statement, downloads_statement) = tokio::try_join!(
+            client.prepare_cached(include_str!("../sql/sparse-index/get-crate.sql")),
+            client.prepare_cached(include_str!("../sql/get-downloads.sql")),
+        )
+        .context("Failed to prepare downloads statements")?;
+
+        let crate_row = client
+            .query_opt(&crate_statement, &[&crate_name])
+            .await
+            .context("Failed to query for crate existence")?
+            .ok_or(IndexError::NotFound)?;
+        let id: i32 = crate_row.get("id");
+
+        let since = Utc::now().date_naive() - Days::new(DOWNLOADS_HISTORY_DAYS);
+        let rows = client
+            .query(&downloads_statement, &[&id, &since])
+            .await
+            .context("Failed to query downloads")?;
+
+        let version_downloads = rows
+            .iter()
+            .map(|row| {
+                Ok(VersionDownloads {
+                    version: Version::parse(row.get("version"))
+                        .context("Failed to parse crate version in db")?,
+                    downloads: row.get::<_, i64>("downloads") as u64,
+                    date: row.get("date"),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(CrateDownloads { version_downloads })
+    }
+
+    async fn get_reverse_dependencies(&self, crate_name: &str) -> IndexResult<ReverseDependencies> {
+        let client = self.pool.get().await.map_err(anyhow::Error::from)?;
+
+        let (crate_statement, dependents_statement) = tokio::try_join!(
+            client.prepare_cached(include_str!("../sql/sparse-index/get-crate.sql")),
+            client.prepare_cached(include_str!("../sql/get-reverse-dependencies.sql")),
+        )
+        .context("Failed to prepare reverse dependencies statements")?;
+
+        let crate_row = client
+            .query_opt(&crate_statement, &[&crate_name])
+            .await
+            .context("Failed to query for crate existence")?
+            .ok_or(IndexError::NotFound)?;
+        let id: i32 = crate_row.get("id");
+
+        let rows = client
+            .query(&dependents_statement, &[&id])
+            .await
+            .context("Failed to query reverse dependencies")?;
+
+        let mut dependencies = rows
+            .iter()
+            .map(|row| {
+                Ok(ReverseDependency {
+                    krate: row.get("name"),
+                    num: Version::parse(row.get("version"))
+                        .context("Failed to parse crate version in db")?,
+                    yanked: row.get("yanked"),
+                    req: VersionReq::parse(row.get("req"))
+                        .context("Failed to parse dependency version req in db")?,
+                    features: row.get("features"),
+                    optional: row.get("optional"),
+                    default_features: row.get("default_features"),
+                    target: row.get("target"),
+                    kind: row.get("kind"),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        dependencies.sort_by(|a, b| a.krate.cmp(&b.krate).then_with(|| a.num.cmp(&b.num)));
+
+        Ok(ReverseDependencies { dependencies })
+    }
+}
+
+fn dependency_row_to_dependency(row: &Row) -> anyhow::Result<Dependency> {
+    let registry: Option<String> = row.get("registry");
+
+    Ok(Dependency {
+        name: row.get("name"),
+        req: VersionReq::parse(row.get("req"))
+            .context("Failed to parse dependency version req in db")?,
+        features: row.get("features"),
+        optional: row.get("optional"),
+        default_features: row.get("default_features"),
+        target: row.get("target"),
+        kind: row.get("kind"),
+        registry: registry.filter(|x| !x.is_empty()),
+        package: row.get("package"),
+    })
 }
 
 fn list_row_to_entry(row: &Row) -> ListAllCrateEntry {
@@ -625,6 +932,7 @@ fn list_row_to_entry(row: &Row) -> ListAllCrateEntry {
         categories: row
             .get::<_, Option<Vec<_>>>("categories")
             .unwrap_or_default(),
+        downloads: row.get::<_, i64>("downloads") as u64,
     }
 }
 
@@ -642,5 +950,6 @@ fn search_row_to_entry(row: &Row) -> SearchResultsEntry {
         name: row.get("name"),
         max_version,
         description: row.try_get("description").unwrap_or_default(),
+        downloads: row.get::<_, i64>("downloads") as u64,
     }
 }
@@ -1,22 +1,32 @@
+use crate::{asymmetric, audit, git_index, mirror};
+use crate::tarball::TarballValidator;
+use crate::webhooks::{self, WebhookEventKind};
 use crate::ServiceState;
-use anyhow::Context;
-use axum::body::Bytes;
-use axum::extract::{Path, Query, State};
+use axum::body::{Body, BodyDataStream, Bytes};
+use axum::extract::{ConnectInfo, Path, Query, State};
 use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{delete, get, post, put};
 use axum::{Form, Json, Router};
+use freighter_api_types::audit::{AuditOperation, AuditRecord};
 use freighter_api_types::auth::request::AuthForm;
 use freighter_api_types::index::request::{Publish, SearchQuery};
-use freighter_api_types::index::response::{CompletedPublication, SearchResults, YankResult};
-use freighter_api_types::index::IndexError;
+use freighter_api_types::index::response::{
+    CompletedPublication, CrateDetails, CrateDownloads, ReverseDependencies, SearchResults,
+    VersionDetails, YankResult,
+};
+use freighter_api_types::index::{IndexError, PublishContext};
 use freighter_api_types::ownership::response::{ChangedOwnership, OwnerList};
-use freighter_api_types::storage::StorageError;
+use freighter_api_types::storage::{BodyStream, StorageError};
+use freighter_auth::tokens::NewToken;
 use freighter_auth::AuthError;
+use futures_util::StreamExt;
 use metrics::counter;
 use semver::Version;
 use serde::Deserialize;
-use sha2::{Digest, Sha256};
-use std::sync::Arc;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 
 #[non_exhaustive]
 #[derive(Deserialize)]
@@ -30,6 +40,10 @@ pub fn api_router() -> Router<Arc<ServiceState>> {
         .route("/:crate_name/:version/yank", delete(yank))
         .route("/:crate_name/:version/unyank", put(unyank))
         .route("/:crate_name/owners", get(list_owners))
+        .route("/:crate_name", get(crate_details))
+        .route("/:crate_name/downloads", get(crate_downloads))
+        .route("/:crate_name/reverse_dependencies", get(reverse_dependencies))
+        .route("/:crate_name/:version", get(version_details))
         .route("/:crate_name/owners", delete(remove_owners))
         .route("/:crate_name/owners", put(add_owners))
         .route("/account", post(register))
@@ -39,146 +53,259 @@ pub fn api_router() -> Router<Arc<ServiceState>> {
 
 async fn publish(
     headers: HeaderMap,
+    source: Option<ConnectInfo<SocketAddr>>,
     State(state): State<Arc<ServiceState>>,
-    mut body: Bytes,
+    body: Body,
+) -> Response {
+    let mut record = audit::start(AuditOperation::Publish, source, None, None);
+    let res = publish_crate(&headers, &state, body, &mut record).await;
+    if res.is_ok()
+        && let (Some(name), Some(version)) = (&record.crate_name, &record.version)
+    {
+        git_index::update_crate(&state, name).await;
+        webhooks::notify(&state, &headers, WebhookEventKind::Publish, name, Some(version), None)
+            .await;
+    }
+    audit::finish(&state, &headers, record, res).await
+}
+
+/// The crate and version are added to the audit record once they're known
+async fn publish_crate(
+    headers: &HeaderMap,
+    state: &ServiceState,
+    body: Body,
+    record: &mut AuditRecord,
 ) -> axum::response::Result<Json<CompletedPublication>> {
     let auth = state
         .auth
-        .token_from_headers(&headers)?
+        .token_from_headers(headers)?
         .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
 
-    if body.len() <= 4 {
-        return Err((StatusCode::BAD_REQUEST, "Missing body").into());
-    }
+    let crate_size_limit = state.config.crate_size_limit;
+    let mut body = PublishBody::new(body);
 
-    let json_len_bytes = body.split_to(4);
-    let json_len = u32::from_le_bytes(json_len_bytes.as_ref().try_into().unwrap()) as usize;
+    let json_len = body
+        .read_u32()
+        .await?
+        .ok_or((StatusCode::BAD_REQUEST, "Missing body"))? as usize;
 
-    if body.len() < json_len {
-        return Err(StatusCode::BAD_REQUEST.into());
+    if json_len.saturating_add(8) > crate_size_limit {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "Crate metadata too large").into());
     }
 
-    let json_bytes = body.split_to(json_len);
-
-    if body.len() <= 4 {
-        return Err(StatusCode::BAD_REQUEST.into());
-    }
-
-    let crate_len_bytes = body.split_to(4);
-    let crate_len = u32::from_le_bytes(crate_len_bytes.as_ref().try_into().unwrap()) as usize;
-
-    if body.len() < crate_len {
-        return Err((StatusCode::BAD_REQUEST, "Crate data truncated").into());
-    }
-
-    let crate_bytes = body.split_to(crate_len);
+    let json_bytes = body
+        .read_exact(json_len)
+        .await?
+        .ok_or(StatusCode::BAD_REQUEST)?;
 
     let json: Publish = serde_json::from_slice(&json_bytes)
         .map_err(|_| (StatusCode::BAD_REQUEST, "JSON parsing error"))?;
+    record.crate_name = Some(json.name.clone());
+    record.version = Some(json.vers.clone());
+    asymmetric::check_publish(auth, &json.name, &json.vers, None)?;
 
-    let auth_result = state.auth.publish(auth, &json.name).await;
+    check_crate_name_policy(state, auth, &json.name).await?;
+    mirror::check_publish(state, &json.name)
+        .await
+        .map_err(publish_index_error)?;
 
-    if let Err(e) = &auth_result {
-        let error_label = match e {
-            AuthError::Unauthorized => "unauthorized",
-            AuthError::Forbidden => "forbidden",
-            AuthError::InvalidCredentials => "invalid_credentials",
-            AuthError::Unimplemented => "unimplemented",
-            AuthError::CrateNotFound => "crate_not_found",
-            AuthError::ServiceError(_) => "service_error",
-        };
+    state
+        .auth
+        .publish(auth, &json.name)
+        .await
+        .map_err(publish_auth_error)?;
+
+    let crate_len = body.read_u32().await?.ok_or(StatusCode::BAD_REQUEST)? as usize;
 
-        counter!("freighter_publish_auth_errors_total", "error" => error_label).increment(1);
+    if json_len + 8 + crate_len > crate_size_limit {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "Crate too large").into());
     }
 
-    auth_result?;
+    // The tarball is uploaded before the index is updated, so conflicts are checked upfront to
+    // avoid a pointless upload
+    match state.index.confirm_existence(&json.name, &json.vers).await {
+        Ok(_) => {
+            return Err(publish_index_error(IndexError::Conflict(format!(
+                "{}-{} already exists",
+                json.name, json.vers
+            )))
+            .into());
+        }
+        Err(IndexError::NotFound) => {}
+        Err(e) => return Err(publish_index_error(e).into()),
+    }
 
     let version = json.vers.to_string();
-    let mut stored_crate = false;
-    let tarball_checksum: [u8; 32] = Sha256::digest(&crate_bytes).into();
-
-    let res = {
-        let end_step = std::pin::pin!(async {
-            let res = state
-                .storage
-                .put_crate(&json.name, &version, crate_bytes, tarball_checksum)
-                .await;
-
-            if let Err(e) = &res {
-                let error_label = match e {
-                    StorageError::NotFound => "not_found",
-                    StorageError::ServiceError(_) => "service_error",
-                };
-
-                counter!("freighter_publish_tarballs_errors_total", "error" => error_label)
-                    .increment(1);
-            }
-
-            res.context("Failed to store the crate in a storage medium")?;
+    let body_error = Arc::new(Mutex::new(None));
+    let validator = TarballValidator::new(&json.name, &json.vers);
+    let crate_body = body.into_stream(crate_len, validator, body_error.clone());
+
+    let tarball_checksum = state
+        .storage
+        .put_crate_stream(&json.name, &version, crate_len as u64, crate_body)
+        .await
+        .map_err(|e| publish_storage_error(e, body_error.lock().unwrap().take()))?;
+
+    let cksum = hex::encode(tarball_checksum);
+    if let Err(e) = asymmetric::check_publish(auth, &json.name, &json.vers, Some(&cksum)) {
+        let _ = state
+            .storage
+            .delete_crate(&json.name, &version, tarball_checksum)
+            .await;
+        return Err(e.into());
+    }
 
-            stored_crate = true;
-            Ok(())
-        });
-        state.index.publish(&json, tarball_checksum, end_step).await
+    let context = PublishContext {
+        // not every auth backend knows the logins
+        publisher: state.auth.token_login(auth).await.ok(),
+        tarball_size: crate_len as u64,
     };
+    let res = state
+        .index
+        .publish(&json, tarball_checksum, &context, std::pin::pin!(async { Ok(()) }))
+        .await;
 
     match res {
-        Ok(res) => {
-            // publish() is never allowed to proceed without the end_step succeeding.
-            assert!(stored_crate);
-            Ok(Json(res))
-        }
+        Ok(res) => Ok(Json(res)),
         Err(e) => {
-            let error_label = match &e {
-                IndexError::Conflict(_) => "conflict",
-                IndexError::CrateNameNotAllowed => "crate_name_not_allowed",
-                IndexError::NotFound => "crate_not_found",
-                IndexError::ServiceError(_) => "service_error",
-            };
-
-            counter!("freighter_publish_index_errors_total", "error" => error_label).increment(1);
-
-            if stored_crate {
+            // Storage paths depend on the checksum, so if the same tarball has been published
+            // concurrently, the upload is now used by the index and must not be deleted
+            let in_use = matches!(e, IndexError::Conflict(_))
+                && state
+                    .index
+                    .confirm_existence(&json.name, &json.vers)
+                    .await
+                    .is_ok_and(|existing| existing.tarball_checksum == tarball_checksum);
+
+            if !in_use {
                 let _ = state
                     .storage
                     .delete_crate(&json.name, &version, tarball_checksum)
                     .await;
             }
-            Err(e.into())
+            Err(publish_index_error(e).into())
+        }
+    }
+}
+
+async fn check_crate_name_policy(
+    state: &ServiceState,
+    auth: &str,
+    crate_name: &str,
+) -> axum::response::Result<()> {
+    if let Some(allowed_users) = state
+        .config
+        .crate_name_policy
+        .check(crate_name)
+        .map_err(publish_index_error)?
+    {
+        let login = state.auth.token_login(auth).await?;
+        if !allowed_users.contains(&login) {
+            return Err(publish_index_error(IndexError::CrateNameNotAllowed(format!(
+                "`{crate_name}` is restricted to some users, and `{login}` isn't one of them"
+            )))
+            .into());
         }
     }
+    Ok(())
+}
+
+/// An error in the format of crates.io, which cargo displays to the user
+fn crates_io_error(status: StatusCode, detail: String) -> Response {
+    let body = serde_json::json!({ "errors": [{ "detail": detail }] });
+    (status, Json(body)).into_response()
+}
+
+fn publish_auth_error(e: AuthError) -> AuthError {
+    let error_label = match &e {
+        AuthError::Unauthorized => "unauthorized",
+        AuthError::Forbidden => "forbidden",
+        AuthError::InvalidCredentials => "invalid_credentials",
+        AuthError::Unimplemented => "unimplemented",
+        AuthError::CrateNotFound => "crate_not_found",
+        AuthError::UserNotFound => "user_not_found",
+        AuthError::TokenNotFound => "token_not_found",
+        AuthError::ServiceError(_) => "service_error",
+    };
+
+    counter!("freighter_publish_auth_errors_total", "error" => error_label).increment(1);
+    e
+}
+
+/// Invalid tarballs are the client's fault, and their error comes from the body stream
+fn publish_storage_error(e: StorageError, client_error: Option<String>) -> Response {
+    if let Some(msg) = client_error {
+        counter!("freighter_publish_tarballs_errors_total", "error" => "invalid").increment(1);
+
+        return crates_io_error(StatusCode::BAD_REQUEST, msg);
+    }
+
+    let error_label = match e {
+        StorageError::NotFound => "not_found",
+        StorageError::ServiceError(_) => "service_error",
+    };
+
+    counter!("freighter_publish_tarballs_errors_total", "error" => error_label).increment(1);
+    e.into_response()
+}
+
+fn publish_index_error(e: IndexError) -> IndexError {
+    let error_label = match &e {
+        IndexError::Conflict(_) => "conflict",
+        IndexError::CrateNameNotAllowed(_) => "crate_name_not_allowed",
+        IndexError::NotFound => "crate_not_found",
+        IndexError::ServiceError(_) => "service_error",
+    };
+
+    counter!("freighter_publish_index_errors_total", "error" => error_label).increment(1);
+    e
 }
 
 async fn yank(
     headers: HeaderMap,
+    source: Option<ConnectInfo<SocketAddr>>,
     State(state): State<Arc<ServiceState>>,
     Path((name, version)): Path<(String, Version)>,
-) -> axum::response::Result<Json<YankResult>> {
-    let auth = state
-        .auth
-        .token_from_headers(&headers)?
-        .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
-
-    state.auth.auth_yank(auth, &name).await?;
-
-    state.index.yank_crate(&name, &version).await?;
-
-    Ok(Json::default())
+) -> Response {
+    let record = audit::start(AuditOperation::Yank, source, Some(&name), Some(&version));
+    let res = set_yanked(&headers, &state, &name, &version, true).await;
+    audit::finish(&state, &headers, record, res).await
 }
 
 async fn unyank(
     headers: HeaderMap,
+    source: Option<ConnectInfo<SocketAddr>>,
     State(state): State<Arc<ServiceState>>,
     Path((name, version)): Path<(String, Version)>,
+) -> Response {
+    let record = audit::start(AuditOperation::Unyank, source, Some(&name), Some(&version));
+    let res = set_yanked(&headers, &state, &name, &version, false).await;
+    audit::finish(&state, &headers, record, res).await
+}
+
+async fn set_yanked(
+    headers: &HeaderMap,
+    state: &ServiceState,
+    name: &str,
+    version: &Version,
+    yanked: bool,
 ) -> axum::response::Result<Json<YankResult>> {
     let auth = state
         .auth
-        .token_from_headers(&headers)?
+        .token_from_headers(headers)?
         .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
 
-    state.auth.auth_yank(auth, &name).await?;
+    state.auth.auth_yank(auth, name).await?;
 
-    state.index.unyank_crate(&name, &version).await?;
+    let event = if yanked {
+        state.index.yank_crate(name, version).await?;
+        WebhookEventKind::Yank
+    } else {
+        state.index.unyank_crate(name, version).await?;
+        WebhookEventKind::Unyank
+    };
+    git_index::update_crate(state, name).await;
+    webhooks::notify(state, headers, event, name, Some(version), None).await;
 
     Ok(Json::default())
 }
@@ -200,64 +327,164 @@ async fn list_owners(
 
 async fn add_owners(
     headers: HeaderMap,
+    source: Option<ConnectInfo<SocketAddr>>,
     State(state): State<Arc<ServiceState>>,
     Path(name): Path<String>,
     Json(owners): Json<OwnerListChange>,
-) -> axum::response::Result<Json<ChangedOwnership>> {
-    let auth = state
-        .auth
-        .token_from_headers(&headers)?
-        .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
-
-    state
-        .auth
-        .add_owners(
-            auth,
-            &owners.users.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
-            &name,
-        )
-        .await?;
-
-    Ok(Json(ChangedOwnership::with_msg("owners successfully added".into())))
+) -> Response {
+    let mut record = audit::start(AuditOperation::AddOwners, source, Some(&name), None);
+    record.details = Some(owners.users.join(", "));
+    let res = change_owners(&headers, &state, &name, &owners, true).await;
+    audit::finish(&state, &headers, record, res).await
 }
 
 async fn remove_owners(
     headers: HeaderMap,
+    source: Option<ConnectInfo<SocketAddr>>,
     State(state): State<Arc<ServiceState>>,
     Path(name): Path<String>,
     Json(owners): Json<OwnerListChange>,
+) -> Response {
+    let mut record = audit::start(AuditOperation::RemoveOwners, source, Some(&name), None);
+    record.details = Some(owners.users.join(", "));
+    let res = change_owners(&headers, &state, &name, &owners, false).await;
+    audit::finish(&state, &headers, record, res).await
+}
+
+async fn change_owners(
+    headers: &HeaderMap,
+    state: &ServiceState,
+    name: &str,
+    owners: &OwnerListChange,
+    add: bool,
 ) -> axum::response::Result<Json<ChangedOwnership>> {
     let auth = state
         .auth
-        .token_from_headers(&headers)?
+        .token_from_headers(headers)?
         .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
 
-    state
-        .auth
-        .remove_owners(
-            auth,
-            &owners.users.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
-            &name,
-        )
-        .await?;
+    let users = owners.users.iter().map(|x| x.as_str()).collect::<Vec<_>>();
 
-    Ok(Json(ChangedOwnership::with_msg(
-        "owners successfully removed".into(),
-    )))
+    if add {
+        state.auth.add_owners(auth, &users, name).await?;
+        webhooks::notify(state, headers, WebhookEventKind::OwnersAdded, name, None, Some(&owners.users)).await;
+
+        Ok(Json(ChangedOwnership::with_msg("owners successfully added".into())))
+    } else {
+        state.auth.remove_owners(auth, &users, name).await?;
+        webhooks::notify(state, headers, WebhookEventKind::OwnersRemoved, name, None, Some(&owners.users)).await;
+
+        Ok(Json(ChangedOwnership::with_msg(
+            "owners successfully removed".into(),
+        )))
+    }
 }
 
 async fn register(
+    headers: HeaderMap,
+    source: Option<ConnectInfo<SocketAddr>>,
     State(state): State<Arc<ServiceState>>,
     Form(auth): Form<AuthForm>,
-) -> axum::response::Result<String> {
+) -> Response {
+    let mut record = audit::start(AuditOperation::Register, source, None, None);
+    record.actor = Some(auth.username.clone());
+    let res = register_user(&state, &auth.username).await;
+    audit::finish(&state, &headers, record, res).await
+}
+
+async fn register_user(state: &ServiceState, username: &str) -> axum::response::Result<String> {
     if !state.config.allow_registration {
         return Err((StatusCode::UNAUTHORIZED, "Registration disabled").into());
     }
 
-    let token = state.auth.register(&auth.username).await?;
+    let token = state.auth.register(username).await?;
     Ok(token)
 }
 
+#[derive(Deserialize)]
+pub struct PublicKeyRequest {
+    /// `k3.public.` PASERK, which `cargo login` prints for asymmetric tokens
+    pub key: String,
+}
+
+/// Lets the user of the token use cargo's asymmetric tokens signed with the key
+pub async fn register_public_key(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    Json(request): Json<PublicKeyRequest>,
+) -> axum::response::Result<StatusCode> {
+    let auth = state
+        .auth
+        .token_from_headers(&headers)?
+        .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+    state.auth.register_public_key(auth, &request.key).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct NewTokenRequest {
+    pub api_token: NewToken,
+}
+
+/// Creates another token of the same user, which can be restricted to some operations and crates
+pub async fn create_token(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    Json(request): Json<NewTokenRequest>,
+) -> axum::response::Result<Json<serde_json::Value>> {
+    let auth = state
+        .auth
+        .token_from_headers(&headers)?
+        .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+    let token = state.auth.create_token(auth, &request.api_token).await?;
+    Ok(Json(serde_json::json!({ "api_token": token })))
+}
+
+/// Lists the tokens of the user, without the tokens themselves
+pub async fn list_tokens(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+) -> axum::response::Result<Json<serde_json::Value>> {
+    let auth = state
+        .auth
+        .token_from_headers(&headers)?
+        .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+    let tokens = state.auth.list_tokens(auth).await?;
+    Ok(Json(serde_json::json!({ "api_tokens": tokens })))
+}
+
+pub async fn revoke_token(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    Path(id): Path<u32>,
+) -> axum::response::Result<Json<serde_json::Value>> {
+    let auth = state
+        .auth
+        .token_from_headers(&headers)?
+        .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+    state.auth.revoke_token(auth, id).await?;
+    Ok(Json(serde_json::json!({})))
+}
+
+#[derive(Deserialize)]
+pub struct TrustedPublishingTokenRequest {
+    /// OIDC ID token of the CI job
+    pub jwt: String,
+}
+
+/// Exchanges an ID token for a short-lived token that can publish the crates trusted by the policies
+pub async fn trusted_publishing_token(
+    State(state): State<Arc<ServiceState>>,
+    Json(request): Json<TrustedPublishingTokenRequest>,
+) -> axum::response::Result<Json<serde_json::Value>> {
+    let trusted = state
+        .trusted_publishing
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "Trusted publishing isn't configured"))?;
+    let token = trusted.exchange(&request.jwt).await?;
+    Ok(Json(serde_json::json!({ "token": token })))
+}
+
 async fn search(
     headers: HeaderMap,
     State(state): State<Arc<ServiceState>>,
@@ -272,17 +499,224 @@ async fn search(
         state.auth.auth_view_full_index(token).await?;
     }
 
-    let search_results = state
+    let mut search_results = state
         .index
         .search(&query.q, query.per_page.map_or(10, |x| x.max(100)))
         .await?;
+    let found = search_results.crates.len();
+    retain_readable(&state, &headers, &mut search_results.crates, |c| Some(&c.name)).await?;
+    search_results.meta.total -= found - search_results.crates.len();
 
     Ok(Json(search_results))
 }
 
+/// Removes crates hidden by the read ACL. Items without a crate name are kept.
+pub(crate) async fn retain_readable<T>(
+    state: &ServiceState,
+    headers: &HeaderMap,
+    crates: &mut Vec<T>,
+    crate_name: impl Fn(&T) -> Option<&str>,
+) -> axum::response::Result<()> {
+    if state.config.read_acl.is_none() {
+        return Ok(());
+    }
+    let token = state
+        .auth
+        .token_from_headers(headers)?
+        .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+    let mut readable = Vec::with_capacity(crates.len());
+    for name in crates.iter().map(crate_name) {
+        let Some(name) = name else {
+            readable.push(true);
+            continue;
+        };
+        match state.auth.auth_index_fetch(token, name).await {
+            Ok(()) => readable.push(true),
+            Err(AuthError::CrateNotFound) => readable.push(false),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let mut readable = readable.into_iter();
+    crates.retain(|_| readable.next().unwrap_or(false));
+    Ok(())
+}
+
+async fn crate_details(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    Path(name): Path<String>,
+) -> axum::response::Result<Json<CrateDetails>> {
+    if state.config.auth_required {
+        let token = state
+            .auth
+            .token_from_headers(&headers)?
+            .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+
+        state.auth.auth_index_fetch(token, &name).await?;
+    }
+
+    let details = state.index.get_crate_details(&name).await?;
+
+    Ok(Json(details))
+}
+
+async fn version_details(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    Path((name, version)): Path<(String, Version)>,
+) -> axum::response::Result<Json<VersionDetails>> {
+    if state.config.auth_required {
+        let token = state
+            .auth
+            .token_from_headers(&headers)?
+            .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+
+        state.auth.auth_index_fetch(token, &name).await?;
+    }
+
+    let details = state.index.get_version_details(&name, &version).await?;
+
+    Ok(Json(details))
+}
+
+async fn crate_downloads(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    Path(name): Path<String>,
+) -> axum::response::Result<Json<CrateDownloads>> {
+    if state.config.auth_required {
+        let token = state
+            .auth
+            .token_from_headers(&headers)?
+            .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+
+        state.auth.auth_index_fetch(token, &name).await?;
+    }
+
+    let downloads = state.index.get_downloads(&name).await?;
+
+    Ok(Json(downloads))
+}
+
+async fn reverse_dependencies(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    Path(name): Path<String>,
+) -> axum::response::Result<Json<ReverseDependencies>> {
+    if state.config.auth_required {
+        let token = state
+            .auth
+            .token_from_headers(&headers)?
+            .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+
+        state.auth.auth_index_fetch(token, &name).await?;
+    }
+
+    let mut dependencies = state.index.get_reverse_dependencies(&name).await?;
+    retain_readable(&state, &headers, &mut dependencies.dependencies, |d| Some(&d.krate)).await?;
+
+    Ok(Json(dependencies))
+}
+
 async fn handle_api_fallback() -> (StatusCode, &'static str) {
     (
         StatusCode::NOT_FOUND,
         "Freighter: Invalid URL for the crates.io API endpoint",
     )
 }
+
+/// Reads the length-prefixed parts of a publish request as they arrive
+struct PublishBody {
+    stream: BodyDataStream,
+    buf: Bytes,
+}
+
+impl PublishBody {
+    fn new(body: Body) -> Self {
+        Self {
+            stream: body.into_data_stream(),
+            buf: Bytes::new(),
+        }
+    }
+
+    /// Returns `None` if the body ends before `len` bytes
+    async fn read_exact(&mut self, len: usize) -> axum::response::Result<Option<Bytes>> {
+        if self.buf.len() >= len {
+            return Ok(Some(self.buf.split_to(len)));
+        }
+
+        let mut out = Vec::with_capacity(len);
+        out.extend_from_slice(&std::mem::take(&mut self.buf));
+        while out.len() < len {
+            let Some(chunk) = self.stream.next().await else {
+                return Ok(None);
+            };
+            let mut chunk =
+                chunk.map_err(|_| (StatusCode::BAD_REQUEST, "Failed to read the request body"))?;
+            let wanted = len - out.len();
+            if chunk.len() > wanted {
+                self.buf = chunk.split_off(wanted);
+            }
+            out.extend_from_slice(&chunk);
+        }
+        Ok(Some(out.into()))
+    }
+
+    async fn read_u32(&mut self) -> axum::response::Result<Option<u32>> {
+        let Some(bytes) = self.read_exact(4).await? else {
+            return Ok(None);
+        };
+        Ok(Some(u32::from_le_bytes(bytes.as_ref().try_into().unwrap())))
+    }
+
+    /// Streams the next `len` bytes of the body, checking them with the `validator`.
+    ///
+    /// The stream fails if the body is cut short or the tarball is invalid, and the reason is put
+    /// in `error` to tell the client's mistakes apart from storage errors.
+    fn into_stream(
+        self,
+        len: usize,
+        validator: TarballValidator,
+        error: Arc<Mutex<Option<String>>>,
+    ) -> BodyStream {
+        let chunks = futures_util::stream::iter([Ok(self.buf)]).chain(self.stream);
+
+        Box::pin(futures_util::stream::unfold(
+            (chunks, len, Some(validator)),
+            move |(mut chunks, remaining, mut validator)| {
+                let error = error.clone();
+                async move {
+                    // the validator is gone after the stream ends
+                    let mut validator = validator.take()?;
+                    let msg = if remaining == 0 {
+                        match validator.finish().await {
+                            Ok(()) => return None,
+                            Err(msg) => msg,
+                        }
+                    } else {
+                        match chunks.next().await {
+                            Some(Ok(mut chunk)) => {
+                                chunk.truncate(remaining);
+                                match validator.feed(chunk.clone()).await {
+                                    Ok(()) => {
+                                        let remaining = remaining - chunk.len();
+                                        return Some((
+                                            Ok(chunk),
+                                            (chunks, remaining, Some(validator)),
+                                        ));
+                                    }
+                                    Err(msg) => msg,
+                                }
+                            }
+                            Some(Err(_)) => "Failed to read the request body".into(),
+                            None => "Crate data truncated".into(),
+                        }
+                    };
+                    let err = io::Error::other(msg.clone());
+                    *error.lock().unwrap() = Some(msg);
+                    Some((Err(err), (chunks, 0, None)))
+                }
+            },
+        ))
+    }
+}
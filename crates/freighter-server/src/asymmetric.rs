@@ -0,0 +1,118 @@
+//! Cargo's asymmetric tokens are signed for a single request. Backends check the signatures,
+//! and the claims are checked against the requests here.
+
+use crate::ServiceState;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, TimeDelta, Utc};
+use freighter_auth::asymmetric::{AsymmetricToken, Claims};
+use semver::Version;
+use std::sync::Arc;
+
+/// Cargo makes a new token for every request
+const MAX_TOKEN_AGE: TimeDelta = TimeDelta::minutes(5);
+
+/// The mutation a request makes, which the token must be for
+#[derive(Debug, PartialEq)]
+enum Mutation<'a> {
+    Publish,
+    Yank { name: &'a str, vers: &'a str },
+    Unyank { name: &'a str, vers: &'a str },
+    Owners { name: &'a str },
+}
+
+impl<'a> Mutation<'a> {
+    fn of_request(method: &Method, path: &'a str) -> Option<Self> {
+        let segments: Vec<_> = path.strip_prefix("/api/v1/crates/")?.split('/').collect();
+        match (method, &segments[..]) {
+            (&Method::PUT, ["new"]) => Some(Self::Publish),
+            (&Method::DELETE, [name, vers, "yank"]) => Some(Self::Yank { name, vers }),
+            (&Method::PUT, [name, vers, "unyank"]) => Some(Self::Unyank { name, vers }),
+            (&Method::PUT | &Method::DELETE, [name, "owners"]) => Some(Self::Owners { name }),
+            _ => None,
+        }
+    }
+
+    fn check(expected: Option<&Self>, claims: &Claims) -> bool {
+        let (mutation, name, vers) = match expected {
+            None => return claims.mutation.is_none(),
+            // the name and version are in the body, and are checked when the body is read
+            Some(Self::Publish) => return claims.mutation.as_deref() == Some("publish"),
+            Some(Self::Yank { name, vers }) => ("yank", *name, Some(*vers)),
+            Some(Self::Unyank { name, vers }) => ("unyank", *name, Some(*vers)),
+            Some(Self::Owners { name }) => ("owners", *name, None),
+        };
+        claims.mutation.as_deref() == Some(mutation)
+            && claims.name.as_deref() == Some(name)
+            && vers.is_none_or(|vers| same_version(claims.vers.as_deref(), vers))
+    }
+}
+
+fn same_version(claim: Option<&str>, vers: &str) -> bool {
+    claim
+        .and_then(|c| Version::parse(c).ok())
+        .is_some_and(|c| Version::parse(vers).is_ok_and(|v| v == c))
+}
+
+/// Index URLs of this registry, without `sparse+` or the trailing `/`
+fn index_urls(state: &ServiceState) -> impl Iterator<Item = String> {
+    let api = state.config.api_endpoint.trim_end_matches('/');
+    let git_index = state.config.git_index.as_ref().map(|_| format!("{api}/git/index"));
+    std::iter::once(format!("{api}/index")).chain(git_index)
+}
+
+/// Rejects asymmetric tokens made for another registry or another request, or too long ago
+pub(crate) async fn check_request(State(state): State<Arc<ServiceState>>, request: Request<Body>, next: Next) -> Response {
+    let Ok(Some(token)) = state.auth.token_from_headers(request.headers()) else {
+        return next.run(request).await;
+    };
+    if !AsymmetricToken::is_asymmetric(token) {
+        return next.run(request).await;
+    }
+    let token = match AsymmetricToken::parse(token) {
+        Ok(token) => token,
+        Err(e) => return e.into_response(),
+    };
+
+    let url = token.footer.url.strip_prefix("sparse+").unwrap_or(&token.footer.url);
+    if !index_urls(&state).any(|u| u == url.trim_end_matches('/')) {
+        return (StatusCode::UNAUTHORIZED, "The token is for another registry").into_response();
+    }
+
+    let now = Utc::now();
+    let fresh = DateTime::parse_from_rfc3339(&token.claims.iat)
+        .is_ok_and(|iat| iat <= now + TimeDelta::minutes(1) && now - iat.with_timezone(&Utc) <= MAX_TOKEN_AGE);
+    if !fresh {
+        return (StatusCode::UNAUTHORIZED, "The token has expired").into_response();
+    }
+
+    let expected = Mutation::of_request(request.method(), request.uri().path());
+    // keys and tokens can't be managed with a token signed for reading the registry
+    if expected.is_none() && !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return (StatusCode::UNAUTHORIZED, "Asymmetric tokens can only be used for cargo's requests").into_response();
+    }
+    if !Mutation::check(expected.as_ref(), &token.claims) {
+        return (StatusCode::UNAUTHORIZED, "The token is for another request").into_response();
+    }
+    next.run(request).await
+}
+
+/// Checks that an asymmetric token is for publishing this version, after the body has been read
+pub(crate) fn check_publish(token: &str, name: &str, vers: &Version, cksum: Option<&str>) -> Result<(), StatusCode> {
+    if !AsymmetricToken::is_asymmetric(token) {
+        return Ok(());
+    }
+    let token = AsymmetricToken::parse(token).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let claims = &token.claims;
+    if claims.name.as_deref() == Some(name)
+        && same_version(claims.vers.as_deref(), &vers.to_string())
+        && cksum.is_none_or(|cksum| claims.cksum.as_deref() == Some(cksum))
+    {
+        Ok(())
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
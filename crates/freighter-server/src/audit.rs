@@ -0,0 +1,170 @@
+use crate::ServiceState;
+use anyhow::Context;
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::Utc;
+use freighter_api_types::audit::{
+    AuditOperation, AuditOutcome, AuditQuery, AuditRecord, AuditRecords, AuditSink,
+};
+use metrics::counter;
+use semver::Version;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Appends the audit log to a file, as JSON lines.
+///
+/// Queries read the whole file, so it should be rotated if it gets big.
+/// The file is written and read on the blocking thread pool, since every record is synced to disk.
+pub struct FileAuditSink {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+impl FileAuditSink {
+    pub fn new(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+}
+
+#[async_trait]
+impl AuditSink for FileAuditSink {
+    async fn record(&self, record: &AuditRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let file = Arc::clone(&self.file);
+        tokio::task::spawn_blocking(move || {
+            // a single write, so that lines aren't interleaved
+            let mut file = file.lock().unwrap();
+            file.write_all(&line)?;
+            file.sync_data()
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn query(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditRecord>> {
+        let log = tokio::fs::read(&self.path).await?;
+
+        let mut records = Vec::new();
+        for line in log.split(|&c| c == b'\n').rev().filter(|line| !line.is_empty()) {
+            let record: AuditRecord =
+                serde_json::from_slice(line).context("invalid audit log line")?;
+            if query.matches(&record) {
+                records.push(record);
+                if records.len() >= query.limit() {
+                    break;
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Starts a record of an operation, which is saved by [`finish`] once its outcome is known.
+pub(crate) fn start(
+    operation: AuditOperation,
+    source: Option<ConnectInfo<SocketAddr>>,
+    crate_name: Option<&str>,
+    version: Option<&Version>,
+) -> AuditRecord {
+    AuditRecord {
+        timestamp: Utc::now(),
+        operation,
+        actor: None,
+        token_id: None,
+        crate_name: crate_name.map(From::from),
+        version: version.cloned(),
+        source_ip: source.map(|ConnectInfo(addr)| addr.ip()),
+        outcome: AuditOutcome::Failure,
+        status: 0,
+        details: None,
+    }
+}
+
+/// Saves the record with the outcome of the operation, if the audit log is enabled.
+///
+/// Failing to save it doesn't fail the request, since the operation has already happened.
+pub(crate) async fn finish(
+    state: &ServiceState,
+    headers: &HeaderMap,
+    mut record: AuditRecord,
+    res: impl IntoResponse,
+) -> Response {
+    let response = res.into_response();
+    let Some(sink) = &state.audit else {
+        return response;
+    };
+
+    let status = response.status();
+    record.status = status.as_u16();
+    record.outcome = if status.is_success() {
+        AuditOutcome::Success
+    } else {
+        AuditOutcome::Failure
+    };
+
+    if let Ok(Some(token)) = state.auth.token_from_headers(headers) {
+        record.token_id = Some(token_id(token));
+        if record.actor.is_none() {
+            record.actor = state.auth.token_login(token).await.ok();
+        }
+    }
+
+    if let Err(e) = sink.record(&record).await {
+        tracing::error!(%e, ?record, "Failed to save audit record");
+        counter!("freighter_audit_errors_total").increment(1);
+    }
+
+    response
+}
+
+/// Start of the token's SHA-256, which identifies the token without revealing it
+fn token_id(token: &str) -> String {
+    hex::encode(&Sha256::digest(token.as_bytes())[..8])
+}
+
+pub async fn query_audit_log(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    Query(query): Query<AuditQuery>,
+) -> axum::response::Result<Json<AuditRecords>> {
+    let token = state
+        .auth
+        .token_from_headers(&headers)?
+        .ok_or((StatusCode::UNAUTHORIZED, "Auth token missing"))?;
+    state.auth.auth_view_full_index(token).await?;
+
+    let sink = state
+        .audit
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "Freighter: The audit log is not enabled"))?;
+
+    let mut records = sink.query(&query).await.map_err(|e| {
+        tracing::error!(%e, "Failed to query audit log");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    crate::api::retain_readable(&state, &headers, &mut records, |r| r.crate_name.as_deref()).await?;
+
+    Ok(Json(AuditRecords { records }))
+}
@@ -0,0 +1,431 @@
+//! Backups of the whole registry, made only through the provider traits, so that they can be
+//! restored into any combination of backends.
+//!
+//! The archive is a gzipped tarball containing, in this order:
+//!
+//! * `freighter-backup.json`, identifying the format of the archive,
+//! * `auth.json`, with the owners and tokens, if the auth backend supports backups,
+//! * for every crate, `crates/{name}.json` with its index entries and metadata, followed by its
+//!   tarballs in `crates/{name}/{version}.crate`.
+//!
+//! [`migrate_index`] copies crates between index backends the same way, without the archive.
+
+use crate::index::index_file;
+use anyhow::{bail, ensure, Context};
+use axum::body::Bytes;
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use freighter_api_types::index::request::{ListQuery, Publish};
+use freighter_api_types::index::response::{CrateVersion, ListAllCrateEntry};
+use freighter_api_types::index::{DownloadCount, IndexError, IndexProvider, PublishContext};
+use freighter_api_types::storage::{StorageError, StorageProvider};
+use freighter_auth::{AuthBackup, AuthError, AuthProvider};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+
+const MANIFEST_PATH: &str = "freighter-backup.json";
+const AUTH_PATH: &str = "auth.json";
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BackupStats {
+    pub crates: usize,
+    /// Versions written to the archive, or added to the index
+    pub versions: usize,
+    /// Versions left out of the archive because their tarball isn't in storage,
+    /// or not imported because they're already in the index
+    pub skipped_versions: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CrateBackup {
+    name: String,
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    /// In the order they were published
+    versions: Vec<VersionBackup>,
+    #[serde(default)]
+    downloads: Vec<DownloadsBackup>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VersionBackup {
+    entry: CrateVersion,
+    description: Option<String>,
+    license: Option<String>,
+    license_file: Option<String>,
+    homepage: Option<String>,
+    documentation: Option<String>,
+    repository: Option<String>,
+    published_by: Option<String>,
+    /// Size of the tarball in bytes
+    #[serde(default)]
+    crate_size: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DownloadsBackup {
+    version: Version,
+    date: NaiveDate,
+    downloads: u64,
+}
+
+/// Writes all crates, their tarballs, and the owners and tokens to the archive.
+///
+/// Versions whose tarball isn't in storage, like mirrored crates that were never downloaded,
+/// are left out.
+pub async fn export(
+    index: &(dyn IndexProvider + Send + Sync),
+    storage: &(dyn StorageProvider + Send + Sync),
+    auth: &(dyn AuthProvider + Send + Sync),
+    output: impl Write,
+) -> anyhow::Result<BackupStats> {
+    let mut stats = BackupStats::default();
+    let mut archive = tar::Builder::new(GzEncoder::new(output, Compression::default()));
+
+    let manifest = Manifest {
+        format: FORMAT_VERSION,
+        created_at: Utc::now(),
+    };
+    append(&mut archive, MANIFEST_PATH, &serde_json::to_vec(&manifest)?)?;
+
+    match auth.export_backup().await {
+        Ok(backup) => append(&mut archive, AUTH_PATH, &serde_json::to_vec(&backup)?)?,
+        Err(AuthError::Unimplemented) => {
+            tracing::warn!("The auth backend doesn't support backups, owners and tokens are not exported");
+        }
+        Err(e) => return Err(e).context("Failed to export owners and tokens"),
+    }
+
+    let crates = list_crates(index).await?;
+
+    for krate in &crates {
+        let mut backup = read_crate(index, krate).await?;
+
+        let mut tarballs = Vec::with_capacity(backup.versions.len());
+        let mut versions = Vec::with_capacity(backup.versions.len());
+        for mut v in backup.versions {
+            let name = &backup.name;
+            let vers = v.entry.vers.to_string();
+            let tarball = match storage.pull_crate(name, &vers, v.entry.cksum).await {
+                Ok(res) => res.data,
+                Err(StorageError::NotFound) => {
+                    tracing::warn!(crate_name = %name, version = %vers, "Tarball is not in storage, skipping the version");
+                    stats.skipped_versions += 1;
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to get tarball of {name}-{vers}"));
+                }
+            };
+            v.crate_size = Some(tarball.len() as u64);
+            tarballs.push((format!("crates/{name}/{vers}.crate"), tarball));
+            versions.push(v);
+        }
+        backup.versions = versions;
+
+        stats.crates += 1;
+        stats.versions += backup.versions.len();
+        append(
+            &mut archive,
+            &format!("crates/{}.json", backup.name),
+            &serde_json::to_vec(&backup)?,
+        )?;
+        for (path, tarball) in tarballs {
+            append(&mut archive, &path, &tarball)?;
+        }
+    }
+
+    archive.into_inner()?.finish()?.flush()?;
+    Ok(stats)
+}
+
+/// Restores an archive made by [`export`]. Versions that are already in the index are skipped,
+/// so an interrupted import can be run again.
+///
+/// Tarballs are verified and stored before their versions are added to the index.
+pub async fn import(
+    index: &(dyn IndexProvider + Send + Sync),
+    storage: &(dyn StorageProvider + Send + Sync),
+    auth: &(dyn AuthProvider + Send + Sync),
+    input: impl Read,
+) -> anyhow::Result<BackupStats> {
+    let mut stats = BackupStats::default();
+    let mut archive = tar::Archive::new(GzDecoder::new(input));
+    let mut manifest_read = false;
+    // the crate whose tarballs are being read, and the sizes of the ones stored so far
+    let mut pending: Option<(CrateBackup, HashMap<Version, u64>)> = None;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+
+        if !manifest_read {
+            ensure!(path == MANIFEST_PATH, "This is not a freighter backup");
+            let manifest: Manifest = serde_json::from_slice(&data)?;
+            ensure!(
+                manifest.format == FORMAT_VERSION,
+                "Unsupported backup format {}",
+                manifest.format
+            );
+            manifest_read = true;
+            continue;
+        }
+
+        if path == AUTH_PATH {
+            let backup: AuthBackup = serde_json::from_slice(&data)?;
+            match auth.import_backup(&backup).await {
+                Ok(()) => {}
+                Err(AuthError::Unimplemented) => {
+                    tracing::warn!("The auth backend doesn't support backups, owners and tokens are not imported");
+                }
+                Err(e) => return Err(e).context("Failed to import owners and tokens"),
+            }
+        } else if let Some(name) = path
+            .strip_prefix("crates/")
+            .and_then(|p| p.strip_suffix(".json"))
+            .filter(|n| !n.contains('/'))
+        {
+            if let Some((krate, stored)) = pending.take() {
+                restore_stored_crate(index, krate, &stored, &mut stats).await?;
+            }
+            let krate: CrateBackup = serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid metadata of {name}"))?;
+            pending = Some((krate, HashMap::new()));
+        } else if let Some((name, vers)) = path
+            .strip_prefix("crates/")
+            .and_then(|p| p.strip_suffix(".crate"))
+            .and_then(|p| p.split_once('/'))
+        {
+            let Some((krate, stored)) = pending.as_mut().filter(|(k, _)| k.name == name) else {
+                bail!("Tarball of {name}-{vers} is not after the metadata of its crate");
+            };
+            let version = Version::parse(vers)?;
+            let Some(v) = krate.versions.iter().find(|v| v.entry.vers == version) else {
+                bail!("Tarball of {name}-{vers} is not in the index");
+            };
+
+            let size = data.len() as u64;
+            let cksum: [u8; 32] = Sha256::digest(&data).into();
+            ensure!(cksum == v.entry.cksum, "{name}-{vers} doesn't match its checksum");
+            storage
+                .put_crate(name, &version.to_string(), Bytes::from(data), cksum)
+                .await
+                .with_context(|| format!("Failed to store tarball of {name}-{vers}"))?;
+            stored.insert(version, size);
+        } else {
+            tracing::warn!(path, "Unknown file in the backup");
+        }
+    }
+
+    ensure!(manifest_read, "The backup is empty");
+    if let Some((krate, stored)) = pending {
+        restore_stored_crate(index, krate, &stored, &mut stats).await?;
+    }
+    Ok(stats)
+}
+
+/// Copies every crate into another index, replaying the versions in publish order, and verifies
+/// that the index entries are then the same.
+///
+/// Versions that are already in the other index are skipped, so it can be run again.
+pub async fn migrate_index(
+    from: &(dyn IndexProvider + Send + Sync),
+    to: &(dyn IndexProvider + Send + Sync),
+) -> anyhow::Result<BackupStats> {
+    let mut stats = BackupStats::default();
+    let crates = list_crates(from).await?;
+    for krate in &crates {
+        let backup = read_crate(from, krate).await?;
+        restore_crate(to, backup, &mut stats).await?;
+    }
+
+    let mut mismatched = Vec::new();
+    for krate in crates {
+        if index_file_of(from, &krate.name).await? != index_file_of(to, &krate.name).await? {
+            mismatched.push(krate.name);
+        }
+    }
+    ensure!(
+        mismatched.is_empty(),
+        "Index entries are different after the migration: {}",
+        mismatched.join(", ")
+    );
+    Ok(stats)
+}
+
+async fn index_file_of(index: &(dyn IndexProvider + Send + Sync), crate_name: &str) -> anyhow::Result<Vec<u8>> {
+    let mut entries = index
+        .get_sparse_entry(crate_name)
+        .await
+        .with_context(|| format!("Failed to get index entry of {crate_name}"))?
+        .entries;
+    Ok(index_file(&mut entries)?)
+}
+
+async fn list_crates(index: &(dyn IndexProvider + Send + Sync)) -> anyhow::Result<Vec<ListAllCrateEntry>> {
+    match index.list(&ListQuery { per_page: None, page: None }).await {
+        Ok(crates) => Ok(crates.results),
+        // empty index
+        Err(IndexError::NotFound) => Ok(Vec::new()),
+        Err(e) => Err(e).context("Failed to list crates"),
+    }
+}
+
+/// Index entries, metadata and download counts of all versions of the crate
+async fn read_crate(
+    index: &(dyn IndexProvider + Send + Sync),
+    krate: &ListAllCrateEntry,
+) -> anyhow::Result<CrateBackup> {
+    let name = &krate.name;
+    let entries = index
+        .get_sparse_entry(name)
+        .await
+        .with_context(|| format!("Failed to get index entry of {name}"))?
+        .entries;
+
+    let mut versions = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let details = index
+            .get_version_details(name, &entry.vers)
+            .await
+            .with_context(|| format!("Failed to get metadata of {name}-{}", entry.vers))?
+            .version;
+        versions.push(VersionBackup {
+            entry,
+            description: details.description,
+            license: details.license,
+            license_file: details.license_file,
+            homepage: details.homepage,
+            documentation: details.documentation,
+            repository: details.repository,
+            published_by: details.published_by.map(|p| p.login),
+            crate_size: details.crate_size,
+        });
+    }
+
+    let downloads = index
+        .get_downloads(name)
+        .await
+        .with_context(|| format!("Failed to get download counts of {name}"))?
+        .version_downloads
+        .into_iter()
+        .map(|d| DownloadsBackup {
+            version: d.version,
+            date: d.date,
+            downloads: d.downloads,
+        })
+        .collect();
+
+    Ok(CrateBackup {
+        name: name.clone(),
+        keywords: krate.keywords.clone(),
+        categories: krate.categories.clone(),
+        versions,
+        downloads,
+    })
+}
+
+/// Restores the crate after its tarballs have been stored
+async fn restore_stored_crate(
+    index: &(dyn IndexProvider + Send + Sync),
+    mut krate: CrateBackup,
+    stored: &HashMap<Version, u64>,
+    stats: &mut BackupStats,
+) -> anyhow::Result<()> {
+    for v in &mut krate.versions {
+        let Some(&tarball_size) = stored.get(&v.entry.vers) else {
+            bail!("Tarball of {}-{} is missing from the backup", krate.name, v.entry.vers);
+        };
+        v.crate_size = Some(tarball_size);
+    }
+    restore_crate(index, krate, stats).await
+}
+
+async fn restore_crate(
+    index: &(dyn IndexProvider + Send + Sync),
+    krate: CrateBackup,
+    stats: &mut BackupStats,
+) -> anyhow::Result<()> {
+    let mut imported = HashSet::new();
+    for v in krate.versions {
+        let name = v.entry.name.clone();
+        let vers = v.entry.vers.clone();
+        let cksum = v.entry.cksum;
+        let yanked = v.entry.yanked;
+
+        let publish = Publish {
+            description: v.description,
+            license: v.license,
+            license_file: v.license_file,
+            homepage: v.homepage,
+            documentation: v.documentation,
+            repository: v.repository,
+            keywords: krate.keywords.clone(),
+            categories: krate.categories.clone(),
+            ..Publish::from(v.entry)
+        };
+        let context = PublishContext {
+            publisher: v.published_by,
+            tarball_size: v.crate_size.unwrap_or_default(),
+        };
+        match index
+            .publish(&publish, cksum, &context, std::pin::pin!(async { Ok(()) }))
+            .await
+        {
+            Ok(_) => {}
+            Err(IndexError::Conflict(_)) => {
+                stats.skipped_versions += 1;
+                continue;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to publish {name}-{vers}")),
+        }
+        if yanked {
+            index.yank_crate(&name, &vers).await?;
+        }
+        stats.versions += 1;
+        imported.insert(vers);
+    }
+
+    // counts of skipped versions are already in the index
+    let downloads: Vec<_> = krate
+        .downloads
+        .into_iter()
+        .filter(|d| imported.contains(&d.version))
+        .map(|d| DownloadCount {
+            name: krate.name.clone(),
+            version: d.version,
+            date: d.date,
+            downloads: d.downloads,
+        })
+        .collect();
+    if !downloads.is_empty() {
+        index.record_downloads(&downloads).await?;
+    }
+
+    stats.crates += 1;
+    Ok(())
+}
+
+fn append(archive: &mut tar::Builder<impl Write>, path: &str, data: &[u8]) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().try_into().unwrap_or_default());
+    archive.append_data(&mut header, path, data)
+}
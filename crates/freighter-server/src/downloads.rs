@@ -1,10 +1,18 @@
-use crate::ServiceState;
+use crate::{mirror, ServiceState};
+use axum::body::Body;
 use axum::extract::{Path, State};
 use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Redirect};
 use axum::routing::get;
 use axum::Router;
+use chrono::{NaiveDate, Utc};
+use freighter_api_types::index::{DownloadCount, IndexProvider};
+use metrics::counter;
 use semver::Version;
-use std::sync::Arc;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 pub fn downloads_router() -> Router<Arc<ServiceState>> {
     Router::new()
@@ -25,14 +33,45 @@ async fn serve_crate(
         state.auth.auth_crate_download(token, &name).await?;
     }
 
+    mirror::sync_index(&state, &name).await;
+
     let expected_crate = state.index.confirm_existence(&name, &version).await?;
 
-    let crate_res = state
-        .storage
-        .pull_crate(&name, &version.to_string(), expected_crate.tarball_checksum)
-        .await?;
+    state.download_counts.record(&name, &version);
+
+    // mirrored tarballs may not have been copied to storage yet
+    let mirrored =
+        mirror::pull_tarball(&state, &name, &version, expected_crate.tarball_checksum).await?;
+
+    if mirrored.is_none()
+        && let Some(ttl) = state.config.presigned_download_ttl_secs
+        && let Some(url) = state
+            .storage
+            .presigned_crate_url(
+                &name,
+                &version.to_string(),
+                expected_crate.tarball_checksum,
+                Duration::from_secs(ttl),
+            )
+            .await?
+    {
+        return Ok(Redirect::temporary(&url).into_response());
+    }
+
+    let crate_res = match mirrored {
+        Some(res) => res.into(),
+        None => {
+            state
+                .storage
+                .stream_crate(&name, &version.to_string(), expected_crate.tarball_checksum)
+                .await?
+        }
+    };
 
-    let mut res = axum::response::Response::new(crate_res.data.into());
+    let mut res = axum::response::Response::new(Body::from_stream(crate_res.body));
+    if let Some(len) = crate_res.content_length {
+        res.headers_mut().insert(header::CONTENT_LENGTH, len.into());
+    }
     if let Some(last_mod) = crate_res.last_modified.and_then(|d| d.to_rfc2822().try_into().ok()) {
         res.headers_mut().insert(header::LAST_MODIFIED, last_mod);
     }
@@ -40,6 +79,67 @@ async fn serve_crate(
     Ok(res)
 }
 
+/// Download counts that haven't been saved to the index yet.
+///
+/// Counting every download in the index would be too slow, so they're added up in memory,
+/// and saved periodically by [`DownloadCounter::flush`].
+#[derive(Default)]
+pub struct DownloadCounter {
+    pending: Mutex<HashMap<(String, Version, NaiveDate), u64>>,
+}
+
+impl DownloadCounter {
+    pub fn record(&self, name: &str, version: &Version) {
+        let today = Utc::now().date_naive();
+        *self
+            .pending
+            .lock()
+            .unwrap()
+            .entry((name.to_owned(), version.clone(), today))
+            .or_default() += 1;
+    }
+
+    /// Counts that haven't been saved yet
+    #[must_use]
+    pub fn pending(&self) -> Vec<DownloadCount> {
+        let pending = self.pending.lock().unwrap();
+        pending
+            .iter()
+            .map(|((name, version, date), &downloads)| DownloadCount {
+                name: name.clone(),
+                version: version.clone(),
+                date: *date,
+                downloads,
+            })
+            .collect()
+    }
+
+    /// Saves the counts to the index. If that fails, they're kept to be saved next time.
+    pub async fn flush(&self, index: &(dyn IndexProvider + Send + Sync)) {
+        let counts = self.pending();
+        if counts.is_empty() {
+            return;
+        }
+
+        // counts are subtracted only if saved, so that downloads counted meanwhile aren't lost
+        if let Err(e) = index.record_downloads(&counts).await {
+            tracing::error!(%e, "Failed to save download counts");
+            counter!("freighter_download_counts_errors_total").increment(1);
+            return;
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        for count in counts {
+            if let Entry::Occupied(mut e) = pending.entry((count.name, count.version, count.date)) {
+                *e.get_mut() -= count.downloads;
+                if *e.get() == 0 {
+                    e.remove();
+                }
+            }
+        }
+    }
+}
+
 async fn handle_downloads_fallback() -> (StatusCode, &'static str) {
     (
         StatusCode::NOT_FOUND,
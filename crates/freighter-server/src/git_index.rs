@@ -0,0 +1,368 @@
+//! The index as a git repository, for cargo older than 1.68 and other tools that can't use
+//! the sparse protocol.
+//!
+//! The repository is kept by the `git` command, and updated from the [`IndexProvider`] whenever
+//! a crate changes, so that it works with any index backend. Instances that share the index
+//! don't see each other's changes, so the repository is also rebuilt periodically.
+//!
+//! [`IndexProvider`]: freighter_api_types::index::IndexProvider
+
+use crate::index::index_file;
+use crate::ServiceState;
+use anyhow::Context;
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use freighter_api_types::index::request::ListQuery;
+use freighter_api_types::index::response::RegistryConfig;
+use freighter_api_types::index::IndexError;
+use metrics::counter;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::MissedTickBehavior;
+
+const BRANCH: &str = "refs/heads/master";
+
+#[derive(Clone, Deserialize)]
+pub struct GitIndexConfig {
+    /// Bare git repository with the index. It's created if it doesn't exist.
+    pub path: PathBuf,
+
+    /// How often the repository is rebuilt from the index, to get changes made by other
+    /// instances of Freighter that share the index
+    #[serde(default = "default_refresh_secs")]
+    pub refresh_secs: u64,
+}
+
+/// Serializes updates of the repository
+#[derive(Default)]
+pub struct GitIndex {
+    lock: tokio::sync::Mutex<()>,
+}
+
+pub fn git_index_router() -> Router<Arc<ServiceState>> {
+    Router::new()
+        .route("/info/refs", get(info_refs))
+        .route("/git-upload-pack", post(upload_pack))
+        .fallback(handle_git_index_fallback)
+}
+
+/// A change of an index file. `None` deletes the file.
+type FileChange = (String, Option<Vec<u8>>);
+
+/// Updates the crate's index file from the index. Failures are only logged, since the index has
+/// been changed already. The repository is fixed by the next [`rebuild`].
+pub(crate) async fn update_crate(state: &ServiceState, crate_name: &str) {
+    let Some(config) = &state.config.git_index else {
+        return;
+    };
+
+    let _lock = state.git_index.lock.lock().await;
+    if let Err(e) = update_crate_file(state, &config.path, crate_name).await {
+        tracing::error!(?e, crate_name, "Failed to update the git index");
+        counter!("freighter_git_index_errors_total").increment(1);
+    }
+}
+
+async fn update_crate_file(state: &ServiceState, repo: &Path, crate_name: &str) -> anyhow::Result<()> {
+    let (path, contents) = match state.index.get_sparse_entry(crate_name).await {
+        // the request may spell the name differently, e.g. with `_` instead of `-`
+        Ok(mut entries) => {
+            let path = entries.entries.first().map_or_else(|| index_path(crate_name), |e| index_path(&e.name));
+            (path, Some(index_file(&mut entries.entries)?))
+        },
+        Err(IndexError::NotFound) => (index_path(crate_name), None),
+        Err(e) => return Err(e.into()),
+    };
+
+    commit(repo, &format!("Update {crate_name}"), false, vec![(path, contents)]).await
+}
+
+/// Creates the repository if needed, and makes its files match the index
+pub async fn rebuild(state: &ServiceState) -> anyhow::Result<()> {
+    let Some(config) = &state.config.git_index else {
+        return Ok(());
+    };
+    let _lock = state.git_index.lock.lock().await;
+
+    if !config.path.join("HEAD").exists() {
+        std::fs::create_dir_all(&config.path)?;
+        let init = Command::new("git")
+            .args(["init", "--quiet", "--bare", "--initial-branch=master"])
+            .arg(&config.path)
+            .output()
+            .await
+            .context("Failed to run git")?;
+        anyhow::ensure!(init.status.success(), "git init failed: {}", String::from_utf8_lossy(&init.stderr));
+    }
+
+    let registry_config = RegistryConfig {
+        dl: state.config.download_endpoint.clone(),
+        api: state.config.api_endpoint.clone(),
+        auth_required: state.config.auth_required,
+    };
+    let mut files = vec![("config.json".to_owned(), Some(serde_json::to_vec_pretty(&registry_config)?))];
+
+    let crates = match state.index.list(&ListQuery { per_page: None, page: None }).await {
+        Ok(crates) => crates.results,
+        // empty index
+        Err(IndexError::NotFound) => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    for krate in crates {
+        let mut entries = state.index.get_sparse_entry(&krate.name).await?;
+        files.push((index_path(&krate.name), Some(index_file(&mut entries.entries)?)));
+    }
+
+    commit(&config.path, "Rebuild the index", true, files).await
+}
+
+/// Runs [`rebuild`] on start, and then every `refresh_secs`, until the state is dropped
+pub(crate) async fn rebuild_periodically(state: Weak<ServiceState>, refresh_secs: u64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(refresh_secs.max(1)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let Some(state) = state.upgrade() else {
+            break;
+        };
+        if let Err(e) = rebuild(&state).await {
+            tracing::error!(?e, "Failed to rebuild the git index");
+            counter!("freighter_git_index_errors_total").increment(1);
+        }
+    }
+}
+
+/// Commits the changes to the branch. With `replace_all`, files that aren't changed are deleted.
+///
+/// Commits that don't change anything are dropped.
+async fn commit(
+    repo: &Path,
+    message: &str,
+    replace_all: bool,
+    changes: Vec<FileChange>,
+) -> anyhow::Result<()> {
+    let parent = git(repo, &["rev-parse", "--verify", "--quiet", BRANCH], Vec::new())
+        .await
+        .ok()
+        .map(|id| String::from_utf8_lossy(&id).trim().to_owned());
+
+    let mut stream = Vec::new();
+    stream.extend_from_slice(format!("commit {BRANCH}\n").as_bytes());
+    stream.extend_from_slice(
+        format!("committer Freighter <freighter@localhost> {} +0000\n", chrono::Utc::now().timestamp())
+            .as_bytes(),
+    );
+    push_data(&mut stream, message.as_bytes());
+    if let Some(parent) = &parent {
+        stream.extend_from_slice(format!("from {parent}\n").as_bytes());
+    }
+    if replace_all {
+        stream.extend_from_slice(b"deleteall\n");
+    }
+    for (path, contents) in changes {
+        match contents {
+            Some(contents) => {
+                stream.extend_from_slice(format!("M 100644 inline {path}\n").as_bytes());
+                push_data(&mut stream, &contents);
+            }
+            None => stream.extend_from_slice(format!("D {path}\n").as_bytes()),
+        }
+    }
+    stream.push(b'\n');
+
+    git(repo, &["fast-import", "--quiet"], stream).await?;
+
+    if let Some(parent) = parent {
+        let trees = git(repo, &["rev-parse", &format!("{BRANCH}^{{tree}}"), &format!("{parent}^{{tree}}")], Vec::new()).await?;
+        let trees = String::from_utf8_lossy(&trees);
+        let mut trees = trees.lines();
+        if trees.next() == trees.next() {
+            git(repo, &["update-ref", BRANCH, &parent], Vec::new()).await?;
+            return Ok(());
+        }
+    }
+
+    // fast-import makes a pack on every run
+    git(repo, &["gc", "--auto", "--quiet"], Vec::new()).await?;
+    Ok(())
+}
+
+fn push_data(stream: &mut Vec<u8>, data: &[u8]) {
+    stream.extend_from_slice(format!("data {}\n", data.len()).as_bytes());
+    stream.extend_from_slice(data);
+    stream.push(b'\n');
+}
+
+/// Runs git in the repository with the input on stdin, and returns its stdout
+async fn git(repo: &Path, args: &[&str], input: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(repo).args(args);
+    run(cmd, input).await
+}
+
+async fn run(mut cmd: Command, input: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git")?;
+
+    // written while the output is read, so that neither pipe fills up
+    let mut stdin = child.stdin.take().context("no stdin")?;
+    let write = async move {
+        stdin.write_all(&input).await?;
+        stdin.shutdown().await
+    };
+    let (written, output) = tokio::join!(write, child.wait_with_output());
+    let output = output?;
+
+    anyhow::ensure!(
+        output.status.success(),
+        "git failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    written.context("Failed to write to git")?;
+    Ok(output.stdout)
+}
+
+/// Path of the crate's file in the index, like `se/rd/serde`
+#[must_use]
+pub fn index_path(crate_name: &str) -> String {
+    let name = crate_name.to_ascii_lowercase();
+    match name.len() {
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
+
+async fn info_refs(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> axum::response::Result<Response> {
+    // the dumb protocol and pushes aren't supported
+    if query.get("service").map(String::as_str) != Some("git-upload-pack") {
+        return Err((StatusCode::FORBIDDEN, "Freighter: Only git-upload-pack is supported").into());
+    }
+    http_backend(&headers, &state, "GET", "/info/refs", "service=git-upload-pack", Bytes::new()).await
+}
+
+async fn upload_pack(
+    headers: HeaderMap,
+    State(state): State<Arc<ServiceState>>,
+    body: Bytes,
+) -> axum::response::Result<Response> {
+    http_backend(&headers, &state, "POST", "/git-upload-pack", "", body).await
+}
+
+/// Serves the request with `git http-backend`, which implements git's smart HTTP protocol as CGI
+async fn http_backend(
+    headers: &HeaderMap,
+    state: &ServiceState,
+    method: &str,
+    path: &str,
+    query: &str,
+    body: Bytes,
+) -> axum::response::Result<Response> {
+    let config = state
+        .config
+        .git_index
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "Freighter: The git index is not enabled"))?;
+    // the repository has every crate, including the private ones
+    if state.config.read_acl.is_some() {
+        return Err((StatusCode::NOT_FOUND, "Freighter: The git index can't be used with read_acl").into());
+    }
+
+    if state.config.auth_required {
+        let token = state.auth.token_from_headers(headers)?.ok_or(StatusCode::UNAUTHORIZED)?;
+        state.auth.auth_view_full_index(token).await?;
+    }
+
+    let header = |name: HeaderName| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_owned()
+    };
+
+    let mut cmd = Command::new("git");
+    cmd.arg("http-backend")
+        .env_clear()
+        .env("GIT_PROJECT_ROOT", &config.path)
+        .env("GIT_HTTP_EXPORT_ALL", "1")
+        .env("REQUEST_METHOD", method)
+        .env("PATH_INFO", path)
+        .env("QUERY_STRING", query)
+        .env("CONTENT_TYPE", header(header::CONTENT_TYPE))
+        .env("CONTENT_LENGTH", body.len().to_string())
+        .env("HTTP_CONTENT_ENCODING", header(header::CONTENT_ENCODING))
+        .env("GIT_PROTOCOL", header(HeaderName::from_static("git-protocol")));
+    if let Some(path) = std::env::var_os("PATH") {
+        cmd.env("PATH", path);
+    }
+
+    let output = run(cmd, body.to_vec()).await.map_err(|e| {
+        tracing::error!(?e, "git http-backend failed");
+        counter!("freighter_git_index_errors_total").increment(1);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    cgi_response(&output).ok_or_else(|| {
+        tracing::error!("Invalid response from git http-backend");
+        StatusCode::INTERNAL_SERVER_ERROR.into()
+    })
+}
+
+/// Converts the CGI output to a response
+fn cgi_response(output: &[u8]) -> Option<Response> {
+    let (head, body) = [&b"\r\n\r\n"[..], b"\n\n"]
+        .iter()
+        .filter_map(|sep| {
+            let pos = output.windows(sep.len()).position(|w| w == *sep)?;
+            Some((&output[..pos], &output[pos + sep.len()..]))
+        })
+        .min_by_key(|(head, _)| head.len())?;
+
+    let mut res = Bytes::copy_from_slice(body).into_response();
+    for line in std::str::from_utf8(head).ok()?.lines() {
+        let (name, value) = line.split_once(':')?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("status") {
+            let code = value.split(' ').next()?.parse().ok()?;
+            *res.status_mut() = StatusCode::from_u16(code).ok()?;
+        } else {
+            res.headers_mut().append(
+                HeaderName::from_bytes(name.as_bytes()).ok()?,
+                HeaderValue::from_str(value).ok()?,
+            );
+        }
+    }
+    Some(res)
+}
+
+async fn handle_git_index_fallback() -> (StatusCode, &'static str) {
+    (
+        StatusCode::NOT_FOUND,
+        "Freighter: Invalid URL for the git index endpoint",
+    )
+}
+
+#[inline(always)]
+const fn default_refresh_secs() -> u64 {
+    60
+}
@@ -1,16 +1,18 @@
-use crate::ServiceState;
+use crate::{mirror, ServiceState};
+use axum::body::Body;
 use axum::extract::{Path, State};
 use axum::http::header::WWW_AUTHENTICATE;
 use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::IntoResponse;
 use axum::routing::get;
 use axum::{Json, Router};
-use axum_extra::extract::JsonLines;
 use freighter_api_types::index::response::{CrateVersion, RegistryConfig};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
 use std::collections::HashSet;
-use std::convert::Infallible;
 use std::sync::Arc;
-use tokio_stream::StreamExt;
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
 
 const CARGO_AUTH_REQUIRED_ERROR: &str = "error: This registry requires cargo authentication\nhttps://doc.rust-lang.org/cargo/reference/registry-authentication.html";
 
@@ -61,18 +63,70 @@ async fn get_sparse_meta(
         state.auth.auth_index_fetch(token, crate_name).await?;
     }
 
+    mirror::sync_index(&state, crate_name).await;
+
     let mut crate_versions = state.index.get_sparse_entry(crate_name).await?;
-    // Fixes already-published crates
-    ensure_correct_metadata(&mut crate_versions.entries);
+    let json_lines = index_file(&mut crate_versions.entries).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Hashing the content makes the ETag the same regardless of which backend or server replica made it
+    let etag = format!("\"{}\"", hex::encode(&Sha256::digest(&json_lines)[..16]));
+    let last_modified = crate_versions.last_modified;
+
+    let mut res = if is_not_modified(&headers, &etag, last_modified) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        Body::from(json_lines).into_response()
+    };
 
-    let mut res = JsonLines::new(tokio_stream::iter(crate_versions.entries).map(Ok::<_, Infallible>)).into_response();
-    if let Some(last_mod) = crate_versions.last_modified.and_then(|d| d.to_rfc2822().try_into().ok()) {
-        res.headers_mut().insert(header::LAST_MODIFIED, last_mod);
+    let res_headers = res.headers_mut();
+    if let Ok(etag) = etag.try_into() {
+        res_headers.insert(header::ETAG, etag);
+    }
+    if let Some(last_mod) = last_modified.and_then(|d| d.format(HTTP_DATE_FORMAT).to_string().try_into().ok()) {
+        res_headers.insert(header::LAST_MODIFIED, last_mod);
     }
 
     Ok(res)
 }
 
+/// Contents of the crate's index file, one version per line
+pub(crate) fn index_file(entries: &mut [CrateVersion]) -> serde_json::Result<Vec<u8>> {
+    // Fixes already-published crates
+    ensure_correct_metadata(entries);
+
+    let mut json_lines = Vec::with_capacity(entries.len() * 512);
+    for entry in &*entries {
+        serde_json::to_writer(&mut json_lines, entry)?;
+        json_lines.push(b'\n');
+    }
+    Ok(json_lines)
+}
+
+/// Whether the client's cached copy is current, per [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-13.2.2)
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    // If-Modified-Since is ignored when If-None-Match is present
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        let Ok(if_none_match) = if_none_match.to_str() else {
+            return false;
+        };
+        return if_none_match.split(',').map(str::trim).any(|tag| {
+            // weak comparison
+            tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag
+        });
+    }
+
+    let if_modified_since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok());
+
+    match (last_modified, if_modified_since) {
+        // HTTP dates have a resolution of a second
+        (Some(last_modified), Some(since)) => last_modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
 fn ensure_correct_metadata(entries: &mut [CrateVersion]) {
     for e in entries {
         let valid_features: HashSet<_> = e.features.keys().chain(e.features2.keys()).cloned().collect();
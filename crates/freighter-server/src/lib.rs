@@ -1,25 +1,33 @@
 use axum::body::Body;
 use axum::extract::{DefaultBodyLimit, MatchedPath, Query, State};
 use axum::http::{header, HeaderMap, HeaderValue, Request, StatusCode};
-use axum::middleware::{from_fn, Next};
+use axum::middleware::{from_fn, from_fn_with_state, Next};
 use axum::response::{Html, IntoResponse, Response};
-use axum::routing::get;
+use axum::routing::{delete, get, post, put};
 use axum::{Json, Router};
+use downloads::DownloadCounter;
+use freighter_api_types::audit::AuditSink;
 use freighter_api_types::index::request::ListQuery;
 use freighter_api_types::index::response::ListAll;
 use freighter_api_types::index::IndexProvider;
 use freighter_api_types::storage::StorageProvider;
+use freighter_auth::read_acl::{self, ReadAclAuth};
+use freighter_auth::trusted_publishing::{self, TrustedPublishing, TrustedPublishingAuth};
 use freighter_auth::AuthProvider;
 use metrics::{counter, histogram};
+use git_index::{GitIndex, GitIndexConfig};
+use mirror::{Mirror, MirrorConfig};
+use policy::CrateNamePolicy;
 use serde::Deserialize;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::time::{Duration, Instant};
-use tokio::time::timeout;
+use tokio::time::{timeout, MissedTickBehavior};
 use tokio::try_join;
 use tower_http::catch_panic::CatchPanicLayer;
 use tower_http::classify::StatusInRangeAsFailures;
 use tower_http::trace::{DefaultOnFailure, TraceLayer};
+use webhooks::{Webhooks, WebhooksConfig};
 
 pub mod index;
 
@@ -27,6 +35,24 @@ pub mod api;
 
 pub mod downloads;
 
+pub mod policy;
+
+pub mod audit;
+
+pub mod webhooks;
+
+pub mod mirror;
+
+pub mod git_index;
+
+pub mod backup;
+
+mod tarball;
+
+mod asymmetric;
+
+const DOWNLOAD_COUNTS_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(Clone, Deserialize)]
 pub struct ServiceConfig {
     pub address: SocketAddr,
@@ -45,6 +71,36 @@ pub struct ServiceConfig {
     /// This includes both the metadata and the crate tarball.
     #[serde(default = "default_crate_size_limit")]
     pub crate_size_limit: usize,
+
+    /// If set, downloads are redirected to URLs that fetch the crate directly from storage,
+    /// valid for this many seconds. Crates are served by freighter if the storage can't make
+    /// such URLs.
+    #[serde(default)]
+    pub presigned_download_ttl_secs: Option<u64>,
+
+    /// Crate names that can't be published, or can be published only by some users.
+    #[serde(default)]
+    pub crate_name_policy: CrateNamePolicy,
+
+    /// HTTP endpoints notified of publishes, yanks and owner changes.
+    #[serde(default)]
+    pub webhooks: WebhooksConfig,
+
+    /// Upstream registry that crates missing in this one are fetched from.
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+
+    /// Also keep the index in a git repository, served at `/git/index`.
+    #[serde(default)]
+    pub git_index: Option<GitIndexConfig>,
+
+    /// Trust policies for CI jobs exchanging OIDC ID tokens for short-lived publish tokens.
+    #[serde(default)]
+    pub trusted_publishing: Option<trusted_publishing::Config>,
+
+    /// Crates that only some users can read. Requires `auth_required`.
+    #[serde(default)]
+    pub read_acl: Option<read_acl::Config>,
 }
 
 impl ServiceConfig {
@@ -55,6 +111,13 @@ impl ServiceConfig {
         if !self.download_endpoint.contains("://") {
             self.download_endpoint = format!("https://{}", self.download_endpoint);
         }
+        if self.read_acl.is_some() && !self.auth_required {
+            tracing::warn!("read_acl needs auth_required, which has been enabled");
+            self.auth_required = true;
+        }
+        if self.read_acl.is_some() && self.git_index.is_some() {
+            tracing::warn!("The git index has every crate, so it isn't served when read_acl is set");
+        }
     }
 }
 
@@ -63,6 +126,14 @@ pub struct ServiceState {
     pub index: Box<dyn IndexProvider + Send + Sync + 'static>,
     pub storage: Box<dyn StorageProvider + Send + Sync + 'static>,
     pub auth: Box<dyn AuthProvider + Send + Sync + 'static>,
+    pub download_counts: DownloadCounter,
+    pub webhooks: Webhooks,
+    pub mirror: Mirror,
+    pub git_index: GitIndex,
+    /// Issues the publish tokens, which `auth` accepts, if trusted publishing is configured
+    pub trusted_publishing: Option<Arc<TrustedPublishing>>,
+    /// Operations that change the registry are logged here, if set
+    pub audit: Option<Box<dyn AuditSink + Send + Sync + 'static>>,
 }
 
 impl ServiceState {
@@ -72,13 +143,32 @@ impl ServiceState {
         index: Box<dyn IndexProvider + Send + Sync + 'static>,
         storage: Box<dyn StorageProvider + Send + Sync + 'static>,
         auth: Box<dyn AuthProvider + Send + Sync + 'static>,
+        audit: Option<Box<dyn AuditSink + Send + Sync + 'static>>,
     ) -> Self {
         config.sanitize();
+        let trusted_publishing = config
+            .trusted_publishing
+            .clone()
+            .map(|c| Arc::new(TrustedPublishing::new(c)));
+        let auth: Box<dyn AuthProvider + Send + Sync + 'static> = match &trusted_publishing {
+            Some(trusted) => Box::new(TrustedPublishingAuth::new(auth, trusted.clone())),
+            None => auth,
+        };
+        let auth: Box<dyn AuthProvider + Send + Sync + 'static> = match config.read_acl.clone() {
+            Some(read_acl) => Box::new(ReadAclAuth::new(auth, read_acl)),
+            None => auth,
+        };
         Self {
             config,
             index,
             storage,
             auth,
+            download_counts: DownloadCounter::default(),
+            webhooks: Webhooks::default(),
+            mirror: Mirror::default(),
+            git_index: GitIndex::default(),
+            trusted_publishing,
+            audit,
         }
     }
 }
@@ -88,26 +178,56 @@ pub fn router(
     index_client: Box<dyn IndexProvider + Send + Sync + 'static>,
     storage_client: Box<dyn StorageProvider + Send + Sync + 'static>,
     auth_client: Box<dyn AuthProvider + Send + Sync + 'static>,
+    audit_sink: Option<Box<dyn AuditSink + Send + Sync + 'static>>,
 ) -> Router {
-    let crate_size_limit = config.crate_size_limit;
-    let state = Arc::new(ServiceState::new(
+    router_with_state(Arc::new(ServiceState::new(
         config,
         index_client,
         storage_client,
         auth_client,
-    ));
+        audit_sink,
+    )))
+}
+
+/// Like [`router`], but the caller keeps the state, e.g. to flush the download counts on shutdown
+pub fn router_with_state(state: Arc<ServiceState>) -> Router {
+    let crate_size_limit = state.config.crate_size_limit;
+
+    tokio::spawn(flush_download_counts(Arc::downgrade(&state)));
+    if !state.config.webhooks.targets.is_empty() {
+        tokio::spawn(webhooks::deliver_webhooks(Arc::downgrade(&state)));
+    }
+    if let Some(git_index) = &state.config.git_index {
+        tokio::spawn(git_index::rebuild_periodically(
+            Arc::downgrade(&state),
+            git_index.refresh_secs,
+        ));
+    }
 
     Router::new()
         .nest("/downloads", downloads::downloads_router())
         .nest("/index", index::index_router())
+        .nest("/git/index", git_index::git_index_router())
         .nest(
             "/api/v1/crates",
             api::api_router().layer(DefaultBodyLimit::max(crate_size_limit)),
         )
+        .route("/api/v1/audit", get(audit::query_audit_log))
+        .route(
+            "/api/v1/trusted_publishing/tokens",
+            post(api::trusted_publishing_token),
+        )
+        .route("/api/v1/keys", put(api::register_public_key))
+        .route(
+            "/api/v1/me/tokens",
+            get(api::list_tokens).put(api::create_token),
+        )
+        .route("/api/v1/me/tokens/:id", delete(api::revoke_token))
         .route("/me", get(register))
         .route("/all", get(list))
         .route("/healthcheck", get(healthcheck))
         .route("/", get(root_page))
+        .layer(from_fn_with_state(state.clone(), asymmetric::check_request))
         .with_state(state)
         .fallback(handle_global_fallback)
         .layer(CatchPanicLayer::custom(|_| {
@@ -129,6 +249,20 @@ pub fn router(
         .layer(from_fn(metrics_layer))
 }
 
+/// Runs until the state is dropped. Counts from the last interval need a final
+/// [`DownloadCounter::flush`] on shutdown.
+async fn flush_download_counts(state: Weak<ServiceState>) {
+    let mut interval = tokio::time::interval(DOWNLOAD_COUNTS_FLUSH_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let Some(state) = state.upgrade() else {
+            break;
+        };
+        state.download_counts.flush(&*state.index).await;
+    }
+}
+
 async fn metrics_layer(request: Request<Body>, next: Next) -> Response {
     let timer = Instant::now();
 
@@ -189,7 +323,8 @@ async fn list(
         state.auth.auth_view_full_index(token).await?;
     }
 
-    let search_results = state.index.list(&query).await?;
+    let mut search_results = state.index.list(&query).await?;
+    api::retain_readable(&state, &headers, &mut search_results.results, |c| Some(&c.name)).await?;
 
     Ok(Json(search_results))
 }
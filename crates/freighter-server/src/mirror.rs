@@ -0,0 +1,335 @@
+//! Pull-through mirror of an upstream registry, like crates.io.
+//!
+//! Index entries are copied from the upstream when they're requested, and tarballs when they're
+//! first downloaded. After that they're served from the local index and storage.
+//!
+//! Mirrored names can't be published locally, except for local crates, which have versions that
+//! aren't in the upstream. The mirror leaves those alone.
+
+use crate::{git_index, ServiceState};
+use axum::body::Bytes;
+use freighter_api_types::crate_name::crate_name_matches;
+use freighter_api_types::index::request::Publish;
+use freighter_api_types::index::{
+    canonical_crate_name, IndexError, IndexProvider, IndexResult, PublishContext,
+};
+use freighter_api_types::index::response::CrateVersion;
+use freighter_api_types::storage::{FileResponse, StorageError};
+use freighter_client::Client;
+use metrics::counter;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Deserialize)]
+pub struct MirrorConfig {
+    /// URL of the upstream's sparse index, like `https://index.crates.io`
+    pub index_url: String,
+
+    /// Sent to the upstream, if it requires auth
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// Names or patterns of the crates fetched from the upstream, like in the crate name policy.
+    /// Only local crates with these names can be published.
+    pub crates: Vec<String>,
+
+    /// How long before the upstream is checked again for new versions of a crate
+    #[serde(default = "default_refresh_secs")]
+    pub refresh_secs: u64,
+}
+
+impl MirrorConfig {
+    /// Whether the crate may be fetched from the upstream
+    #[must_use]
+    pub fn mirrors(&self, crate_name: &str) -> bool {
+        self.crates.iter().any(|p| crate_name_matches(p, crate_name))
+    }
+}
+
+#[derive(Default)]
+pub struct Mirror {
+    /// Made on first use, since it needs the upstream's config
+    client: OnceCell<Client>,
+    /// When crates were last checked in the upstream, by canonical name
+    synced: Mutex<HashMap<String, Instant>>,
+    /// Versions whose tarballs are known to be in storage
+    stored: Mutex<HashSet<(String, Version)>>,
+    /// Crates with versions that aren't in the upstream, by canonical name
+    local: Mutex<HashSet<String>>,
+}
+
+impl Mirror {
+    async fn client(&self, config: &MirrorConfig) -> IndexResult<&Client> {
+        self.client
+            .get_or_try_init(|| async {
+                let http = reqwest::Client::builder()
+                    .timeout(UPSTREAM_TIMEOUT)
+                    .build()
+                    .map_err(anyhow::Error::from)?;
+                Client::from_reqwest(&config.index_url, config.token.clone(), http)
+                    .await
+                    .map_err(upstream_error)
+            })
+            .await
+    }
+
+    /// Marks the crate as synced, if it's due
+    fn sync_due(&self, crate_name: &str, refresh_secs: u64) -> bool {
+        let now = Instant::now();
+        let name = canonical_crate_name(crate_name);
+        let mut synced = self.synced.lock().unwrap();
+        if synced
+            .get(&name)
+            .is_some_and(|last| now.duration_since(*last) < Duration::from_secs(refresh_secs))
+        {
+            return false;
+        }
+        synced.insert(name, now);
+        true
+    }
+
+    fn is_local(&self, crate_name: &str) -> bool {
+        self.local.lock().unwrap().contains(&canonical_crate_name(crate_name))
+    }
+
+    fn mark_local(&self, crate_name: &str) {
+        self.local.lock().unwrap().insert(canonical_crate_name(crate_name));
+    }
+
+    /// Versions of the crate in the upstream and in the index.
+    ///
+    /// Fails with `Conflict` if it's a local crate, and `NotFound` if it's in neither.
+    async fn versions(
+        &self,
+        config: &MirrorConfig,
+        index: &(dyn IndexProvider + Send + Sync),
+        crate_name: &str,
+    ) -> IndexResult<(Vec<CrateVersion>, Vec<CrateVersion>)> {
+        let upstream = match self
+            .client(config)
+            .await?
+            .fetch_index(&crate_name.to_ascii_lowercase())
+            .await
+        {
+            Ok(upstream) => upstream,
+            Err(freighter_client::Error::NotFound) => Vec::new(),
+            Err(e) => return Err(upstream_error(e)),
+        };
+
+        let local = match index.get_sparse_entry(crate_name).await {
+            Ok(local) => local.entries,
+            Err(IndexError::NotFound) if upstream.is_empty() => return Err(IndexError::NotFound),
+            Err(IndexError::NotFound) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        if let Some(v) = local
+            .iter()
+            .find(|l| !upstream.iter().any(|u| u.vers == l.vers && u.cksum == l.cksum))
+        {
+            return Err(IndexError::Conflict(format!(
+                "{}-{} is not from the upstream registry",
+                v.name, v.vers
+            )));
+        }
+        Ok((upstream, local))
+    }
+
+    /// Copies the versions of the crate that are missing in the index, and their yanked status.
+    ///
+    /// Crates that have versions which aren't in the upstream are local crates, and are left alone.
+    ///
+    /// Returns whether the index has changed.
+    async fn sync(
+        &self,
+        config: &MirrorConfig,
+        index: &(dyn IndexProvider + Send + Sync),
+        crate_name: &str,
+    ) -> IndexResult<bool> {
+        let (upstream, local) = self.versions(config, index, crate_name).await?;
+
+        let mut changed = false;
+        for version in upstream {
+            let name = version.name.clone();
+            let vers = version.vers.clone();
+            let yanked = version.yanked;
+
+            if let Some(l) = local.iter().find(|l| l.vers == vers) {
+                if l.yanked != yanked {
+                    set_yanked(index, &name, &vers, yanked).await?;
+                    changed = true;
+                }
+                continue;
+            }
+
+            let cksum = version.cksum;
+            match index
+                .publish(
+                    &Publish::from(version),
+                    cksum,
+                    &PublishContext::default(),
+                    std::pin::pin!(async { Ok(()) }),
+                )
+                .await
+            {
+                Ok(_) => changed = true,
+                // synced concurrently
+                Err(IndexError::Conflict(_)) => continue,
+                Err(e) => return Err(e),
+            }
+            if yanked {
+                set_yanked(index, &name, &vers, true).await?;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+async fn set_yanked(
+    index: &(dyn IndexProvider + Send + Sync),
+    crate_name: &str,
+    version: &Version,
+    yanked: bool,
+) -> IndexResult<()> {
+    if yanked {
+        index.yank_crate(crate_name, version).await
+    } else {
+        index.unyank_crate(crate_name, version).await
+    }
+}
+
+/// Adds versions published in the upstream since the crate was last checked to the index.
+///
+/// Failures are only logged, so that the local copy can still be used when the upstream is down.
+pub(crate) async fn sync_index(state: &ServiceState, crate_name: &str) {
+    let Some(config) = &state.config.mirror else {
+        return;
+    };
+    if !config.mirrors(crate_name)
+        || state.mirror.is_local(crate_name)
+        || !state.mirror.sync_due(crate_name, config.refresh_secs)
+    {
+        return;
+    }
+
+    match state.mirror.sync(config, &*state.index, crate_name).await {
+        Ok(changed) => {
+            counter!("freighter_mirror_syncs_total", "result" => "success").increment(1);
+            if changed {
+                git_index::update_crate(state, crate_name).await;
+            }
+        }
+        Err(IndexError::NotFound) => {
+            tracing::debug!(crate_name, "Crate not found in the upstream registry");
+        }
+        Err(IndexError::Conflict(e)) => {
+            tracing::info!(%e, crate_name, "Not mirroring a local crate");
+            state.mirror.mark_local(crate_name);
+        }
+        Err(e) => {
+            tracing::warn!(%e, crate_name, "Failed to sync crate with the upstream registry");
+            counter!("freighter_mirror_syncs_total", "result" => "failure").increment(1);
+        }
+    }
+}
+
+/// Fails if the crate's name is mirrored, unless it's a local crate.
+///
+/// Otherwise anyone could publish a version that builds would pick over the upstream's,
+/// and become the crate's owner.
+pub(crate) async fn check_publish(state: &ServiceState, crate_name: &str) -> IndexResult<()> {
+    let Some(config) = &state.config.mirror else {
+        return Ok(());
+    };
+    if !config.mirrors(crate_name) || state.mirror.is_local(crate_name) {
+        return Ok(());
+    }
+
+    match state.mirror.versions(config, &*state.index, crate_name).await {
+        Err(IndexError::Conflict(_)) => {
+            state.mirror.mark_local(crate_name);
+            Ok(())
+        }
+        Ok(_) | Err(IndexError::NotFound) => Err(IndexError::CrateNameNotAllowed(format!(
+            "`{crate_name}` is mirrored from the upstream registry"
+        ))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Copies the tarball of a mirrored crate from the upstream, if it's not in storage yet.
+///
+/// Returns the copied tarball, or `None` if it's in storage.
+pub(crate) async fn pull_tarball(
+    state: &ServiceState,
+    crate_name: &str,
+    version: &Version,
+    tarball_checksum: [u8; 32],
+) -> IndexResult<Option<FileResponse>> {
+    let Some(config) = &state.config.mirror else {
+        return Ok(None);
+    };
+    let key = (canonical_crate_name(crate_name), version.clone());
+    if !config.mirrors(crate_name)
+        || state.mirror.is_local(crate_name)
+        || state.mirror.stored.lock().unwrap().contains(&key)
+    {
+        return Ok(None);
+    }
+
+    let vers = version.to_string();
+    let res = match state.storage.stream_crate(crate_name, &vers, tarball_checksum).await {
+        // served from storage by the caller
+        Ok(_) => None,
+        Err(StorageError::NotFound) => {
+            let tarball = state
+                .mirror
+                .client(config)
+                .await?
+                .download_crate(crate_name, version)
+                .await
+                .map_err(upstream_error)?;
+
+            if <[u8; 32]>::from(Sha256::digest(&tarball)) != tarball_checksum {
+                counter!("freighter_mirror_checksum_errors_total").increment(1);
+                return Err(IndexError::ServiceError(anyhow::anyhow!(
+                    "{crate_name}-{version} from the upstream registry doesn't match its checksum"
+                )));
+            }
+
+            let data = Bytes::from(tarball);
+            state
+                .storage
+                .put_crate(crate_name, &vers, data.clone(), tarball_checksum)
+                .await?;
+            counter!("freighter_mirror_tarballs_total").increment(1);
+
+            Some(FileResponse {
+                last_modified: None,
+                data,
+            })
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    state.mirror.stored.lock().unwrap().insert(key);
+    Ok(res)
+}
+
+fn upstream_error(e: freighter_client::Error) -> IndexError {
+    match e {
+        freighter_client::Error::NotFound => IndexError::NotFound,
+        e => IndexError::ServiceError(anyhow::Error::new(e).context("upstream registry error")),
+    }
+}
+
+#[inline(always)]
+const fn default_refresh_secs() -> u64 {
+    5 * 60
+}
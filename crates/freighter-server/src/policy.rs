@@ -0,0 +1,60 @@
+//! Rules for which crate names can be published.
+
+use freighter_api_types::crate_name::{canonical_crate_name, crate_name_matches};
+use freighter_api_types::index::{IndexError, IndexResult};
+use serde::Deserialize;
+
+/// Names used by Rust itself, and names of files that can't be created on Windows.
+const RESERVED_NAMES: &[&str] = &[
+    "alloc", "core", "proc_macro", "std", "test",
+    "aux", "con", "nul", "prn",
+    "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9",
+    "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Names are compared ignoring case and `-`/`_` differences.
+/// Patterns can use `*` to match any number of characters, and `?` to match one character.
+#[derive(Clone, Default, Deserialize)]
+pub struct CrateNamePolicy {
+    /// Names or patterns that nobody can publish
+    #[serde(default)]
+    pub denied_names: Vec<String>,
+
+    /// Names or patterns that only some users can publish
+    #[serde(default)]
+    pub restricted_names: Vec<RestrictedNames>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct RestrictedNames {
+    pub pattern: String,
+    /// Logins of the users allowed to publish crates with these names
+    pub allowed_users: Vec<String>,
+}
+
+impl CrateNamePolicy {
+    /// Fails if the name can't be published by anyone.
+    ///
+    /// If the name is restricted to some users, returns the users.
+    pub fn check(&self, crate_name: &str) -> IndexResult<Option<&[String]>> {
+        let name = canonical_crate_name(crate_name);
+
+        if RESERVED_NAMES.contains(&name.as_str()) {
+            return Err(IndexError::CrateNameNotAllowed(format!(
+                "`{crate_name}` is a reserved name"
+            )));
+        }
+
+        if let Some(pattern) = self.denied_names.iter().find(|p| crate_name_matches(p, &name)) {
+            return Err(IndexError::CrateNameNotAllowed(format!(
+                "`{crate_name}` is denied by the registry's `{pattern}` rule"
+            )));
+        }
+
+        Ok(self
+            .restricted_names
+            .iter()
+            .find(|r| crate_name_matches(&r.pattern, &name))
+            .map(|r| &r.allowed_users[..]))
+    }
+}
@@ -0,0 +1,208 @@
+//! Checks of `.crate` tarballs, done while they're being uploaded.
+
+use axum::body::Bytes;
+use flate2::bufread::GzDecoder;
+use semver::Version;
+use serde::Deserialize;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Component, Path};
+use tar::EntryType;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Cargo.toml larger than this is not something cargo would make
+const MAX_MANIFEST_SIZE: u64 = 1024 * 1024;
+
+/// Validates a tarball fed to it chunk by chunk.
+///
+/// The tar reader is blocking, so it runs on a blocking thread, and gets the chunks over a
+/// channel.
+pub(crate) struct TarballValidator {
+    tx: Option<mpsc::Sender<Bytes>>,
+    task: Option<JoinHandle<Result<(), String>>>,
+    result: Result<(), String>,
+}
+
+impl TarballValidator {
+    #[must_use]
+    pub fn new(name: &str, version: &Version) -> Self {
+        let (tx, rx) = mpsc::channel(4);
+        let name = name.to_owned();
+        let version = version.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            validate_tarball(
+                ChannelReader {
+                    rx,
+                    buf: Bytes::new(),
+                },
+                &name,
+                &version,
+            )
+        });
+
+        Self {
+            tx: Some(tx),
+            task: Some(task),
+            result: Ok(()),
+        }
+    }
+
+    /// Returns an error as soon as it's known that the tarball is invalid
+    pub async fn feed(&mut self, chunk: Bytes) -> Result<(), String> {
+        if let Some(tx) = &self.tx
+            && tx.send(chunk).await.is_ok()
+        {
+            return Ok(());
+        }
+
+        // The validator has stopped reading, so it has either failed or seen the end of the archive
+        self.tx = None;
+        self.result().await
+    }
+
+    /// Call after the last chunk
+    pub async fn finish(mut self) -> Result<(), String> {
+        self.tx = None;
+        self.result().await
+    }
+
+    async fn result(&mut self) -> Result<(), String> {
+        if let Some(task) = self.task.take() {
+            self.result = task
+                .await
+                .unwrap_or_else(|_| Err("Crate tarball validation has failed".into()));
+        }
+        self.result.clone()
+    }
+}
+
+struct ChannelReader {
+    rx: mpsc::Receiver<Bytes>,
+    buf: Bytes,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.buf.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.buf = chunk,
+                None => return Ok(0),
+            }
+        }
+        let len = out.len().min(self.buf.len());
+        out[..len].copy_from_slice(&self.buf.split_to(len));
+        Ok(len)
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    package: ManifestPackage,
+}
+
+#[derive(Deserialize)]
+struct ManifestPackage {
+    name: String,
+    version: Option<String>,
+}
+
+/// Checks that the tarball is a gzipped tar in the format made by `cargo package`,
+/// and that its manifest is for the given crate version.
+///
+/// Returns an error message for the user.
+pub(crate) fn validate_tarball(
+    tarball: impl Read,
+    name: &str,
+    version: &Version,
+) -> Result<(), String> {
+    let invalid = |e: io::Error| format!("The crate tarball is invalid: {e}");
+
+    let prefix = format!("{name}-{version}");
+    let manifest_path = Path::new(&prefix).join("Cargo.toml");
+    let mut manifest = None;
+
+    // not the `read` decoder, which loses what it buffered past the end of the gzip stream
+    let mut archive = tar::Archive::new(GzDecoder::new(BufReader::new(tarball)));
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        let entry_type = entry.header().entry_type();
+        if entry_type == EntryType::XGlobalHeader {
+            continue;
+        }
+
+        let path = entry.path().map_err(invalid)?.into_owned();
+        match entry_type {
+            EntryType::Regular | EntryType::Continuous | EntryType::Directory => {}
+            EntryType::Symlink | EntryType::Link => {
+                return Err(format!(
+                    "The crate tarball contains a link `{}`, but links aren't allowed",
+                    path.display()
+                ));
+            }
+            _ => {
+                return Err(format!(
+                    "The crate tarball contains `{}` of an unsupported type",
+                    path.display()
+                ));
+            }
+        }
+
+        let mut components = path.components();
+        if components.next() != Some(Component::Normal(prefix.as_ref()))
+            || !components.all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(format!(
+                "The crate tarball contains `{}`, which is outside of the `{prefix}/` directory",
+                path.display()
+            ));
+        }
+
+        if path == manifest_path && entry_type != EntryType::Directory {
+            if manifest.is_some() {
+                return Err(format!(
+                    "The crate tarball contains more than one `{prefix}/Cargo.toml`"
+                ));
+            }
+            if entry.size() > MAX_MANIFEST_SIZE {
+                return Err(format!(
+                    "The crate's `Cargo.toml` is too large ({} bytes, the limit is {MAX_MANIFEST_SIZE})",
+                    entry.size()
+                ));
+            }
+            let mut toml = String::new();
+            entry.read_to_string(&mut toml).map_err(invalid)?;
+            manifest = Some(toml);
+        }
+    }
+
+    // reads the rest of the gzip stream, to check it's not truncated or corrupted
+    let mut gzip = archive.into_inner();
+    io::copy(&mut gzip, &mut io::sink()).map_err(invalid)?;
+    if !gzip.into_inner().fill_buf().map_err(invalid)?.is_empty() {
+        return Err("The crate tarball has data after the end of the gzip stream".into());
+    }
+
+    let manifest =
+        manifest.ok_or_else(|| format!("The crate tarball is missing `{prefix}/Cargo.toml`"))?;
+    let manifest: Manifest = toml::from_str(&manifest)
+        .map_err(|e| format!("The crate's `Cargo.toml` could not be parsed: {e}"))?;
+
+    let manifest_version = manifest
+        .package
+        .version
+        .as_deref()
+        .and_then(|v| Version::parse(v).ok());
+    if manifest.package.name != name || manifest_version.as_ref() != Some(version) {
+        return Err(format!(
+            "The crate's `Cargo.toml` is for {} {}, but it's being published as {name} {version}",
+            manifest.package.name,
+            manifest
+                .package
+                .version
+                .as_deref()
+                .unwrap_or("without a version"),
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,331 @@
+use crate::ServiceState;
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use metrics::counter;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Weak};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+/// How often due deliveries are sent
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(1);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RETRY_DELAY: Duration = Duration::from_hours(1);
+/// Deliveries are dropped after failing this many times
+const MAX_ATTEMPTS: u32 = 12;
+
+#[derive(Clone, Deserialize)]
+pub struct WebhooksConfig {
+    #[serde(default)]
+    pub targets: Vec<WebhookTarget>,
+
+    /// Directory where deliveries are kept until they succeed, so that they survive restarts.
+    /// If not set, pending deliveries are lost on restart.
+    #[serde(default)]
+    pub queue_path: Option<PathBuf>,
+
+    /// Delay before the first retry of a failed delivery. It doubles with every attempt.
+    #[serde(default = "default_retry_delay_secs")]
+    pub retry_delay_secs: u64,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            queue_path: None,
+            retry_delay_secs: default_retry_delay_secs(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct WebhookTarget {
+    pub url: String,
+    /// Key of the HMAC-SHA256 signature in the `X-Freighter-Signature` header
+    pub secret: String,
+    /// Events sent to this target. All events by default.
+    #[serde(default)]
+    pub events: Option<Vec<WebhookEventKind>>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    Publish,
+    Yank,
+    Unyank,
+    OwnersAdded,
+    OwnersRemoved,
+}
+
+/// The JSON payload POSTed to the targets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub event: WebhookEventKind,
+    pub timestamp: DateTime<Utc>,
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub version: Option<Version>,
+    /// Login of the user who caused the event, if known
+    pub actor: Option<String>,
+    /// Users added or removed as owners
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub owners: Option<Vec<String>>,
+}
+
+/// An event waiting to be sent to one target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Delivery {
+    id: String,
+    target_url: String,
+    event: WebhookEventKind,
+    /// Sent and signed exactly as serialized when the event happened
+    payload: String,
+    attempts: u32,
+    next_attempt: DateTime<Utc>,
+}
+
+/// Deliveries of webhook events that haven't succeeded yet.
+///
+/// Events are queued by [`Webhooks::send`], and sent in the background by [`Webhooks::deliver`].
+#[derive(Default)]
+pub struct Webhooks {
+    pending: Mutex<Vec<Delivery>>,
+    /// Whether deliveries left from before a restart have been loaded
+    loaded: AtomicBool,
+}
+
+impl Webhooks {
+    /// Queues the event for every target that wants it
+    pub async fn send(&self, config: &WebhooksConfig, event: &WebhookEvent) {
+        let payload = match serde_json::to_string(event) {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::error!(%e, "Failed to serialize webhook event");
+                return;
+            }
+        };
+
+        for target in &config.targets {
+            if target.events.as_ref().is_some_and(|events| !events.contains(&event.event)) {
+                continue;
+            }
+
+            let delivery = Delivery {
+                id: format!("{:032x}", rand::random::<u128>()),
+                target_url: target.url.clone(),
+                event: event.event,
+                payload: payload.clone(),
+                attempts: 0,
+                next_attempt: event.timestamp,
+            };
+            persist(config, &delivery).await;
+            self.pending.lock().unwrap().push(delivery);
+        }
+    }
+
+    /// Number of deliveries that haven't succeeded yet
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Sends the deliveries that are due. Failed ones are retried later, with a backoff.
+    pub async fn deliver(&self, config: &WebhooksConfig, client: &reqwest::Client) {
+        if !self.loaded.swap(true, Ordering::Relaxed) {
+            self.load(config).await;
+        }
+
+        let now = Utc::now();
+        let due: Vec<_> = {
+            let mut pending = self.pending.lock().unwrap();
+            let (due, later) = pending.drain(..).partition(|d| d.next_attempt <= now);
+            *pending = later;
+            due
+        };
+
+        for mut delivery in due {
+            // targets removed from the config don't get the events anymore
+            let Some(target) = config.targets.iter().find(|t| t.url == delivery.target_url) else {
+                remove_persisted(config, &delivery).await;
+                continue;
+            };
+
+            match post(client, target, &delivery).await {
+                Ok(()) => {
+                    counter!("freighter_webhook_deliveries_total", "result" => "success").increment(1);
+                    remove_persisted(config, &delivery).await;
+                }
+                Err(e) => {
+                    counter!("freighter_webhook_deliveries_total", "result" => "failure").increment(1);
+                    delivery.attempts += 1;
+                    if delivery.attempts >= MAX_ATTEMPTS {
+                        tracing::error!(%e, url = %delivery.target_url, id = %delivery.id, "Giving up on webhook delivery");
+                        remove_persisted(config, &delivery).await;
+                        continue;
+                    }
+
+                    tracing::warn!(%e, url = %delivery.target_url, id = %delivery.id, "Webhook delivery failed, will retry");
+                    let delay = Duration::from_secs(config.retry_delay_secs)
+                        .saturating_mul(1 << (delivery.attempts - 1))
+                        .min(MAX_RETRY_DELAY);
+                    delivery.next_attempt = Utc::now() + delay;
+                    persist(config, &delivery).await;
+                    self.pending.lock().unwrap().push(delivery);
+                }
+            }
+        }
+    }
+
+    async fn load(&self, config: &WebhooksConfig) {
+        let Some(dir) = &config.queue_path else {
+            return;
+        };
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                tracing::error!(%e, "Failed to read webhook queue");
+                return;
+            }
+        };
+
+        let mut loaded = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            match tokio::fs::read(&path).await.map_err(anyhow::Error::from).and_then(|data| {
+                serde_json::from_slice::<Delivery>(&data).map_err(anyhow::Error::from)
+            }) {
+                Ok(delivery) => loaded.push(delivery),
+                Err(e) => tracing::error!(%e, path = %path.display(), "Invalid webhook delivery"),
+            }
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        for delivery in loaded {
+            if !pending.iter().any(|d| d.id == delivery.id) {
+                pending.push(delivery);
+            }
+        }
+    }
+}
+
+/// Queues the event, if any webhooks are configured
+pub(crate) async fn notify(
+    state: &ServiceState,
+    headers: &HeaderMap,
+    event: WebhookEventKind,
+    crate_name: &str,
+    version: Option<&Version>,
+    owners: Option<&[String]>,
+) {
+    if state.config.webhooks.targets.is_empty() {
+        return;
+    }
+
+    let actor = match state.auth.token_from_headers(headers) {
+        Ok(Some(token)) => state.auth.token_login(token).await.ok(),
+        _ => None,
+    };
+
+    let event = WebhookEvent {
+        event,
+        timestamp: Utc::now(),
+        crate_name: crate_name.to_owned(),
+        version: version.cloned(),
+        actor,
+        owners: owners.map(<[String]>::to_vec),
+    };
+    state.webhooks.send(&state.config.webhooks, &event).await;
+}
+
+/// Runs until the state is dropped. Persisted deliveries are sent after a restart.
+pub(crate) async fn deliver_webhooks(state: Weak<ServiceState>) {
+    let client = match reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!(%e, "Failed to create webhook client");
+            return;
+        }
+    };
+
+    let mut interval = tokio::time::interval(DELIVERY_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let Some(state) = state.upgrade() else {
+            break;
+        };
+        state.webhooks.deliver(&state.config.webhooks, &client).await;
+    }
+}
+
+async fn post(
+    client: &reqwest::Client,
+    target: &WebhookTarget,
+    delivery: &Delivery,
+) -> anyhow::Result<()> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(target.secret.as_bytes())?;
+    mac.update(delivery.payload.as_bytes());
+    let signature = hex::encode(mac.finalize().into_bytes());
+
+    let event = serde_json::to_value(delivery.event)?;
+    client
+        .post(&target.url)
+        .header("content-type", "application/json")
+        .header("x-freighter-event", event.as_str().unwrap_or_default())
+        .header("x-freighter-delivery", &delivery.id)
+        .header("x-freighter-signature", format!("sha256={signature}"))
+        .body(delivery.payload.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+fn delivery_path(dir: &Path, delivery: &Delivery) -> PathBuf {
+    dir.join(format!("{}.json", delivery.id))
+}
+
+async fn persist(config: &WebhooksConfig, delivery: &Delivery) {
+    let Some(dir) = &config.queue_path else {
+        return;
+    };
+
+    let path = delivery_path(dir, delivery);
+    let tmp_path = path.with_extension("tmp");
+    let res = async {
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(&tmp_path, serde_json::to_vec(delivery)?).await?;
+        tokio::fs::rename(&tmp_path, &path).await
+    };
+    if let Err(e) = res.await {
+        tracing::error!(%e, path = %path.display(), "Failed to persist webhook delivery");
+        counter!("freighter_webhook_queue_errors_total").increment(1);
+    }
+}
+
+async fn remove_persisted(config: &WebhooksConfig, delivery: &Delivery) {
+    if let Some(dir) = &config.queue_path
+        && let Err(e) = tokio::fs::remove_file(delivery_path(dir, delivery)).await
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        tracing::error!(%e, "Failed to remove webhook delivery");
+        counter!("freighter_webhook_queue_errors_total").increment(1);
+    }
+}
+
+#[inline(always)]
+const fn default_retry_delay_secs() -> u64 {
+    10
+}
@@ -1,13 +1,18 @@
 pub mod common;
 
-use crate::common::utils::{crate_version, generate_crate_payload};
+use crate::common::utils::{
+    crate_tarball, crate_version, generate_crate_payload, tar_entry, tarball,
+};
 use crate::common::{MockIndexProvider, ServiceStateBuilder};
 use axum::body::{to_bytes, Body};
 
 use axum::http::{Request, StatusCode};
-use freighter_api_types::index::response::CrateVersion;
+use freighter_api_types::index::response::{CrateVersion, Dependency};
+use freighter_api_types::index::DependencyKind;
+use freighter_server::policy::{CrateNamePolicy, RestrictedNames};
 use freighter_server::{api, router};
 use hyper::header::AUTHORIZATION;
+use semver::VersionReq;
 use serde_json::Value;
 use std::collections::BTreeMap;
 use tower::ServiceExt;
@@ -24,7 +29,12 @@ async fn publish_crate() {
         })
         .build();
 
-    let payload = generate_crate_payload("example-lib", "1.0.1", &[1u8; 100], &[]);
+    let payload = generate_crate_payload(
+        "example-lib",
+        "1.0.1",
+        &crate_tarball("example-lib", "1.0.1"),
+        &[],
+    );
 
     let response = router
         .with_state(state)
@@ -52,7 +62,12 @@ async fn publish_crate_auth_denied() {
         })
         .build();
 
-    let payload = generate_crate_payload("example-lib", "1.0.1", &[1u8; 100], &[]);
+    let payload = generate_crate_payload(
+        "example-lib",
+        "1.0.1",
+        &crate_tarball("example-lib", "1.0.1"),
+        &[],
+    );
 
     let response = router
         .with_state(state)
@@ -70,6 +85,341 @@ async fn publish_crate_auth_denied() {
     assert_eq!(response.status(), StatusCode::FORBIDDEN);
 }
 
+#[tokio::test]
+async fn publish_crate_truncated() {
+    let router = api::api_router();
+
+    const TOKEN: &str = "12345";
+
+    let state = ServiceStateBuilder::default()
+        .auth_provider(common::MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        })
+        .build();
+
+    let mut payload = generate_crate_payload(
+        "example-lib",
+        "1.0.1",
+        &crate_tarball("example-lib", "1.0.1"),
+        &[],
+    );
+    payload.truncate(payload.len() - 10);
+
+    let response = router
+        .with_state(state)
+        .oneshot(
+            Request::builder()
+                .uri("/new")
+                .method("PUT")
+                .header(AUTHORIZATION, TOKEN)
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn publish_crate_too_large() {
+    let router = api::api_router();
+
+    const TOKEN: &str = "12345";
+
+    let state = ServiceStateBuilder::default()
+        .auth_provider(common::MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        })
+        .build();
+
+    // only the declared length matters, the tarball isn't read
+    let mut payload = generate_crate_payload("example-lib", "1.0.1", &[], &[]);
+    let len_pos = payload.len() - 4;
+    payload[len_pos..].copy_from_slice(&(2 * 1024 * 1024u32).to_le_bytes());
+
+    let response = router
+        .with_state(state)
+        .oneshot(
+            Request::builder()
+                .uri("/new")
+                .method("PUT")
+                .header(AUTHORIZATION, TOKEN)
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn publish_crate_conflict() {
+    let router = api::api_router();
+
+    const TOKEN: &str = "12345";
+
+    let state = ServiceStateBuilder::default()
+        .auth_provider(common::MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        })
+        .index_provider(MockIndexProvider {
+            crates: BTreeMap::from([(
+                "example-lib".to_owned(),
+                vec![crate_version("example-lib", "1.0.1")],
+            )]),
+        })
+        .build();
+
+    let payload = generate_crate_payload(
+        "example-lib",
+        "1.0.1",
+        &crate_tarball("example-lib", "1.0.1"),
+        &[],
+    );
+
+    let response = router
+        .with_state(state)
+        .oneshot(
+            Request::builder()
+                .uri("/new")
+                .method("PUT")
+                .header(AUTHORIZATION, TOKEN)
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+async fn publish_tarball(tarball: &[u8]) -> (StatusCode, Value) {
+    const TOKEN: &str = "12345";
+
+    let state = ServiceStateBuilder::default()
+        .auth_provider(common::MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        })
+        .build();
+
+    let payload = generate_crate_payload("example-lib", "1.0.1", tarball, &[]);
+
+    let response = api::api_router()
+        .with_state(state)
+        .oneshot(
+            Request::builder()
+                .uri("/new")
+                .method("PUT")
+                .header(AUTHORIZATION, TOKEN)
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn publish_crate_not_gzip() {
+    let (status, body) = publish_tarball(&[1u8; 100]).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["errors"][0]["detail"].is_string(), "{body}");
+}
+
+#[tokio::test]
+async fn publish_crate_manifest_mismatch() {
+    let (status, body) = publish_tarball(&tarball(&[tar_entry(
+        "example-lib-1.0.1/Cargo.toml",
+        tar::EntryType::Regular,
+        b"[package]\nname = \"example-lib\"\nversion = \"1.0.0\"\n",
+    )]))
+    .await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["errors"][0]["detail"],
+        "The crate's `Cargo.toml` is for example-lib 1.0.0, but it's being published as example-lib 1.0.1"
+    );
+}
+
+#[tokio::test]
+async fn publish_crate_missing_manifest() {
+    let (status, body) = publish_tarball(&tarball(&[tar_entry(
+        "example-lib-1.0.1/src/lib.rs",
+        tar::EntryType::Regular,
+        b"",
+    )]))
+    .await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["errors"][0]["detail"],
+        "The crate tarball is missing `example-lib-1.0.1/Cargo.toml`"
+    );
+}
+
+#[tokio::test]
+async fn publish_crate_path_outside_prefix() {
+    let mut entries = vec![tar_entry(
+        "example-lib-1.0.1/../../etc/passwd",
+        tar::EntryType::Regular,
+        b"",
+    )];
+    entries.extend(tarball_entries_of_valid_crate());
+    let (status, _) = publish_tarball(&tarball(&entries)).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn publish_crate_symlink() {
+    let mut entries = tarball_entries_of_valid_crate();
+    entries.push(tar_entry(
+        "example-lib-1.0.1/src",
+        tar::EntryType::Symlink,
+        b"",
+    ));
+    let (status, _) = publish_tarball(&tarball(&entries)).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn publish_crate_device_file() {
+    let mut entries = tarball_entries_of_valid_crate();
+    entries.push(tar_entry(
+        "example-lib-1.0.1/dev",
+        tar::EntryType::Char,
+        b"",
+    ));
+    let (status, _) = publish_tarball(&tarball(&entries)).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn publish_crate_manifest_too_large() {
+    let mut manifest = b"[package]\nname = \"example-lib\"\nversion = \"1.0.1\"\n".to_vec();
+    manifest.resize(2 * 1024 * 1024, b'\n');
+    let (status, body) = publish_tarball(&tarball(&[tar_entry(
+        "example-lib-1.0.1/Cargo.toml",
+        tar::EntryType::Regular,
+        &manifest,
+    )]))
+    .await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["errors"][0]["detail"],
+        "The crate's `Cargo.toml` is too large (2097152 bytes, the limit is 1048576)"
+    );
+}
+
+#[tokio::test]
+async fn publish_crate_trailing_data() {
+    let valid = tarball(&tarball_entries_of_valid_crate());
+    let (status, _) = publish_tarball(&valid).await;
+    assert_eq!(status, StatusCode::OK);
+
+    for trailing in [&b"junk"[..], &valid] {
+        let (status, body) = publish_tarball(&[&valid[..], trailing].concat()).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["errors"][0]["detail"],
+            "The crate tarball has data after the end of the gzip stream"
+        );
+    }
+}
+
+fn tarball_entries_of_valid_crate() -> Vec<(tar::Header, Vec<u8>)> {
+    vec![tar_entry(
+        "example-lib-1.0.1/Cargo.toml",
+        tar::EntryType::Regular,
+        b"[package]\nname = \"example-lib\"\nversion = \"1.0.1\"\n",
+    )]
+}
+
+async fn publish_with_policy(name: &str, token: &str, policy: CrateNamePolicy) -> StatusCode {
+    let state = ServiceStateBuilder::default()
+        .auth_provider(common::MockAuthProvider {
+            valid_tokens: ["alice".to_owned(), "bob".to_owned()].into(),
+        })
+        .crate_name_policy(policy)
+        .build();
+
+    let payload = generate_crate_payload(name, "1.0.0", &crate_tarball(name, "1.0.0"), &[]);
+
+    let response = api::api_router()
+        .with_state(state)
+        .oneshot(
+            Request::builder()
+                .uri("/new")
+                .method("PUT")
+                .header(AUTHORIZATION, token)
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    response.status()
+}
+
+#[tokio::test]
+async fn publish_reserved_crate_name() {
+    for name in ["std", "NUL", "com1", "proc-macro"] {
+        let status = publish_with_policy(name, "alice", CrateNamePolicy::default()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{name}");
+    }
+}
+
+#[tokio::test]
+async fn publish_denied_crate_name() {
+    let policy = CrateNamePolicy {
+        denied_names: vec!["evil-*".to_owned(), "secret".to_owned()],
+        restricted_names: vec![],
+    };
+
+    for (name, expected) in [
+        ("evil-lib", StatusCode::BAD_REQUEST),
+        ("Evil_Lib", StatusCode::BAD_REQUEST),
+        ("secret", StatusCode::BAD_REQUEST),
+        ("secrets", StatusCode::OK),
+        ("not-evil", StatusCode::OK),
+    ] {
+        let status = publish_with_policy(name, "alice", policy.clone()).await;
+        assert_eq!(status, expected, "{name}");
+    }
+}
+
+#[tokio::test]
+async fn publish_restricted_crate_name() {
+    let policy = CrateNamePolicy {
+        denied_names: vec![],
+        restricted_names: vec![RestrictedNames {
+            pattern: "acme-internal-*".to_owned(),
+            allowed_users: vec!["alice".to_owned()],
+        }],
+    };
+
+    for (name, token, expected) in [
+        ("acme-internal-lib", "alice", StatusCode::OK),
+        ("acme_internal_lib", "bob", StatusCode::BAD_REQUEST),
+        ("acme-internal-lib", "mallory", StatusCode::UNAUTHORIZED),
+        ("acme-lib", "bob", StatusCode::OK),
+    ] {
+        let status = publish_with_policy(name, token, policy.clone()).await;
+        assert_eq!(status, expected, "{name} {token}");
+    }
+}
+
 #[tokio::test]
 async fn index_auth() {
     let state = ServiceStateBuilder::default()
@@ -90,7 +440,7 @@ async fn index_auth() {
         })
         .build_no_arc();
 
-    let router = router(state.config, state.index, state.storage, state.auth);
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
 
     let response = router
         .clone()
@@ -135,7 +485,7 @@ async fn list_all_crates() {
         .index_provider(MockIndexProvider { crates })
         .build_no_arc();
 
-    let router = router(state.config, state.index, state.storage, state.auth);
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
 
     let response = router
         .oneshot(Request::builder().uri("/all").body(Body::empty()).unwrap())
@@ -168,7 +518,8 @@ async fn list_all_crates() {
                     "repository": "ssh://git@b.com/a/f.git",
                     "documentation": null,
                     "keywords": [ "example" ],
-                    "categories": [ "a", "x" ]
+                    "categories": [ "a", "x" ],
+                    "downloads": 2
                 },
                 {
                     "name": "freighter",
@@ -187,13 +538,268 @@ async fn list_all_crates() {
                     "repository": "ssh://git@b.com/a/f.git",
                     "documentation": null,
                     "keywords": [ "example" ],
-                    "categories": [ "a", "x" ]
+                    "categories": [ "a", "x" ],
+                    "downloads": 2
                 }
             ]
         })
     );
 }
 
+#[tokio::test]
+async fn crate_details() {
+    let mut versions: Vec<_> = ["1.3.0", "1.3.1", "1.4.0"]
+        .iter()
+        .map(|version| crate_version("example-lib", version))
+        .collect();
+    versions[2].yanked = true;
+    let crates = BTreeMap::from([("example-lib".to_owned(), versions)]);
+
+    let state = ServiceStateBuilder::default()
+        .index_provider(MockIndexProvider { crates })
+        .build();
+
+    let response = api::api_router()
+        .with_state(state.clone())
+        .oneshot(
+            Request::builder()
+                .uri("/example-lib")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = to_bytes(response.into_body(), 100_000).await.unwrap();
+    let value: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "crate": {
+                "id": "example-lib",
+                "name": "example-lib",
+                "description": "Description example-lib",
+                "homepage": "e.com",
+                "documentation": null,
+                "repository": "ssh://git@b.com/a/f.git",
+                "keywords": [ "example" ],
+                "categories": [ "a", "x" ],
+                "created_at": "1970-01-01T00:00:00Z",
+                "updated_at": "1970-01-01T00:00:00Z",
+                "max_version": "1.3.1",
+                "downloads": 3
+            },
+            "versions": [
+                { "num": "1.4.0", "yanked": true, "downloads": 1 },
+                { "num": "1.3.1", "yanked": false, "downloads": 1 },
+                { "num": "1.3.0", "yanked": false, "downloads": 1 },
+            ]
+        })
+    );
+
+    let response = api::api_router()
+        .with_state(state)
+        .oneshot(
+            Request::builder()
+                .uri("/missing")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn crate_downloads() {
+    let crates = BTreeMap::from([(
+        "example-lib".to_owned(),
+        ["1.3.0", "1.3.1"]
+            .iter()
+            .map(|version| crate_version("example-lib", version))
+            .collect::<Vec<_>>(),
+    )]);
+
+    let state = ServiceStateBuilder::default()
+        .index_provider(MockIndexProvider { crates })
+        .build();
+
+    let response = api::api_router()
+        .with_state(state.clone())
+        .oneshot(
+            Request::builder()
+                .uri("/example-lib/downloads")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = to_bytes(response.into_body(), 100_000).await.unwrap();
+    let value: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "version_downloads": [
+                { "version": "1.3.0", "downloads": 1, "date": "2023-11-14" },
+                { "version": "1.3.1", "downloads": 1, "date": "2023-11-14" },
+            ]
+        })
+    );
+
+    let response = api::api_router()
+        .with_state(state)
+        .oneshot(
+            Request::builder()
+                .uri("/missing/downloads")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn version_details() {
+    let crates = BTreeMap::from([(
+        "example-lib".to_owned(),
+        vec![crate_version("example-lib", "1.3.0")],
+    )]);
+
+    let state = ServiceStateBuilder::default()
+        .index_provider(MockIndexProvider { crates })
+        .build();
+
+    let response = api::api_router()
+        .with_state(state.clone())
+        .oneshot(
+            Request::builder()
+                .uri("/example-lib/1.3.0")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = to_bytes(response.into_body(), 100_000).await.unwrap();
+    let value: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "version": {
+                "crate": "example-lib",
+                "num": "1.3.0",
+                "checksum": "ff".repeat(32),
+                "yanked": false,
+                "features": {},
+                "dependencies": [],
+                "links": null,
+                "description": "Description example-lib",
+                "license": "MIT",
+                "license_file": null,
+                "homepage": "e.com",
+                "documentation": null,
+                "repository": "ssh://git@b.com/a/f.git",
+                "created_at": "2023-11-14T22:13:20Z",
+                "published_by": { "login": "example-user" },
+                "crate_size": 1234,
+                "downloads": 1
+            }
+        })
+    );
+
+    for uri in ["/example-lib/2.0.0", "/missing/1.3.0"] {
+        let response = api::api_router()
+            .with_state(state.clone())
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+async fn reverse_dependencies() {
+    let mut dependent = crate_version("example-bin", "0.1.0");
+    dependent.deps.push(Dependency {
+        name: "lib".to_owned(),
+        req: VersionReq::parse("^1.3").unwrap(),
+        features: vec!["std".to_owned()],
+        optional: false,
+        default_features: true,
+        target: None,
+        kind: DependencyKind::Normal,
+        registry: None,
+        package: Some("example-lib".to_owned()),
+    });
+    let crates = BTreeMap::from([
+        (
+            "example-lib".to_owned(),
+            vec![crate_version("example-lib", "1.3.0")],
+        ),
+        ("example-bin".to_owned(), vec![dependent]),
+    ]);
+
+    let state = ServiceStateBuilder::default()
+        .index_provider(MockIndexProvider { crates })
+        .build();
+
+    let response = api::api_router()
+        .with_state(state.clone())
+        .oneshot(
+            Request::builder()
+                .uri("/example-lib/reverse_dependencies")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = to_bytes(response.into_body(), 100_000).await.unwrap();
+    let value: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "dependencies": [{
+                "crate": "example-bin",
+                "num": "0.1.0",
+                "yanked": false,
+                "req": "^1.3",
+                "features": [ "std" ],
+                "optional": false,
+                "default_features": true,
+                "target": null,
+                "kind": "normal"
+            }]
+        })
+    );
+
+    let response = api::api_router()
+        .with_state(state)
+        .oneshot(
+            Request::builder()
+                .uri("/missing/reverse_dependencies")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn one_char_crate_name() {
     const CRATE_NAMES: &[&str] = &["a", "aa", "aaa", "aaaa", "aa-aa", "aa_aa"];
@@ -210,7 +816,7 @@ async fn one_char_crate_name() {
         })
         .build_no_arc();
 
-    let router = router(state.config, state.index, state.storage, state.auth);
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
 
     let response = router
         .oneshot(
@@ -247,7 +853,7 @@ async fn two_char_crate_name() {
         })
         .build_no_arc();
 
-    let router = router(state.config, state.index, state.storage, state.auth);
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
 
     let response = router
         .oneshot(
@@ -284,7 +890,7 @@ async fn three_char_crate_name() {
         })
         .build_no_arc();
 
-    let router = router(state.config, state.index, state.storage, state.auth);
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
 
     let response = router
         .oneshot(
@@ -321,7 +927,7 @@ async fn four_char_crate_name() {
         })
         .build_no_arc();
 
-    let router = router(state.config, state.index, state.storage, state.auth);
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
 
     let response = router
         .oneshot(
@@ -358,7 +964,7 @@ async fn hyphen_crate_name() {
         })
         .build_no_arc();
 
-    let router = router(state.config, state.index, state.storage, state.auth);
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
 
     let response = router
         .oneshot(
@@ -395,7 +1001,7 @@ async fn underscore_crate_name() {
         })
         .build_no_arc();
 
-    let router = router(state.config, state.index, state.storage, state.auth);
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
 
     let response = router
         .oneshot(
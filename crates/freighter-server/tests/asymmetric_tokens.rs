@@ -0,0 +1,178 @@
+pub mod common;
+
+use crate::common::utils::{crate_tarball, generate_crate_payload};
+use crate::common::{MockIndexProvider, MockStorageProvider, ServiceStateBuilder};
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{TimeDelta, Utc};
+use freighter_auth::fs_backend::{self, FsAuthProvider};
+use freighter_auth::AuthProvider;
+use p384::ecdsa::signature::Signer;
+use p384::ecdsa::{Signature, SigningKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256, Sha384};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+const INDEX_URL: &str = "sparse+https://localhost:5000/index/";
+
+/// `k3.public.` PASERK of the key, like `cargo login` prints
+fn public_key(key: &SigningKey) -> String {
+    let point = key.verifying_key().to_encoded_point(true);
+    format!("k3.public.{}", URL_SAFE_NO_PAD.encode(point.as_bytes()))
+}
+
+/// PASETO's PAE
+fn pre_auth_encode(pieces: &[&[u8]]) -> Vec<u8> {
+    let mut out = (pieces.len() as u64).to_le_bytes().to_vec();
+    for piece in pieces {
+        out.extend_from_slice(&(piece.len() as u64).to_le_bytes());
+        out.extend_from_slice(piece);
+    }
+    out
+}
+
+/// Signs a `v3.public` token like cargo does
+fn asymmetric_token(key: &SigningKey, url: &str, claims: &Value) -> String {
+    let paserk = public_key(key);
+    let key_id = Sha384::new().chain_update("k3.pid.").chain_update(&paserk).finalize();
+    let footer = json!({ "url": url, "kip": format!("k3.pid.{}", URL_SAFE_NO_PAD.encode(&key_id[..33])) }).to_string();
+    let point = key.verifying_key().to_encoded_point(true);
+    let mut message = claims.to_string().into_bytes();
+    let signed = pre_auth_encode(&[point.as_bytes(), b"v3.public.", &message, footer.as_bytes(), b""]);
+    let signature: Signature = key.sign(&signed);
+    message.extend_from_slice(&signature.to_bytes());
+    format!("v3.public.{}.{}", URL_SAFE_NO_PAD.encode(message), URL_SAFE_NO_PAD.encode(footer))
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339()
+}
+
+/// Registry with the key registered for a user
+async fn registry(dir: &TempDir, key: &SigningKey) -> Router {
+    let auth = FsAuthProvider::new(fs_backend::Config {
+        auth_path: dir.path().to_path_buf(),
+        auth_tokens_pepper: [1; 18],
+    })
+    .unwrap();
+    let token = auth.register("alice").await.unwrap();
+
+    let mut config = ServiceStateBuilder::default().config;
+    config.auth_required = true;
+    let router = freighter_server::router(
+        config,
+        Box::new(MockIndexProvider::default()),
+        Box::new(MockStorageProvider::default()),
+        Box::new(auth),
+        None,
+    );
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/keys")
+                .method("PUT")
+                .header(header::AUTHORIZATION, token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "key": public_key(key) }).to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    router
+}
+
+async fn request(router: &Router, method: &str, uri: &str, token: &str, body: Body) -> StatusCode {
+    router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .method(method)
+                .header(header::AUTHORIZATION, token)
+                .body(body)
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn publish(router: &Router, token: &str) -> StatusCode {
+    let tarball = crate_tarball("example-lib", "1.0.0");
+    let payload = generate_crate_payload("example-lib", "1.0.0", &tarball, &[]);
+    request(router, "PUT", "/api/v1/crates/new", token, Body::from(payload)).await
+}
+
+#[tokio::test]
+async fn publish_with_asymmetric_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = SigningKey::from_slice(&[7; 48]).unwrap();
+    let router = registry(&dir, &key).await;
+
+    let cksum = format!("{:x}", Sha256::digest(crate_tarball("example-lib", "1.0.0")));
+    let claims = |name: &str, vers: &str, cksum: &str| {
+        json!({"iat": now(), "mutation": "publish", "name": name, "vers": vers, "cksum": cksum, "v": 1})
+    };
+
+    let other_version = asymmetric_token(&key, INDEX_URL, &claims("example-lib", "2.0.0", &cksum));
+    assert_eq!(publish(&router, &other_version).await, StatusCode::UNAUTHORIZED);
+    let other_tarball = asymmetric_token(&key, INDEX_URL, &claims("example-lib", "1.0.0", "0000"));
+    assert_eq!(publish(&router, &other_tarball).await, StatusCode::UNAUTHORIZED);
+    let read_token = asymmetric_token(&key, INDEX_URL, &json!({"iat": now()}));
+    assert_eq!(publish(&router, &read_token).await, StatusCode::UNAUTHORIZED);
+
+    let token = asymmetric_token(&key, INDEX_URL, &claims("example-lib", "1.0.0", &cksum));
+    assert_eq!(publish(&router, &token).await, StatusCode::OK);
+
+    let other_key = SigningKey::from_slice(&[8; 48]).unwrap();
+    let unknown_key = asymmetric_token(&other_key, INDEX_URL, &claims("example-lib", "1.0.0", &cksum));
+    assert_eq!(publish(&router, &unknown_key).await, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn tokens_are_bound_to_the_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = SigningKey::from_slice(&[7; 48]).unwrap();
+    let router = registry(&dir, &key).await;
+
+    let read_token = asymmetric_token(&key, INDEX_URL, &json!({"iat": now()}));
+    assert_eq!(request(&router, "GET", "/index/config.json", &read_token, Body::empty()).await, StatusCode::OK);
+
+    let other_registry = asymmetric_token(&key, "sparse+https://example.com/index/", &json!({"iat": now()}));
+    assert_eq!(request(&router, "GET", "/index/config.json", &other_registry, Body::empty()).await, StatusCode::UNAUTHORIZED);
+
+    let old = (Utc::now() - TimeDelta::hours(1)).to_rfc3339();
+    let old_token = asymmetric_token(&key, INDEX_URL, &json!({"iat": old}));
+    assert_eq!(request(&router, "GET", "/index/config.json", &old_token, Body::empty()).await, StatusCode::UNAUTHORIZED);
+
+    let yank = json!({"iat": now(), "mutation": "yank", "name": "example-lib", "vers": "1.0.0"});
+    let yank_token = asymmetric_token(&key, INDEX_URL, &yank);
+    assert_eq!(request(&router, "GET", "/index/config.json", &yank_token, Body::empty()).await, StatusCode::UNAUTHORIZED);
+    let uri = "/api/v1/crates/example-lib/1.0.1/yank";
+    assert_eq!(request(&router, "DELETE", uri, &yank_token, Body::empty()).await, StatusCode::UNAUTHORIZED);
+    let uri = "/api/v1/crates/example-lib/1.0.0/unyank";
+    assert_eq!(request(&router, "PUT", uri, &yank_token, Body::empty()).await, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn read_tokens_cant_manage_credentials() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = SigningKey::from_slice(&[7; 48]).unwrap();
+    let router = registry(&dir, &key).await;
+    let read_token = asymmetric_token(&key, INDEX_URL, &json!({"iat": now()}));
+
+    let other_key = SigningKey::from_slice(&[8; 48]).unwrap();
+    let body = Body::from(json!({ "key": public_key(&other_key) }).to_string());
+    assert_eq!(request(&router, "PUT", "/api/v1/keys", &read_token, body).await, StatusCode::UNAUTHORIZED);
+
+    let body = Body::from(json!({ "api_token": { "name": "stolen" } }).to_string());
+    assert_eq!(request(&router, "PUT", "/api/v1/me/tokens", &read_token, body).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(request(&router, "DELETE", "/api/v1/me/tokens/1", &read_token, Body::empty()).await, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,210 @@
+pub mod common;
+
+use crate::common::utils::{crate_tarball, generate_crate_payload};
+use crate::common::{MockAuditSink, MockAuthProvider, ServiceStateBuilder};
+use axum::body::{to_bytes, Body};
+use axum::extract::ConnectInfo;
+use axum::http::{Request, StatusCode};
+use chrono::{TimeZone, Utc};
+use freighter_api_types::audit::{
+    AuditOperation, AuditOutcome, AuditQuery, AuditRecord, AuditRecords, AuditSink,
+};
+use freighter_server::audit::FileAuditSink;
+use freighter_server::{api, router};
+use hyper::header::AUTHORIZATION;
+use semver::Version;
+use std::net::SocketAddr;
+use tower::ServiceExt;
+
+const TOKEN: &str = "12345";
+
+fn example_record(operation: AuditOperation, crate_name: &str, version: &str) -> AuditRecord {
+    AuditRecord {
+        timestamp: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        operation,
+        actor: Some("alice".to_owned()),
+        token_id: None,
+        crate_name: Some(crate_name.to_owned()),
+        version: Some(Version::parse(version).unwrap()),
+        source_ip: None,
+        outcome: AuditOutcome::Success,
+        status: 200,
+        details: None,
+    }
+}
+
+async fn publish_audited(token: &str) -> AuditRecord {
+    let sink = MockAuditSink::default();
+    let state = ServiceStateBuilder::default()
+        .auth_provider(MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        })
+        .audit_sink(sink.clone())
+        .build();
+
+    let payload = generate_crate_payload(
+        "example-lib",
+        "1.0.1",
+        &crate_tarball("example-lib", "1.0.1"),
+        &[],
+    );
+    let source: SocketAddr = "192.0.2.1:1234".parse().unwrap();
+
+    api::api_router()
+        .with_state(state)
+        .oneshot(
+            Request::builder()
+                .uri("/new")
+                .method("PUT")
+                .header(AUTHORIZATION, token)
+                .extension(ConnectInfo(source))
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let mut records = sink.records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    records.pop().unwrap()
+}
+
+#[tokio::test]
+async fn publish_is_audited() {
+    let record = publish_audited(TOKEN).await;
+
+    assert_eq!(record.operation, AuditOperation::Publish);
+    assert_eq!(record.outcome, AuditOutcome::Success);
+    assert_eq!(record.status, 200);
+    assert_eq!(record.actor.as_deref(), Some(TOKEN));
+    assert_eq!(record.crate_name.as_deref(), Some("example-lib"));
+    assert_eq!(record.version, Some(Version::new(1, 0, 1)));
+    assert_eq!(record.source_ip, Some("192.0.2.1".parse().unwrap()));
+
+    // identifies the token without revealing it
+    let token_id = record.token_id.unwrap();
+    assert!(!token_id.contains(TOKEN));
+    assert_eq!(publish_audited(TOKEN).await.token_id.unwrap(), token_id);
+}
+
+#[tokio::test]
+async fn failed_publish_is_audited() {
+    let record = publish_audited("1234").await;
+
+    assert_eq!(record.operation, AuditOperation::Publish);
+    assert_eq!(record.outcome, AuditOutcome::Failure);
+    assert_eq!(record.status, 403);
+    assert_eq!(record.actor, None);
+    assert!(record.token_id.is_some());
+    assert_eq!(record.crate_name.as_deref(), Some("example-lib"));
+}
+
+#[tokio::test]
+async fn query_audit_log() {
+    let sink = MockAuditSink::default();
+    sink.records.lock().unwrap().extend([
+        example_record(AuditOperation::Publish, "example-lib", "1.0.0"),
+        example_record(AuditOperation::Publish, "example-lib", "1.0.1"),
+        example_record(AuditOperation::Yank, "example-lib", "1.0.0"),
+        example_record(AuditOperation::Yank, "other-lib", "1.0.0"),
+    ]);
+
+    let state = ServiceStateBuilder::default()
+        .auth_provider(MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        })
+        .audit_sink(sink)
+        .build_no_arc();
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
+
+    let uri = "/api/v1/audit?crate=Example_Lib&operation=publish";
+    let response = router
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .header(AUTHORIZATION, TOKEN)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = to_bytes(response.into_body(), 100_000).await.unwrap();
+    let records: AuditRecords = serde_json::from_slice(&body).unwrap();
+    let versions: Vec<_> = records
+        .records
+        .iter()
+        .map(|r| r.version.as_ref().unwrap().to_string())
+        .collect();
+    assert_eq!(versions, ["1.0.1", "1.0.0"]);
+}
+
+#[tokio::test]
+async fn query_audit_log_disabled() {
+    let state = ServiceStateBuilder::default()
+        .auth_provider(MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        })
+        .build_no_arc();
+    let router = router(state.config, state.index, state.storage, state.auth, state.audit);
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/audit")
+                .header(AUTHORIZATION, TOKEN)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn file_audit_sink() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("logs/audit.jsonl");
+
+    let sink = FileAuditSink::new(&path).unwrap();
+    sink.record(&example_record(AuditOperation::Publish, "example-lib", "1.0.0"))
+        .await
+        .unwrap();
+    sink.record(&example_record(AuditOperation::Yank, "example-lib", "1.0.0"))
+        .await
+        .unwrap();
+
+    // appends after reopening
+    let sink = FileAuditSink::new(&path).unwrap();
+    sink.record(&example_record(AuditOperation::Publish, "other-lib", "2.0.0"))
+        .await
+        .unwrap();
+
+    let all = sink.query(&AuditQuery::default()).await.unwrap();
+    let operations: Vec<_> = all.iter().map(|r| r.operation).collect();
+    assert_eq!(
+        operations,
+        [
+            AuditOperation::Publish,
+            AuditOperation::Yank,
+            AuditOperation::Publish
+        ]
+    );
+    assert_eq!(all[0].crate_name.as_deref(), Some("other-lib"));
+
+    let query = AuditQuery {
+        crate_name: Some("example_lib".to_owned()),
+        limit: Some(1),
+        ..AuditQuery::default()
+    };
+    let found = sink.query(&query).await.unwrap();
+    assert_eq!(found, [example_record(AuditOperation::Yank, "example-lib", "1.0.0")]);
+}
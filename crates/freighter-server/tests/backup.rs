@@ -0,0 +1,225 @@
+pub mod common;
+
+use crate::common::MemoryStorageProvider;
+use axum::body::Bytes;
+use chrono::Utc;
+use freighter_api_types::index::request::Publish;
+use freighter_api_types::index::{DownloadCount, IndexProvider, PublishContext};
+use freighter_api_types::storage::StorageProvider;
+use freighter_auth::fs_backend::{self, FsAuthProvider};
+use freighter_auth::AuthProvider;
+use freighter_fs_index::{Config, FsIndexProvider};
+use freighter_server::backup;
+use semver::Version;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+struct Registry {
+    index: FsIndexProvider,
+    storage: MemoryStorageProvider,
+    auth: FsAuthProvider,
+}
+
+impl Registry {
+    fn new(dir: &Path) -> Self {
+        Self {
+            index: FsIndexProvider::new(Config::Path(dir.join("index"))).unwrap(),
+            storage: MemoryStorageProvider::default(),
+            auth: FsAuthProvider::new(fs_backend::Config {
+                auth_path: dir.join("auth"),
+                auth_tokens_pepper: [1; 18],
+            })
+            .unwrap(),
+        }
+    }
+
+    async fn publish(&self, name: &str, version: &str, token: &str) {
+        let tarball = Bytes::from(format!("{name}-{version}"));
+        let cksum = Sha256::digest(&tarball).into();
+        self.storage.put_crate(name, version, tarball, cksum).await.unwrap();
+        self.auth.publish(token, name).await.unwrap();
+        let publish = Publish {
+            name: name.to_owned(),
+            vers: Version::parse(version).unwrap(),
+            description: Some(format!("The {name} crate")),
+            keywords: vec!["example".to_owned()],
+            ..Publish::empty()
+        };
+        let context = PublishContext {
+            publisher: Some("alice".to_owned()),
+            tarball_size: 9,
+        };
+        self.index
+            .publish(&publish, cksum, &context, std::pin::pin!(async { Ok(()) }))
+            .await
+            .unwrap();
+    }
+
+    async fn export(&self) -> Vec<u8> {
+        let mut archive = Vec::new();
+        backup::export(&self.index, &self.storage, &self.auth, &mut archive)
+            .await
+            .unwrap();
+        archive
+    }
+
+    async fn import(&self, archive: &[u8]) -> anyhow::Result<backup::BackupStats> {
+        backup::import(&self.index, &self.storage, &self.auth, archive).await
+    }
+}
+
+#[tokio::test]
+async fn export_and_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = Registry::new(&dir.path().join("source"));
+    let token = source.auth.register("alice").await.unwrap();
+    source.publish("example-lib", "1.0.0", &token).await;
+    source.publish("example-lib", "1.1.0", &token).await;
+    source.publish("other-lib", "0.1.0", &token).await;
+    source
+        .index
+        .yank_crate("example-lib", &Version::new(1, 1, 0))
+        .await
+        .unwrap();
+    source
+        .index
+        .record_downloads(&[DownloadCount {
+            name: "example-lib".to_owned(),
+            version: Version::new(1, 0, 0),
+            date: Utc::now().date_naive(),
+            downloads: 5,
+        }])
+        .await
+        .unwrap();
+
+    let archive = source.export().await;
+
+    let restored = Registry::new(&dir.path().join("restored"));
+    let stats = restored.import(&archive).await.unwrap();
+    assert_eq!((stats.crates, stats.versions, stats.skipped_versions), (2, 3, 0));
+
+    let entries = restored.index.get_sparse_entry("example-lib").await.unwrap().entries;
+    let original = source.index.get_sparse_entry("example-lib").await.unwrap().entries;
+    assert!(entries == original);
+    assert!(entries[1].yanked);
+
+    let details = restored
+        .index
+        .get_version_details("example-lib", &Version::new(1, 0, 0))
+        .await
+        .unwrap()
+        .version;
+    assert_eq!(details.description.as_deref(), Some("The example-lib crate"));
+    assert_eq!(details.published_by.unwrap().login, "alice");
+    assert_eq!(details.downloads, 5);
+
+    let krate = restored.index.get_crate_details("example-lib").await.unwrap().krate;
+    assert_eq!(krate.keywords, ["example"]);
+
+    let tarball = restored
+        .storage
+        .pull_crate("other-lib", "0.1.0", [0; 32])
+        .await
+        .unwrap();
+    assert_eq!(&tarball.data[..], b"other-lib-0.1.0");
+
+    // the token and owners are restored too
+    assert_eq!(restored.auth.token_login(&token).await.unwrap(), "alice");
+    restored.auth.auth_yank(&token, "other-lib").await.unwrap();
+
+    // importing again doesn't change anything
+    let stats = restored.import(&archive).await.unwrap();
+    assert_eq!((stats.versions, stats.skipped_versions), (0, 3));
+    let details = restored
+        .index
+        .get_version_details("example-lib", &Version::new(1, 0, 0))
+        .await
+        .unwrap()
+        .version;
+    assert_eq!(details.downloads, 5);
+}
+
+#[tokio::test]
+async fn versions_without_tarballs_are_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = Registry::new(&dir.path().join("source"));
+    let token = source.auth.register("alice").await.unwrap();
+    source.publish("example-lib", "1.0.0", &token).await;
+    source.publish("example-lib", "1.1.0", &token).await;
+    source
+        .storage
+        .delete_crate("example-lib", "1.1.0", [0; 32])
+        .await
+        .unwrap();
+
+    let restored = Registry::new(&dir.path().join("restored"));
+    let stats = restored.import(&source.export().await).await.unwrap();
+    assert_eq!((stats.versions, stats.skipped_versions), (1, 0));
+
+    let entries = restored.index.get_sparse_entry("example-lib").await.unwrap().entries;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].vers, Version::new(1, 0, 0));
+}
+
+#[tokio::test]
+async fn corrupted_tarballs_are_not_imported() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = Registry::new(&dir.path().join("source"));
+    let token = source.auth.register("alice").await.unwrap();
+    source.publish("example-lib", "1.0.0", &token).await;
+    source
+        .storage
+        .crates
+        .lock()
+        .unwrap()
+        .insert("example-lib-1.0.0".to_owned(), Bytes::from_static(b"corrupted"));
+
+    let restored = Registry::new(&dir.path().join("restored"));
+    let err = restored.import(&source.export().await).await.unwrap_err();
+    assert!(err.to_string().contains("checksum"), "{err}");
+    assert!(restored.storage.crates.lock().unwrap().is_empty());
+    assert!(restored.index.get_sparse_entry("example-lib").await.is_err());
+}
+
+#[tokio::test]
+async fn other_archives_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let registry = Registry::new(dir.path());
+    assert!(registry.import(b"not a backup").await.is_err());
+}
+
+#[tokio::test]
+async fn migrate_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = Registry::new(&dir.path().join("source"));
+    let token = source.auth.register("alice").await.unwrap();
+    source.publish("example-lib", "1.0.0", &token).await;
+    source.publish("example-lib", "0.9.0", &token).await;
+    source
+        .index
+        .yank_crate("example-lib", &Version::new(1, 0, 0))
+        .await
+        .unwrap();
+
+    let target = FsIndexProvider::new(Config::Path(dir.path().join("target"))).unwrap();
+    let stats = backup::migrate_index(&source.index, &target).await.unwrap();
+    assert_eq!((stats.crates, stats.versions), (1, 2));
+
+    // in publish order
+    let entries = target.get_sparse_entry("example-lib").await.unwrap().entries;
+    assert_eq!(entries[0].vers, Version::new(1, 0, 0));
+    assert!(entries[0].yanked);
+    assert_eq!(entries[1].vers, Version::new(0, 9, 0));
+
+    let details = target
+        .get_version_details("example-lib", &Version::new(0, 9, 0))
+        .await
+        .unwrap()
+        .version;
+    assert_eq!(details.published_by.unwrap().login, "alice");
+    assert_eq!(details.crate_size, Some(9));
+
+    // running it again only verifies
+    let stats = backup::migrate_index(&source.index, &target).await.unwrap();
+    assert_eq!((stats.versions, stats.skipped_versions), (0, 2));
+}
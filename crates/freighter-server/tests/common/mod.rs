@@ -1,27 +1,37 @@
 pub mod utils;
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use axum::body::Bytes;
+use freighter_api_types::audit::{AuditQuery, AuditRecord, AuditSink};
 use freighter_api_types::index::request::{ListQuery, Publish};
 use freighter_api_types::index::response::{
-    CompletedPublication, CrateVersion, ListAll, ListAllCrateEntry, ListAllCrateVersion,
-    SearchResults,
+    CompletedPublication, CrateDetails, CrateDetailsEntry, CrateDetailsVersion, CrateDownloads,
+    CrateVersion, ListAll, ListAllCrateEntry, ListAllCrateVersion, ReverseDependencies,
+    ReverseDependency, SearchResults, VersionDetails, VersionDetailsEntry, VersionDownloads,
+    VersionPublisher,
 };
 use freighter_api_types::index::{
-    CrateVersionExists, IndexError, IndexProvider, IndexResult, SparseEntries,
+    CrateVersionExists, DownloadCount, IndexError, IndexProvider, IndexResult, PublishContext,
+    SparseEntries,
 };
 use freighter_api_types::ownership::response::ListedOwner;
-use freighter_api_types::storage::{FileResponse, StorageProvider, StorageResult};
+use freighter_api_types::storage::{FileResponse, StorageError, StorageProvider, StorageResult};
 use freighter_auth::{AuthError, AuthProvider, AuthResult};
+use freighter_server::policy::CrateNamePolicy;
+use freighter_server::webhooks::WebhooksConfig;
 use freighter_server::{ServiceConfig, ServiceState};
 use semver::Version;
 
+/// 2023-11-14T22:13:20Z
+pub const MOCK_LAST_MODIFIED: i64 = 1_700_000_000;
+
 #[derive(Default)]
 pub struct MockIndexProvider {
     pub crates: BTreeMap<String, Vec<CrateVersion>>,
@@ -37,17 +47,95 @@ impl IndexProvider for MockIndexProvider {
 
     async fn get_sparse_entry(&self, crate_name: &str) -> IndexResult<SparseEntries> {
         if let Some(entries) = self.crates.get(crate_name).cloned() {
-            Ok(SparseEntries { entries, last_modified: None })
+            Ok(SparseEntries {
+                entries,
+                last_modified: chrono::DateTime::from_timestamp(MOCK_LAST_MODIFIED, 0),
+            })
         } else {
             Err(IndexError::NotFound)
         }
     }
+    async fn get_crate_details(&self, crate_name: &str) -> IndexResult<CrateDetails> {
+        let versions = self.crates.get(crate_name).ok_or(IndexError::NotFound)?;
+        let mut versions: Vec<_> = versions
+            .iter()
+            .map(|v| CrateDetailsVersion {
+                num: v.vers.clone(),
+                yanked: v.yanked,
+                downloads: 1,
+            })
+            .collect();
+        versions.reverse();
+        let max_version = CrateDetailsVersion::max_version(&versions).ok_or(IndexError::NotFound)?;
+
+        Ok(CrateDetails {
+            krate: CrateDetailsEntry {
+                id: crate_name.to_owned(),
+                name: crate_name.to_owned(),
+                description: Some(format!("Description {crate_name}")),
+                homepage: Some("e.com".to_owned()),
+                documentation: None,
+                repository: Some("ssh://git@b.com/a/f.git".to_owned()),
+                keywords: vec!["example".to_owned()],
+                categories: vec!["a".to_owned(), "x".to_owned()],
+                created_at: Default::default(),
+                updated_at: Default::default(),
+                max_version,
+                downloads: versions.len() as u64,
+            },
+            versions,
+        })
+    }
+    async fn get_version_details(
+        &self,
+        crate_name: &str,
+        version: &Version,
+    ) -> IndexResult<VersionDetails> {
+        let entry = self
+            .crates
+            .get(crate_name)
+            .and_then(|versions| versions.iter().find(|v| &v.vers == version))
+            .ok_or(IndexError::NotFound)?
+            .clone();
+
+        Ok(VersionDetails {
+            version: VersionDetailsEntry {
+                krate: entry.name,
+                num: entry.vers,
+                checksum: entry.cksum,
+                yanked: entry.yanked,
+                features: entry.features,
+                dependencies: entry.deps,
+                links: entry.links,
+                description: Some(format!("Description {crate_name}")),
+                license: Some("MIT".to_owned()),
+                license_file: None,
+                homepage: Some("e.com".to_owned()),
+                documentation: None,
+                repository: Some("ssh://git@b.com/a/f.git".to_owned()),
+                created_at: chrono::DateTime::from_timestamp(MOCK_LAST_MODIFIED, 0),
+                published_by: Some(VersionPublisher {
+                    login: "example-user".to_owned(),
+                }),
+                crate_size: Some(1234),
+                downloads: 1,
+            },
+        })
+    }
     async fn confirm_existence(
         &self,
-        _crate_name: &str,
-        _version: &Version,
+        crate_name: &str,
+        version: &Version,
     ) -> IndexResult<CrateVersionExists> {
-        unimplemented!()
+        let entry = self
+            .crates
+            .get(crate_name)
+            .and_then(|versions| versions.iter().find(|v| &v.vers == version))
+            .ok_or(IndexError::NotFound)?;
+        Ok(CrateVersionExists {
+            yanked: entry.yanked,
+            tarball_checksum: entry.cksum,
+        })
     }
     async fn yank_crate(&self, _crate_name: &str, _version: &Version) -> IndexResult<()> {
         unimplemented!()
@@ -62,6 +150,7 @@ impl IndexProvider for MockIndexProvider {
         &self,
         _version: &Publish,
         _checksum: [u8; 32],
+        _context: &PublishContext,
         end_step: Pin<&mut (dyn Future<Output = IndexResult<()>> + Send)>,
     ) -> IndexResult<CompletedPublication> {
         end_step.await?;
@@ -91,26 +180,96 @@ impl IndexProvider for MockIndexProvider {
                     documentation: None,
                     keywords: vec!["example".to_owned()],
                     categories: vec!["a".to_owned(), "x".to_owned()],
+                    // matches get_downloads
+                    downloads: v.len() as u64,
                 }
             })
             .collect();
 
         Ok(ListAll { results: crates })
     }
+
+    async fn record_downloads(&self, _downloads: &[DownloadCount]) -> IndexResult<()> {
+        Ok(())
+    }
+
+    async fn get_downloads(&self, crate_name: &str) -> IndexResult<CrateDownloads> {
+        let versions = self.crates.get(crate_name).ok_or(IndexError::NotFound)?;
+        let date = chrono::DateTime::from_timestamp(MOCK_LAST_MODIFIED, 0)
+            .unwrap()
+            .date_naive();
+        let version_downloads = versions
+            .iter()
+            .map(|v| VersionDownloads {
+                version: v.vers.clone(),
+                downloads: 1,
+                date,
+            })
+            .collect();
+        Ok(CrateDownloads { version_downloads })
+    }
+    async fn get_reverse_dependencies(&self, crate_name: &str) -> IndexResult<ReverseDependencies> {
+        if !self.crates.contains_key(crate_name) {
+            return Err(IndexError::NotFound);
+        }
+        let dependencies = self
+            .crates
+            .values()
+            .flatten()
+            .flat_map(|v| {
+                v.deps
+                    .iter()
+                    .filter(|d| d.package.as_ref().unwrap_or(&d.name) == crate_name)
+                    .map(|d| ReverseDependency {
+                        krate: v.name.clone(),
+                        num: v.vers.clone(),
+                        yanked: v.yanked,
+                        req: d.req.clone(),
+                        features: d.features.clone(),
+                        optional: d.optional,
+                        default_features: d.default_features,
+                        target: d.target.clone(),
+                        kind: d.kind,
+                    })
+            })
+            .collect();
+        Ok(ReverseDependencies { dependencies })
+    }
 }
 
 #[derive(Clone, Default)]
-pub struct MockStorageProvider;
+pub struct MockStorageProvider {
+    /// Whether it makes presigned URLs for downloads
+    pub presigned_urls: bool,
+}
 
 #[async_trait]
 impl StorageProvider for MockStorageProvider {
     async fn pull_crate(
         &self,
-        _name: &str,
-        _version: &str,
+        name: &str,
+        version: &str,
         _digest: [u8; 32],
     ) -> StorageResult<FileResponse> {
-        unimplemented!()
+        Ok(FileResponse {
+            last_modified: None,
+            data: Bytes::from(format!("{name}-{version}")),
+        })
+    }
+
+    async fn presigned_crate_url(
+        &self,
+        name: &str,
+        version: &str,
+        _digest: [u8; 32],
+        expires_in: Duration,
+    ) -> StorageResult<Option<String>> {
+        Ok(self.presigned_urls.then(|| {
+            format!(
+                "https://storage.example.com/{name}-{version}.crate?expires={}",
+                expires_in.as_secs()
+            )
+        }))
     }
 
     async fn put_crate(
@@ -137,6 +296,101 @@ impl StorageProvider for MockStorageProvider {
     }
 }
 
+/// Keeps the records in memory, shared with the test.
+/// Keeps the crates in memory, shared with the test
+#[derive(Clone, Default)]
+pub struct MemoryStorageProvider {
+    pub crates: Arc<Mutex<HashMap<String, Bytes>>>,
+    /// Whether it makes presigned URLs for downloads
+    pub presigned_urls: bool,
+}
+
+#[async_trait]
+impl StorageProvider for MemoryStorageProvider {
+    async fn pull_crate(
+        &self,
+        name: &str,
+        version: &str,
+        _digest: [u8; 32],
+    ) -> StorageResult<FileResponse> {
+        let data = self
+            .crates
+            .lock()
+            .unwrap()
+            .get(&format!("{name}-{version}"))
+            .cloned()
+            .ok_or(StorageError::NotFound)?;
+        Ok(FileResponse {
+            last_modified: None,
+            data,
+        })
+    }
+
+    async fn presigned_crate_url(
+        &self,
+        name: &str,
+        version: &str,
+        _digest: [u8; 32],
+        _expires_in: Duration,
+    ) -> StorageResult<Option<String>> {
+        Ok(self
+            .presigned_urls
+            .then(|| format!("https://storage.example.com/{name}-{version}.crate")))
+    }
+
+    async fn put_crate(
+        &self,
+        name: &str,
+        version: &str,
+        crate_bytes: Bytes,
+        _sha256: [u8; 32],
+    ) -> StorageResult<()> {
+        self.crates
+            .lock()
+            .unwrap()
+            .insert(format!("{name}-{version}"), crate_bytes);
+        Ok(())
+    }
+
+    async fn delete_crate(
+        &self,
+        name: &str,
+        version: &str,
+        _digest: [u8; 32],
+    ) -> StorageResult<()> {
+        self.crates.lock().unwrap().remove(&format!("{name}-{version}"));
+        Ok(())
+    }
+
+    async fn healthcheck(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MockAuditSink {
+    pub records: Arc<Mutex<Vec<AuditRecord>>>,
+}
+
+#[async_trait]
+impl AuditSink for MockAuditSink {
+    async fn record(&self, record: &AuditRecord) -> anyhow::Result<()> {
+        self.records.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+
+    async fn query(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditRecord>> {
+        let records = self.records.lock().unwrap();
+        Ok(records
+            .iter()
+            .rev()
+            .filter(|r| query.matches(r))
+            .take(query.limit())
+            .cloned()
+            .collect())
+    }
+}
+
 #[derive(Default)]
 pub struct MockAuthProvider {
     pub valid_tokens: HashSet<String>,
@@ -163,6 +417,13 @@ impl AuthProvider for MockAuthProvider {
     ) -> AuthResult<()> {
         unimplemented!()
     }
+    async fn token_login(&self, token: &str) -> AuthResult<String> {
+        if self.valid_tokens.contains(token) {
+            Ok(token.to_owned())
+        } else {
+            Err(AuthError::InvalidCredentials)
+        }
+    }
     async fn publish(&self, token: &str, _crate_name: &str) -> AuthResult<()> {
         if self.valid_tokens.contains(token) {
             Ok(())
@@ -200,6 +461,7 @@ pub struct ServiceStateBuilder {
     pub index: MockIndexProvider,
     pub storage: MockStorageProvider,
     pub auth: MockAuthProvider,
+    pub audit: Option<MockAuditSink>,
 }
 
 impl Default for ServiceStateBuilder {
@@ -213,10 +475,18 @@ impl Default for ServiceStateBuilder {
                 allow_registration: true,
                 auth_required: false,
                 crate_size_limit: 1024 * 1024,
+                presigned_download_ttl_secs: None,
+                crate_name_policy: Default::default(),
+                webhooks: Default::default(),
+                mirror: None,
+                git_index: None,
+                trusted_publishing: None,
+                read_acl: None,
             },
             index: Default::default(),
             storage: Default::default(),
             auth: Default::default(),
+            audit: None,
         }
     }
 }
@@ -240,12 +510,36 @@ impl ServiceStateBuilder {
         self
     }
 
+    #[must_use]
+    pub fn audit_sink(mut self, sink: MockAuditSink) -> Self {
+        self.audit = Some(sink);
+        self
+    }
+
     #[must_use]
     pub fn auth_required(mut self, req: bool) -> Self {
         self.config.auth_required = req;
         self
     }
 
+    #[must_use]
+    pub fn presigned_download_ttl_secs(mut self, ttl: Option<u64>) -> Self {
+        self.config.presigned_download_ttl_secs = ttl;
+        self
+    }
+
+    #[must_use]
+    pub fn crate_name_policy(mut self, policy: CrateNamePolicy) -> Self {
+        self.config.crate_name_policy = policy;
+        self
+    }
+
+    #[must_use]
+    pub fn webhooks(mut self, webhooks: WebhooksConfig) -> Self {
+        self.config.webhooks = webhooks;
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> Arc<ServiceState> {
         Arc::new(ServiceState {
@@ -253,6 +547,14 @@ impl ServiceStateBuilder {
             index: Box::new(self.index),
             storage: Box::new(self.storage),
             auth: Box::new(self.auth),
+            download_counts: Default::default(),
+            webhooks: Default::default(),
+            mirror: Default::default(),
+            git_index: Default::default(),
+            trusted_publishing: None,
+            audit: self
+                .audit
+                .map(|a| Box::new(a) as Box<dyn AuditSink + Send + Sync>),
         })
     }
 
@@ -263,6 +565,14 @@ impl ServiceStateBuilder {
             index: Box::new(self.index),
             storage: Box::new(self.storage),
             auth: Box::new(self.auth),
+            download_counts: Default::default(),
+            webhooks: Default::default(),
+            mirror: Default::default(),
+            git_index: Default::default(),
+            trusted_publishing: None,
+            audit: self
+                .audit
+                .map(|a| Box::new(a) as Box<dyn AuditSink + Send + Sync>),
         }
     }
 }
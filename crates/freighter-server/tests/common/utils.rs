@@ -1,3 +1,5 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use freighter_api_types::index::request::PublishDependency;
 use freighter_api_types::index::response::CrateVersion;
 use semver::{Version, VersionReq};
@@ -67,3 +69,32 @@ pub fn generate_crate_payload(
     payload.extend_from_slice(tarball);
     payload
 }
+
+/// A `.crate` tarball with only a `Cargo.toml` for the given crate version
+#[must_use]
+pub fn crate_tarball(name: &str, vers: &str) -> Vec<u8> {
+    let manifest = format!("[package]\nname = \"{name}\"\nversion = \"{vers}\"\n");
+    let path = format!("{name}-{vers}/Cargo.toml");
+    tarball(&[tar_entry(&path, tar::EntryType::Regular, manifest.as_bytes())])
+}
+
+/// Header and data of a tar entry. The path isn't sanitized.
+#[must_use]
+pub fn tar_entry(path: &str, entry_type: tar::EntryType, data: &[u8]) -> (tar::Header, Vec<u8>) {
+    let mut header = tar::Header::new_old();
+    header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+    header.set_entry_type(entry_type);
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    (header, data.to_vec())
+}
+
+#[must_use]
+pub fn tarball(entries: &[(tar::Header, Vec<u8>)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (header, data) in entries {
+        builder.append(header, &data[..]).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
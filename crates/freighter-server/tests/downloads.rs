@@ -0,0 +1,121 @@
+pub mod common;
+
+use crate::common::utils::crate_version;
+use crate::common::{MockIndexProvider, MockStorageProvider, ServiceStateBuilder};
+use axum::body::{to_bytes, Body};
+use axum::http::header::LOCATION;
+use axum::http::{Request, StatusCode};
+use axum::response::Response;
+use freighter_server::downloads;
+use std::collections::BTreeMap;
+use tower::ServiceExt;
+
+async fn download(builder: ServiceStateBuilder, uri: &str) -> Response {
+    let crates = BTreeMap::from([(
+        "example-lib".to_owned(),
+        vec![crate_version("example-lib", "1.3.0")],
+    )]);
+
+    let state = builder
+        .index_provider(MockIndexProvider { crates })
+        .build();
+
+    downloads::downloads_router()
+        .with_state(state)
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn download_crate() {
+    let response = download(ServiceStateBuilder::default(), "/example-lib/1.3.0").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 100_000).await.unwrap();
+    assert_eq!(&body[..], b"example-lib-1.3.0");
+}
+
+#[tokio::test]
+async fn download_missing_crate() {
+    let builder = ServiceStateBuilder::default()
+        .presigned_download_ttl_secs(Some(60))
+        .storage_provider(MockStorageProvider {
+            presigned_urls: true,
+        });
+    let response = download(builder, "/example-lib/2.0.0").await;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn download_crate_presigned_redirect() {
+    let builder = ServiceStateBuilder::default()
+        .presigned_download_ttl_secs(Some(60))
+        .storage_provider(MockStorageProvider {
+            presigned_urls: true,
+        });
+    let response = download(builder, "/example-lib/1.3.0").await;
+
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        response.headers()[LOCATION],
+        "https://storage.example.com/example-lib-1.3.0.crate?expires=60"
+    );
+}
+
+#[tokio::test]
+async fn download_crate_presigned_unsupported() {
+    // falls back to serving the crate
+    let builder = ServiceStateBuilder::default().presigned_download_ttl_secs(Some(60));
+    let response = download(builder, "/example-lib/1.3.0").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 100_000).await.unwrap();
+    assert_eq!(&body[..], b"example-lib-1.3.0");
+}
+
+#[tokio::test]
+async fn download_counts() {
+    let crates = BTreeMap::from([(
+        "example-lib".to_owned(),
+        vec![
+            crate_version("example-lib", "1.3.0"),
+            crate_version("example-lib", "1.3.1"),
+        ],
+    )]);
+
+    let state = ServiceStateBuilder::default()
+        .index_provider(MockIndexProvider { crates })
+        .build();
+
+    for uri in [
+        "/example-lib/1.3.0",
+        "/example-lib/1.3.0",
+        "/example-lib/1.3.1",
+        "/example-lib/2.0.0",
+    ] {
+        downloads::downloads_router()
+            .with_state(state.clone())
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+    }
+
+    let mut pending = state.download_counts.pending();
+    pending.sort_by(|a, b| a.version.cmp(&b.version));
+    let counts: Vec<_> = pending
+        .iter()
+        .map(|c| (c.name.as_str(), c.version.to_string(), c.downloads))
+        .collect();
+    assert_eq!(
+        counts,
+        [
+            ("example-lib", "1.3.0".to_owned(), 2),
+            ("example-lib", "1.3.1".to_owned(), 1),
+        ]
+    );
+
+    state.download_counts.flush(&*state.index).await;
+    assert!(state.download_counts.pending().is_empty());
+}
@@ -7,13 +7,15 @@ use deadpool_postgres::Config;
 use freighter_api_types::index::request::{Publish, PublishDependency};
 use freighter_api_types::index::IndexProvider;
 use freighter_auth::fs_backend::FsAuthProvider;
-use freighter_auth::AuthProvider;
+use freighter_auth::pg_backend::PgAuthProvider;
+use freighter_auth::{AuthError, AuthProvider};
 use freighter_client::Client;
 use freighter_fs_index::FsIndexProvider;
 use freighter_pg_index::PgIndexProvider;
 use freighter_server::ServiceConfig;
 use freighter_storage::s3_client::S3StorageProvider;
 use semver::{Version, VersionReq};
+use sha2::Digest;
 use std::collections::HashMap;
 use std::env::var;
 use std::future::Future;
@@ -33,6 +35,7 @@ struct TestServerConfig {
     bucket_access_key_id: String,
     bucket_access_key_secret: String,
     auth_required: bool,
+    presigned_download_ttl_secs: Option<u64>,
 }
 
 impl TestServerConfig {
@@ -60,6 +63,7 @@ impl TestServerConfig {
             bucket_access_key_secret: var("BUCKET_ACCESS_KEY_SECRET")
                 .unwrap_or("valid-secret".to_owned()),
             auth_required: false,
+            presigned_download_ttl_secs: None,
         }
     }
 }
@@ -89,6 +93,13 @@ fn server(
         allow_registration: true,
         auth_required: config.auth_required,
         crate_size_limit: 1024 * 1024,
+        presigned_download_ttl_secs: config.presigned_download_ttl_secs,
+        crate_name_policy: Default::default(),
+        webhooks: Default::default(),
+        mirror: None,
+        git_index: None,
+        trusted_publishing: None,
+        read_acl: None,
     };
 
     let router = freighter_server::router(
@@ -96,8 +107,9 @@ fn server(
         Box::new(index_client),
         Box::new(storage_client),
         Box::new(auth_client),
+        None,
     );
-    let service = router.into_make_service();
+    let service = router.into_make_service_with_connect_info::<SocketAddr>();
     let (tx, rx) = oneshot::channel();
     Ok((rx, async move {
         let listener = TcpListener::bind(addr).await.unwrap();
@@ -157,6 +169,80 @@ async fn e2e_publish_crate_fs_auth_required() {
     e2e_publish_crate_in_index(FsIndexProvider::new(index_config).unwrap(), config).await;
 }
 
+#[tokio::test]
+async fn e2e_publish_crate_fs_presigned_downloads() {
+    let mut config = TestServerConfig::from_env(3004);
+    config.presigned_download_ttl_secs = Some(60);
+    let dir = tempfile::tempdir().unwrap();
+
+    type ProviderConfig = <FsIndexProvider as IndexProvider>::Config;
+    let index_config = ProviderConfig::Path(dir.path().into());
+    e2e_publish_crate_in_index(FsIndexProvider::new(index_config).unwrap(), config).await;
+}
+
+#[tokio::test]
+async fn e2e_pg_auth() {
+    let config = TestServerConfig::from_env(3005);
+    type AuthConfig = <PgAuthProvider as AuthProvider>::Config;
+    let auth = PgAuthProvider::new(AuthConfig { auth_db: config.db }).unwrap();
+
+    use rand::distr::{Alphanumeric, SampleString};
+    let test_unique_str = Alphanumeric.sample_string(&mut rand::rng(), 12);
+    let user1_name = format!("user1-{test_unique_str}");
+    let user2_name = format!("user2-{test_unique_str}");
+    let crate1 = format!("crate1-{test_unique_str}");
+
+    let user1 = auth.register(&user1_name).await.unwrap();
+    let user2 = auth.register(&user2_name).await.unwrap();
+    assert_ne!(user1, user2);
+    assert!(matches!(auth.register(&user1_name).await, Err(AuthError::Forbidden)));
+    assert_eq!(auth.token_login(&user1).await.unwrap(), user1_name);
+    assert!(matches!(auth.token_login("badtoken").await, Err(AuthError::InvalidCredentials)));
+
+    assert!(matches!(auth.auth_yank(&user1, &crate1).await, Err(AuthError::CrateNotFound)));
+    assert!(matches!(auth.publish("badtoken", &crate1).await, Err(AuthError::InvalidCredentials)));
+    auth.publish(&user1, &crate1).await.unwrap();
+    assert!(matches!(auth.publish(&user2, &crate1).await, Err(AuthError::Forbidden)));
+    auth.auth_yank(&user1, &crate1).await.unwrap();
+
+    assert!(matches!(
+        auth.add_owners(&user1, &["nobody"], &crate1).await,
+        Err(AuthError::UserNotFound)
+    ));
+    auth.add_owners(&user1, &[&user2_name], &crate1).await.unwrap();
+    let owners = auth.list_owners("", &crate1).await.unwrap();
+    assert_eq!(owners.len(), 2);
+    auth.publish(&user2, &crate1).await.unwrap();
+
+    auth.remove_owners(&user2, &[&user1_name], &crate1).await.unwrap();
+    assert!(matches!(auth.publish(&user1, &crate1).await, Err(AuthError::Forbidden)));
+    assert!(matches!(
+        auth.remove_owners(&user2, &[&user2_name], &crate1).await,
+        Err(AuthError::Forbidden)
+    ));
+    auth.auth_yank(&user2, &crate1).await.unwrap();
+
+    let backup = auth.export_backup().await.unwrap();
+    assert_eq!(backup.crate_owners[&crate1].len(), 1);
+    assert!(backup.tokens.iter().any(|t| t.login == user1_name));
+
+    // restored tokens are hashed the same way
+    let user3_name = format!("user3-{test_unique_str}");
+    let user3 = format!("frp_{test_unique_str}");
+    let mut restore = freighter_auth::AuthBackup::default();
+    restore.tokens.push(freighter_auth::BackupToken {
+        login: user3_name.clone(),
+        format: "pg-sha256".into(),
+        token: hex::encode(sha2::Sha256::digest(&user3)),
+        created_at: None,
+        details: None,
+    });
+    restore.crate_owners.insert(crate1.clone(), [user3_name.clone()].into());
+    auth.import_backup(&restore).await.unwrap();
+    assert_eq!(auth.token_login(&user3).await.unwrap(), user3_name);
+    auth.auth_yank(&user3, &crate1).await.unwrap();
+}
+
 async fn e2e_publish_crate_in_index(
     index_client: impl IndexProvider + Send + 'static,
     config: TestServerConfig,
@@ -204,7 +290,7 @@ async fn e2e_publish_crate_in_index(
     }
 
     // 2. Publish a crate!
-    let tarball = [1u8; 100];
+    let tarball = common::utils::crate_tarball(&crate_to_publish, "1.2.3");
 
     freighter_client
         .publish(
@@ -316,7 +402,7 @@ async fn e2e_publish_crate_in_index(
                 badges: None,
                 links: None,
             },
-            &tarball,
+            &common::utils::crate_tarball(&crate_to_publish_2, "2.0.0"),
         )
         .await
         .unwrap();
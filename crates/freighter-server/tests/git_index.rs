@@ -0,0 +1,211 @@
+pub mod common;
+
+use crate::common::utils::{crate_tarball, generate_crate_payload};
+use crate::common::{MockAuthProvider, MockStorageProvider, ServiceStateBuilder};
+use freighter_api_types::index::request::Publish;
+use freighter_api_types::index::response::CrateVersion;
+use freighter_api_types::index::{IndexProvider, PublishContext};
+use freighter_auth::yes_backend::{self, YesAuthProvider};
+use freighter_fs_index::{Config, FsIndexProvider};
+use freighter_server::git_index::{self, index_path, GitIndexConfig};
+use freighter_server::{router, ServiceConfig, ServiceState};
+use semver::Version;
+use tower::ServiceExt;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+
+const TOKEN: &str = "12345";
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn git_index_config(dir: &TempDir) -> ServiceConfig {
+    let mut config = ServiceStateBuilder::default().config;
+    config.git_index = Some(GitIndexConfig {
+        path: dir.path().join("index.git"),
+        refresh_secs: 60,
+    });
+    config
+}
+
+#[test]
+fn index_paths() {
+    assert_eq!(index_path("a"), "1/a");
+    assert_eq!(index_path("ab"), "2/ab");
+    assert_eq!(index_path("abc"), "3/a/abc");
+    assert_eq!(index_path("Serde_JSON"), "se/rd/serde_json");
+}
+
+#[tokio::test]
+async fn rebuild_matches_the_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = FsIndexProvider::new(Config::Path(dir.path().join("index"))).unwrap();
+    let publish = Publish {
+        name: "example-lib".into(),
+        vers: Version::new(1, 0, 0),
+        ..Publish::empty()
+    };
+    index
+        .publish(&publish, [1; 32], &PublishContext::default(), std::pin::pin!(async { Ok(()) }))
+        .await
+        .unwrap();
+
+    let state = ServiceState::new(
+        git_index_config(&dir),
+        Box::new(index),
+        Box::new(MockStorageProvider::default()),
+        Box::new(MockAuthProvider::default()),
+        None,
+    );
+    git_index::rebuild(&state).await.unwrap();
+
+    let repo = dir.path().join("index.git");
+    let file = git(&repo, &["show", "master:ex/am/example-lib"]);
+    let entry: CrateVersion = serde_json::from_str(file.trim()).unwrap();
+    assert_eq!(entry.vers, Version::new(1, 0, 0));
+    assert_eq!(entry.cksum, [1; 32]);
+
+    let config: serde_json::Value = serde_json::from_str(&git(&repo, &["show", "master:config.json"])).unwrap();
+    assert_eq!(config["dl"], "https://localhost:4000");
+
+    // nothing has changed
+    git_index::rebuild(&state).await.unwrap();
+    assert_eq!(git(&repo, &["rev-list", "--count", "master"]).trim(), "1");
+}
+
+#[tokio::test]
+async fn git_index_is_served_and_updated() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = FsIndexProvider::new(Config::Path(dir.path().join("index"))).unwrap();
+    let state = ServiceState::new(
+        git_index_config(&dir),
+        Box::new(index),
+        Box::new(MockStorageProvider::default()),
+        Box::new(MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        }),
+        None,
+    );
+    // the server rebuilds it too, but in the background
+    git_index::rebuild(&state).await.unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = router(state.config, state.index, state.storage, state.auth, state.audit);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let payload = generate_crate_payload(
+        "example-lib",
+        "1.0.1",
+        &crate_tarball("example-lib", "1.0.1"),
+        &[],
+    );
+    let response = reqwest::Client::new()
+        .put(format!("http://{addr}/api/v1/crates/new"))
+        .header("authorization", TOKEN)
+        .body(payload)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let clone = dir.path().join("clone");
+    let url = format!("http://{addr}/git/index");
+    let output = tokio::process::Command::new("git")
+        .args(["clone", "--quiet", &url])
+        .arg(&clone)
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let file = std::fs::read_to_string(clone.join("ex/am/example-lib")).unwrap();
+    let entry: CrateVersion = serde_json::from_str(file.trim()).unwrap();
+    assert_eq!(entry.vers, Version::new(1, 0, 1));
+    assert!(clone.join("config.json").exists());
+}
+
+#[tokio::test]
+async fn yank_with_another_spelling() {
+    let dir = tempfile::tempdir().unwrap();
+    let auth = YesAuthProvider::new(yes_backend::Config {
+        auth_allow_full_access_without_any_checks: true,
+    })
+    .unwrap();
+    let index = FsIndexProvider::new(Config::Path(dir.path().join("index"))).unwrap();
+    let state = ServiceState::new(
+        git_index_config(&dir),
+        Box::new(index),
+        Box::new(MockStorageProvider::default()),
+        Box::new(auth),
+        None,
+    );
+    git_index::rebuild(&state).await.unwrap();
+    let app = router(state.config, state.index, state.storage, state.auth, state.audit);
+
+    let request = |method: &str, uri: &str, body: Vec<u8>| {
+        axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", TOKEN)
+            .body(axum::body::Body::from(body))
+            .unwrap()
+    };
+    let payload = generate_crate_payload("example-lib", "1.0.0", &crate_tarball("example-lib", "1.0.0"), &[]);
+    let response = app.clone().oneshot(request("PUT", "/api/v1/crates/new", payload)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let response = app.oneshot(request("DELETE", "/api/v1/crates/Example_Lib/1.0.0/yank", vec![])).await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    let repo = dir.path().join("index.git");
+    let file = git(&repo, &["show", "master:ex/am/example-lib"]);
+    let entry: CrateVersion = serde_json::from_str(file.trim()).unwrap();
+    assert!(entry.yanked);
+    assert_eq!(git(&repo, &["ls-tree", "-r", "--name-only", "master"]), "config.json\nex/am/example-lib\n");
+}
+
+/// Waits for the repository's files to contain the path
+async fn wait_for_file(repo: &Path, path: &str) {
+    for _ in 0..50 {
+        let files = Command::new("git").arg("-C").arg(repo).args(["ls-tree", "-r", "--name-only", "master"]).output().unwrap();
+        if String::from_utf8_lossy(&files.stdout).lines().any(|f| f == path) {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("{path} isn't in the git index");
+}
+
+#[tokio::test]
+async fn changes_of_other_instances_are_picked_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = git_index_config(&dir);
+    config.git_index.as_mut().unwrap().refresh_secs = 1;
+    let _app = router(
+        config,
+        Box::new(FsIndexProvider::new(Config::Path(dir.path().join("index"))).unwrap()),
+        Box::new(MockStorageProvider::default()),
+        Box::new(MockAuthProvider::default()),
+        None,
+    );
+    let repo = dir.path().join("index.git");
+    wait_for_file(&repo, "config.json").await;
+
+    // published by another instance sharing the index
+    let other = FsIndexProvider::new(Config::Path(dir.path().join("index"))).unwrap();
+    let publish = Publish {
+        name: "example-lib".into(),
+        vers: Version::new(1, 0, 0),
+        ..Publish::empty()
+    };
+    other
+        .publish(&publish, [1; 32], &PublishContext::default(), std::pin::pin!(async { Ok(()) }))
+        .await
+        .unwrap();
+
+    wait_for_file(&repo, "ex/am/example-lib").await;
+}
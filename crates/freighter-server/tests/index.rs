@@ -5,7 +5,12 @@ use crate::common::{MockIndexProvider, ServiceStateBuilder};
 use axum::body::to_bytes;
 
 use axum::body::Body;
+use axum::http::header::{
+    HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
 use axum::http::{Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
 use freighter_server::index;
 use std::collections::BTreeMap;
 use tower::ServiceExt;
@@ -98,3 +103,90 @@ async fn valid_crate() {
 "#
     );
 }
+
+async fn get_index_entry(router: &Router, headers: &[(HeaderName, &str)]) -> Response {
+    let mut request = Request::builder().uri("/ex/am/example-lib");
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+
+    router
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+fn example_lib_router() -> Router {
+    let crates = BTreeMap::from([(
+        "example-lib".to_owned(),
+        ["1.3.0", "1.3.1"]
+            .iter()
+            .map(|version| crate_version("example-lib", version))
+            .collect::<Vec<_>>(),
+    )]);
+
+    let state = ServiceStateBuilder::default()
+        .index_provider(MockIndexProvider { crates })
+        .build();
+
+    index::index_router().with_state(state)
+}
+
+#[tokio::test]
+async fn index_etag() {
+    let router = example_lib_router();
+
+    let response = get_index_entry(&router, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[ETAG].to_str().unwrap().to_owned();
+    assert!(etag.starts_with('"'));
+
+    // the same content has the same ETag
+    let response = get_index_entry(&router, &[]).await;
+    assert_eq!(response.headers()[ETAG], etag);
+
+    let response = get_index_entry(&router, &[(IF_NONE_MATCH, &etag)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], etag);
+    assert!(to_bytes(response.into_body(), usize::MAX).await.unwrap().is_empty());
+
+    let weak_etags = format!("\"other\", W/{etag}");
+    let response = get_index_entry(&router, &[(IF_NONE_MATCH, &weak_etags)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let response = get_index_entry(&router, &[(IF_NONE_MATCH, "\"other\"")]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!to_bytes(response.into_body(), usize::MAX).await.unwrap().is_empty());
+
+    // If-None-Match takes precedence
+    let response = get_index_entry(
+        &router,
+        &[
+            (IF_NONE_MATCH, "\"other\""),
+            (IF_MODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:20 GMT"),
+        ],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn index_if_modified_since() {
+    let router = example_lib_router();
+
+    let response = get_index_entry(&router, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let last_modified = response.headers()[LAST_MODIFIED].to_str().unwrap().to_owned();
+    assert_eq!(last_modified, "Tue, 14 Nov 2023 22:13:20 GMT");
+
+    for (since, expected) in [
+        (last_modified.as_str(), StatusCode::NOT_MODIFIED),
+        ("Wed, 15 Nov 2023 00:00:00 GMT", StatusCode::NOT_MODIFIED),
+        ("Tue, 14 Nov 2023 22:13:19 GMT", StatusCode::OK),
+        ("not a date", StatusCode::OK),
+    ] {
+        let response = get_index_entry(&router, &[(IF_MODIFIED_SINCE, since)]).await;
+        assert_eq!(response.status(), expected, "{since}");
+    }
+}
@@ -0,0 +1,222 @@
+pub mod common;
+
+use crate::common::utils::{crate_tarball, crate_version, generate_crate_payload};
+use crate::common::{MemoryStorageProvider, MockAuthProvider, MockIndexProvider, ServiceStateBuilder};
+use axum::body::{to_bytes, Body, Bytes};
+use axum::http::{header, Request, StatusCode};
+use freighter_api_types::index::request::Publish;
+use freighter_api_types::index::response::CrateVersion;
+use freighter_api_types::index::PublishContext;
+use freighter_fs_index::{Config, FsIndexProvider};
+use freighter_server::mirror::MirrorConfig;
+use freighter_server::{api, downloads, index, router, ServiceState};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+const TOKEN: &str = "12345";
+
+/// The mock storage serves `{name}-{version}` as the tarball
+fn upstream_version(name: &str, version: &str, yanked: bool) -> CrateVersion {
+    CrateVersion {
+        cksum: Sha256::digest(format!("{name}-{version}")).into(),
+        yanked,
+        ..crate_version(name, version)
+    }
+}
+
+/// Starts another freighter to be the upstream, and returns the URL of its index
+async fn start_upstream() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut builder = ServiceStateBuilder::default().index_provider(MockIndexProvider {
+        crates: [
+            (
+                "example-lib".to_owned(),
+                vec![
+                    upstream_version("example-lib", "1.0.0", false),
+                    upstream_version("example-lib", "1.1.0", true),
+                ],
+            ),
+            (
+                "bad-lib".to_owned(),
+                vec![CrateVersion {
+                    cksum: [0; 32],
+                    ..crate_version("bad-lib", "1.0.0")
+                }],
+            ),
+        ]
+        .into(),
+    });
+    builder.config.download_endpoint = format!("http://{addr}/downloads/{{crate}}/{{version}}");
+    let state = builder.build_no_arc();
+    let app = router(state.config, state.index, state.storage, state.auth, state.audit);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    format!("http://{addr}/index")
+}
+
+async fn mirror_state(
+    crates: Vec<String>,
+    storage: MemoryStorageProvider,
+) -> (Arc<ServiceState>, TempDir) {
+    let index_dir = tempfile::tempdir().unwrap();
+
+    let mut config = ServiceStateBuilder::default().config;
+    // used if the storage makes presigned URLs
+    config.presigned_download_ttl_secs = Some(60);
+    config.mirror = Some(MirrorConfig {
+        index_url: start_upstream().await,
+        token: None,
+        crates,
+        refresh_secs: 300,
+    });
+
+    let state = ServiceState::new(
+        config,
+        Box::new(FsIndexProvider::new(Config::Path(index_dir.path().to_owned())).unwrap()),
+        Box::new(storage),
+        Box::new(MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        }),
+        None,
+    );
+    (Arc::new(state), index_dir)
+}
+
+async fn get(state: &Arc<ServiceState>, uri: &str) -> (StatusCode, Bytes) {
+    let app = axum::Router::new()
+        .nest("/index", index::index_router())
+        .nest("/downloads", downloads::downloads_router())
+        .with_state(state.clone());
+    let response = app
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, to_bytes(response.into_body(), usize::MAX).await.unwrap())
+}
+
+#[tokio::test]
+async fn mirrors_index_and_tarballs() {
+    let storage = MemoryStorageProvider::default();
+    let (state, _index_dir) = mirror_state(vec!["*".to_owned()], storage.clone()).await;
+
+    let (status, body) = get(&state, "/index/ex/am/example-lib").await;
+    assert_eq!(status, StatusCode::OK);
+    let entries: Vec<CrateVersion> = std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].cksum, upstream_version("example-lib", "1.0.0", false).cksum);
+    assert!(!entries[0].yanked);
+    assert!(entries[1].yanked);
+
+    // the index entry is now local
+    assert!(state.index.get_sparse_entry("example-lib").await.is_ok());
+
+    assert!(storage.crates.lock().unwrap().is_empty());
+    let (status, body) = get(&state, "/downloads/example-lib/1.0.0").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(&body[..], b"example-lib-1.0.0");
+    assert_eq!(
+        storage.crates.lock().unwrap().get("example-lib-1.0.0").map(|b| &b[..]),
+        Some(&b"example-lib-1.0.0"[..])
+    );
+
+    let (status, body) = get(&state, "/downloads/example-lib/1.0.0").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(&body[..], b"example-lib-1.0.0");
+}
+
+#[tokio::test]
+async fn mirrored_tarballs_are_verified() {
+    let storage = MemoryStorageProvider::default();
+    let (state, _index_dir) = mirror_state(vec!["*".to_owned()], storage.clone()).await;
+
+    let (status, _) = get(&state, "/downloads/bad-lib/1.0.0").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(storage.crates.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn only_configured_crates_are_mirrored() {
+    let (state, _index_dir) = mirror_state(vec!["bad-*".to_owned()], MemoryStorageProvider::default()).await;
+
+    let (status, _) = get(&state, "/index/ex/am/example-lib").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+async fn publish(state: &Arc<ServiceState>, name: &str, version: &str) -> StatusCode {
+    let app = axum::Router::new()
+        .nest("/api/v1/crates", api::api_router())
+        .with_state(state.clone());
+    let payload = generate_crate_payload(name, version, &crate_tarball(name, version), &[]);
+    app.oneshot(
+        Request::builder()
+            .uri("/api/v1/crates/new")
+            .method("PUT")
+            .header(header::AUTHORIZATION, TOKEN)
+            .body(Body::from(payload))
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+    .status()
+}
+
+/// Adds a version that isn't in the upstream, and its tarball
+async fn publish_local(state: &ServiceState, storage: &MemoryStorageProvider, name: &str, version: &str) {
+    let tarball = format!("{name}-{version}");
+    let publish = Publish {
+        name: name.into(),
+        vers: version.parse().unwrap(),
+        ..Publish::empty()
+    };
+    let cksum = Sha256::digest(&tarball).into();
+    state
+        .index
+        .publish(&publish, cksum, &PublishContext::default(), std::pin::pin!(async { Ok(()) }))
+        .await
+        .unwrap();
+    storage.crates.lock().unwrap().insert(tarball.clone(), tarball.into());
+}
+
+#[tokio::test]
+async fn mirrored_names_cant_be_published() {
+    let storage = MemoryStorageProvider::default();
+    let (state, _index_dir) = mirror_state(vec!["*".to_owned()], storage.clone()).await;
+
+    assert_eq!(publish(&state, "example-lib", "2.0.0").await, StatusCode::BAD_REQUEST);
+    assert_eq!(publish(&state, "Example_Lib", "2.0.0").await, StatusCode::BAD_REQUEST);
+    assert_eq!(publish(&state, "not-in-upstream", "1.0.0").await, StatusCode::BAD_REQUEST);
+    assert!(storage.crates.lock().unwrap().is_empty());
+
+    // published before the name was mirrored
+    publish_local(&state, &storage, "private-lib", "1.0.0").await;
+    assert_eq!(publish(&state, "private-lib", "1.1.0").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn local_crates_are_served_from_storage() {
+    let storage = MemoryStorageProvider {
+        presigned_urls: true,
+        ..MemoryStorageProvider::default()
+    };
+    let (state, _index_dir) = mirror_state(vec!["*".to_owned()], storage.clone()).await;
+    publish_local(&state, &storage, "private-lib", "1.0.0").await;
+
+    let (status, _) = get(&state, "/downloads/private-lib/1.0.0").await;
+    assert_eq!(status, StatusCode::TEMPORARY_REDIRECT);
+
+    // mirrored tarballs are served from storage once copied
+    let (status, body) = get(&state, "/downloads/example-lib/1.0.0").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(&body[..], b"example-lib-1.0.0");
+    let (status, _) = get(&state, "/downloads/example-lib/1.0.0").await;
+    assert_eq!(status, StatusCode::TEMPORARY_REDIRECT);
+}
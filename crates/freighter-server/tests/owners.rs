@@ -0,0 +1,77 @@
+pub mod common;
+
+use crate::common::utils::{crate_tarball, generate_crate_payload};
+use crate::common::{MockStorageProvider, ServiceStateBuilder};
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use freighter_api_types::index::IndexProvider;
+use freighter_auth::fs_backend::{self, FsAuthProvider};
+use freighter_auth::AuthProvider;
+use freighter_fs_index::{Config, FsIndexProvider};
+use semver::Version;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+async fn send(router: &Router, method: &str, uri: &str, token: &str, body: Vec<u8>) -> (StatusCode, Value) {
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn other_spellings_are_owned_too() {
+    let dir = tempfile::tempdir().unwrap();
+    let auth = FsAuthProvider::new(fs_backend::Config {
+        auth_path: dir.path().join("auth"),
+        auth_tokens_pepper: [1; 18],
+    })
+    .unwrap();
+    let alice = auth.register("alice").await.unwrap();
+    let mallory = auth.register("mallory").await.unwrap();
+    let index = FsIndexProvider::new(Config::Path(dir.path().join("index"))).unwrap();
+    let router = freighter_server::router(
+        ServiceStateBuilder::default().config,
+        Box::new(index),
+        Box::new(MockStorageProvider::default()),
+        Box::new(auth),
+        None,
+    );
+
+    let payload = |name: &str, version: &str| generate_crate_payload(name, version, &crate_tarball(name, version), &[]);
+    let (status, _) = send(&router, "PUT", "/api/v1/crates/new", &alice, payload("foo-bar", "1.0.0")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&router, "PUT", "/api/v1/crates/new", &mallory, payload("foo_bar", "1.0.1")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&router, "DELETE", "/api/v1/crates/foo_bar/1.0.0/yank", &mallory, vec![]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let users = json!({ "users": ["mallory"] }).to_string().into_bytes();
+    let (status, _) = send(&router, "PUT", "/api/v1/crates/Foo_Bar/owners", &mallory, users.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&router, "DELETE", "/api/v1/crates/foo_bar/owners", &mallory, users).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, owners) = send(&router, "GET", "/api/v1/crates/foo_bar/owners", &mallory, vec![]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(owners["users"], json!([{ "id": 0, "login": "alice", "name": null }]));
+
+    let (status, _) = send(&router, "DELETE", "/api/v1/crates/Foo_Bar/1.0.0/yank", &alice, vec![]).await;
+    assert_eq!(status, StatusCode::OK);
+    let index = FsIndexProvider::new(Config::Path(dir.path().join("index"))).unwrap();
+    let entries = index.get_sparse_entry("foo-bar").await.unwrap().entries;
+    assert_eq!(entries.iter().map(|e| (&e.vers, e.yanked)).collect::<Vec<_>>(), [(&Version::new(1, 0, 0), true)]);
+}
@@ -0,0 +1,172 @@
+pub mod common;
+
+use crate::common::utils::crate_version;
+use crate::common::{MockAuditSink, MockIndexProvider, MockStorageProvider, ServiceStateBuilder};
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use freighter_api_types::audit::{AuditOperation, AuditOutcome, AuditRecord};
+use freighter_api_types::index::response::Dependency;
+use freighter_api_types::index::DependencyKind;
+use freighter_auth::fs_backend::{self, FsAuthProvider};
+use freighter_auth::AuthProvider;
+use freighter_server::git_index::GitIndexConfig;
+use semver::VersionReq;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tower::ServiceExt;
+
+async fn get(router: &Router, uri: &str, token: &str) -> (StatusCode, Value) {
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .header(header::AUTHORIZATION, token)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+fn audit_record(operation: AuditOperation, crate_name: Option<&str>) -> AuditRecord {
+    AuditRecord {
+        timestamp: chrono::Utc::now(),
+        operation,
+        actor: Some("owner".into()),
+        token_id: None,
+        crate_name: crate_name.map(From::from),
+        version: None,
+        source_ip: None,
+        outcome: AuditOutcome::Success,
+        status: 200,
+        details: None,
+    }
+}
+
+#[tokio::test]
+async fn private_crates_are_hidden() {
+    let dir = tempfile::tempdir().unwrap();
+    let auth = FsAuthProvider::new(fs_backend::Config {
+        auth_path: dir.path().to_path_buf(),
+        auth_tokens_pepper: [1; 18],
+    })
+    .unwrap();
+    let partner = auth.register("partner").await.unwrap();
+    let other = auth.register("other").await.unwrap();
+    let owner = auth.register("owner").await.unwrap();
+    auth.publish(&owner, "partner-lib").await.unwrap();
+
+    let mut partner_lib = crate_version("partner-lib", "1.0.0");
+    partner_lib.deps.push(Dependency {
+        name: "public-lib".to_owned(),
+        req: VersionReq::parse("^1").unwrap(),
+        features: vec![],
+        optional: false,
+        default_features: true,
+        target: None,
+        kind: DependencyKind::Normal,
+        registry: None,
+        package: None,
+    });
+    let crates = BTreeMap::from([
+        ("partner-lib".to_owned(), vec![partner_lib]),
+        ("public-lib".to_owned(), vec![crate_version("public-lib", "1.0.0")]),
+    ]);
+    let mut config = ServiceStateBuilder::default().config;
+    config.auth_required = false;
+    config.read_acl = Some(
+        serde_json::from_value(json!({
+            "groups": {"partners": ["partner"]},
+            "rules": [{"crates": ["partner-*"], "groups": ["partners"]}],
+        }))
+        .unwrap(),
+    );
+    let audit = MockAuditSink::default();
+    audit.records.lock().unwrap().extend([
+        audit_record(AuditOperation::Register, None),
+        audit_record(AuditOperation::Publish, Some("partner-lib")),
+        audit_record(AuditOperation::Publish, Some("public-lib")),
+    ]);
+    let router = freighter_server::router(
+        config,
+        Box::new(MockIndexProvider { crates }),
+        Box::new(MockStorageProvider::default()),
+        Box::new(auth),
+        Some(Box::new(audit)),
+    );
+
+    assert_eq!(get(&router, "/index/pa/rt/partner-lib", &partner).await.0, StatusCode::OK);
+    assert_eq!(get(&router, "/index/pa/rt/partner-lib", &other).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get(&router, "/index/pu/bl/public-lib", &other).await.0, StatusCode::OK);
+    assert_eq!(get(&router, "/downloads/partner-lib/1.0.0", &other).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get(&router, "/downloads/partner-lib/1.0.0", &partner).await.0, StatusCode::OK);
+    assert_eq!(get(&router, "/api/v1/crates/partner-lib", &other).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get(&router, "/api/v1/crates/partner-lib/owners", &other).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get(&router, "/api/v1/crates/partner-lib/owners", &partner).await.0, StatusCode::OK);
+
+    let names = |list: &Value| -> Vec<String> {
+        list["results"].as_array().unwrap().iter().map(|c| c["name"].as_str().unwrap().to_owned()).collect()
+    };
+    let (status, list) = get(&router, "/all", &partner).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(names(&list), ["partner-lib", "public-lib"]);
+    let (status, list) = get(&router, "/all", &other).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(names(&list), ["public-lib"]);
+
+    let dependents = |deps: &Value| -> Vec<String> {
+        deps["dependencies"].as_array().unwrap().iter().map(|d| d["crate"].as_str().unwrap().to_owned()).collect()
+    };
+    let (status, deps) = get(&router, "/api/v1/crates/public-lib/reverse_dependencies", &partner).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(dependents(&deps), ["partner-lib"]);
+    let (status, deps) = get(&router, "/api/v1/crates/public-lib/reverse_dependencies", &other).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(dependents(&deps).is_empty());
+
+    let operations = |log: &Value| -> Vec<String> {
+        log["records"].as_array().unwrap().iter().map(|r| format!("{} {}", r["operation"], r["crate"])).collect()
+    };
+    let (status, log) = get(&router, "/api/v1/audit", &partner).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(operations(&log), [r#""publish" "public-lib""#, r#""publish" "partner-lib""#, r#""register" null"#]);
+    let (status, log) = get(&router, "/api/v1/audit", &other).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(operations(&log), [r#""publish" "public-lib""#, r#""register" null"#]);
+
+    // auth is required for the read ACL
+    assert_eq!(get(&router, "/index/pu/bl/public-lib", "").await.0, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn git_index_is_not_served() {
+    let dir = tempfile::tempdir().unwrap();
+    let auth = FsAuthProvider::new(fs_backend::Config {
+        auth_path: dir.path().join("auth"),
+        auth_tokens_pepper: [1; 18],
+    })
+    .unwrap();
+    let token = auth.register("alice").await.unwrap();
+
+    let mut config = ServiceStateBuilder::default().config;
+    let repo = dir.path().join("index.git");
+    let init = std::process::Command::new("git").args(["init", "--quiet", "--bare"]).arg(&repo).status().unwrap();
+    assert!(init.success());
+    config.git_index = Some(GitIndexConfig { path: repo, refresh_secs: 60 });
+    config.read_acl = Some(serde_json::from_value(json!({"rules": [{"crates": ["partner-*"]}]})).unwrap());
+    let router = freighter_server::router(
+        config,
+        Box::new(MockIndexProvider::default()),
+        Box::new(MockStorageProvider::default()),
+        Box::new(auth),
+        None,
+    );
+
+    let uri = "/git/index/info/refs?service=git-upload-pack";
+    assert_eq!(get(&router, uri, &token).await.0, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,116 @@
+pub mod common;
+
+use crate::common::utils::{crate_tarball, generate_crate_payload};
+use crate::common::{MockIndexProvider, MockStorageProvider, ServiceStateBuilder};
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use freighter_auth::fs_backend::{self, FsAuthProvider};
+use freighter_auth::AuthProvider;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+/// Registry with a user, and the user's token
+async fn registry(dir: &TempDir) -> (Router, String) {
+    let auth = FsAuthProvider::new(fs_backend::Config {
+        auth_path: dir.path().to_path_buf(),
+        auth_tokens_pepper: [1; 18],
+    })
+    .unwrap();
+    let token = auth.register("alice").await.unwrap();
+
+    let router = freighter_server::router(
+        ServiceStateBuilder::default().config,
+        Box::new(MockIndexProvider::default()),
+        Box::new(MockStorageProvider::default()),
+        Box::new(auth),
+        None,
+    );
+    (router, token)
+}
+
+async fn request(router: &Router, method: &str, uri: &str, token: &str, body: Option<Value>) -> (StatusCode, Value) {
+    let body = body.map_or_else(Body::empty, |b| Body::from(b.to_string()));
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .method(method)
+                .header(header::AUTHORIZATION, token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+async fn publish(router: &Router, token: &str, name: &str) -> StatusCode {
+    let tarball = crate_tarball(name, "1.0.0");
+    let payload = generate_crate_payload(name, "1.0.0", &tarball, &[]);
+    router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/crates/new")
+                .method("PUT")
+                .header(header::AUTHORIZATION, token)
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn scoped_token_publishes_only_its_crates() {
+    let dir = tempfile::tempdir().unwrap();
+    let (router, token) = registry(&dir).await;
+
+    let new_token = json!({"api_token": {"name": "ci", "endpoint_scopes": ["publish-new"], "crate_scopes": ["example-*"]}});
+    let (status, created) = request(&router, "PUT", "/api/v1/me/tokens", &token, Some(new_token)).await;
+    assert_eq!(status, StatusCode::OK);
+    let scoped = created["api_token"]["token"].as_str().unwrap();
+
+    assert_eq!(publish(&router, scoped, "other-lib").await, StatusCode::FORBIDDEN);
+    assert_eq!(publish(&router, scoped, "example-lib").await, StatusCode::OK);
+    assert_eq!(publish(&router, &token, "other-lib").await, StatusCode::OK);
+
+    let new_token = json!({"api_token": {"name": "unscoped"}});
+    let (status, _) = request(&router, "PUT", "/api/v1/me/tokens", scoped, Some(new_token)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn list_and_revoke_tokens() {
+    let dir = tempfile::tempdir().unwrap();
+    let (router, token) = registry(&dir).await;
+
+    let new_token = json!({"api_token": {"name": "laptop", "expired_at": "2100-01-01T00:00:00Z"}});
+    let (status, created) = request(&router, "PUT", "/api/v1/me/tokens", &token, Some(new_token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["api_token"]["name"], "laptop");
+    let laptop = created["api_token"]["token"].as_str().unwrap();
+
+    let (status, listed) = request(&router, "GET", "/api/v1/me/tokens", laptop, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let tokens = listed["api_tokens"].as_array().unwrap();
+    assert_eq!(tokens.len(), 2);
+    assert!(tokens.iter().all(|t| t.get("token").is_none()));
+    let laptop_info = tokens.iter().find(|t| t["name"] == "laptop").unwrap();
+    assert_eq!(laptop_info["expired_at"], "2100-01-01T00:00:00Z");
+    let first_id = &tokens.iter().find(|t| t["name"] == "default").unwrap()["id"];
+
+    let (status, _) = request(&router, "DELETE", &format!("/api/v1/me/tokens/{first_id}"), laptop, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = request(&router, "GET", "/api/v1/me/tokens", &token, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = request(&router, "DELETE", &format!("/api/v1/me/tokens/{first_id}"), laptop, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,150 @@
+pub mod common;
+
+use crate::common::utils::{crate_tarball, generate_crate_payload};
+use crate::common::{MockIndexProvider, MockStorageProvider, ServiceStateBuilder};
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use freighter_auth::fs_backend::{self, FsAuthProvider};
+use freighter_auth::trusted_publishing::{Config, IssuerConfig, TrustPolicy};
+use freighter_auth::AuthProvider;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+const ISSUER: &str = "https://ci.example.com";
+const AUDIENCE: &str = "https://registry.example.com";
+const CI_KEY: &[u8] = b"very secret key for tests";
+
+/// Registry with the logins of the users, and their tokens
+async fn registry(dir: &TempDir, users: &[&str]) -> (Router, Vec<String>) {
+    let auth = FsAuthProvider::new(fs_backend::Config {
+        auth_path: dir.path().join("auth"),
+        auth_tokens_pepper: [1; 18],
+    })
+    .unwrap();
+    let mut tokens = Vec::new();
+    for user in users {
+        tokens.push(auth.register(user).await.unwrap());
+    }
+
+    let jwks_path = dir.path().join("jwks.json");
+    let jwks = json!({"keys": [{"kty": "oct", "kid": "ci", "k": "dmVyeSBzZWNyZXQga2V5IGZvciB0ZXN0cw"}]});
+    std::fs::write(&jwks_path, jwks.to_string()).unwrap();
+
+    let mut config = ServiceStateBuilder::default().config;
+    config.trusted_publishing = Some(Config {
+        audience: AUDIENCE.into(),
+        token_ttl_secs: 60,
+        signing_key: None,
+        issuers: vec![IssuerConfig {
+            url: ISSUER.into(),
+            jwks_url: Some(format!("file://{}", jwks_path.display())),
+            algorithms: vec![Algorithm::HS256],
+        }],
+        policies: vec![TrustPolicy {
+            crate_name: "example-lib".into(),
+            issuer: ISSUER.into(),
+            repository: "acme/example".into(),
+            workflow: Some("release.yml".into()),
+            git_ref: None,
+        }],
+    });
+
+    let router = freighter_server::router(
+        config,
+        Box::new(MockIndexProvider::default()),
+        Box::new(MockStorageProvider::default()),
+        Box::new(auth),
+        None,
+    );
+    (router, tokens)
+}
+
+fn id_token(repository: &str) -> String {
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some("ci".into());
+    let claims = json!({
+        "iss": ISSUER,
+        "aud": AUDIENCE,
+        "exp": jsonwebtoken::get_current_timestamp() + 60,
+        "repository": repository,
+        "workflow_ref": format!("{repository}/.github/workflows/release.yml@refs/heads/main"),
+        "ref": "refs/heads/main",
+    });
+    jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(CI_KEY)).unwrap()
+}
+
+async fn exchange(router: &Router, id_token: &str) -> (StatusCode, Value) {
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/trusted_publishing/tokens")
+                .method("POST")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "jwt": id_token }).to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+async fn publish(router: &Router, token: &str, name: &str, version: &str) -> StatusCode {
+    let payload = generate_crate_payload(name, version, &crate_tarball(name, version), &[]);
+    router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/crates/new")
+                .method("PUT")
+                .header(header::AUTHORIZATION, token)
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn publish_with_exchanged_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let (router, tokens) = registry(&dir, &["alice"]).await;
+
+    let (status, body) = exchange(&router, &id_token("acme/example")).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = body["token"].as_str().unwrap();
+
+    assert_eq!(publish(&router, &tokens[0], "example-lib", "1.0.0").await, StatusCode::OK);
+    assert_eq!(publish(&router, token, "example-lib", "1.0.1").await, StatusCode::OK);
+    assert_eq!(publish(&router, token, "other-lib", "1.0.0").await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn first_version_needs_an_owner() {
+    let dir = tempfile::tempdir().unwrap();
+    let (router, tokens) = registry(&dir, &["alice", "bob"]).await;
+    let (_, body) = exchange(&router, &id_token("acme/example")).await;
+    let token = body["token"].as_str().unwrap();
+
+    // otherwise the crate would have no owners, and the next user to publish it would own it
+    assert_eq!(publish(&router, token, "example-lib", "1.0.0").await, StatusCode::FORBIDDEN);
+    assert_eq!(publish(&router, &tokens[0], "example-lib", "1.0.0").await, StatusCode::OK);
+    assert_eq!(publish(&router, token, "example-lib", "1.0.1").await, StatusCode::OK);
+    assert_eq!(publish(&router, &tokens[1], "example-lib", "1.0.2").await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn untrusted_repositories_get_no_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let (router, _) = registry(&dir, &[]).await;
+
+    let (status, body) = exchange(&router, &id_token("mallory/example")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body.get("token").is_none());
+}
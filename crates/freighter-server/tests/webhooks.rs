@@ -0,0 +1,165 @@
+pub mod common;
+
+use crate::common::utils::{crate_tarball, generate_crate_payload};
+use crate::common::{MockAuthProvider, ServiceStateBuilder};
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use freighter_server::webhooks::{WebhookEventKind, WebhookTarget, Webhooks, WebhooksConfig};
+use freighter_server::{api, ServiceState};
+use hmac::{Hmac, Mac};
+use hyper::header::AUTHORIZATION;
+use sha2::Sha256;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+const TOKEN: &str = "12345";
+const SECRET: &str = "webhook-secret";
+
+/// Records the webhooks it gets, after failing the first few
+#[derive(Clone, Default)]
+struct Receiver {
+    requests: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+    failures_left: Arc<AtomicUsize>,
+}
+
+async fn receive(State(receiver): State<Receiver>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    receiver.requests.lock().unwrap().push((headers, body));
+    let failed = receiver
+        .failures_left
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+        .is_ok();
+    if failed {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::OK
+    }
+}
+
+async fn start_receiver(failures: usize) -> (String, Receiver) {
+    let receiver = Receiver::default();
+    receiver.failures_left.store(failures, Ordering::SeqCst);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let app = Router::new()
+        .route("/hook", post(receive))
+        .with_state(receiver.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    (url, receiver)
+}
+
+fn webhooks_config(url: String, events: Option<Vec<WebhookEventKind>>) -> WebhooksConfig {
+    WebhooksConfig {
+        targets: vec![WebhookTarget {
+            url,
+            secret: SECRET.to_owned(),
+            events,
+        }],
+        ..Default::default()
+    }
+}
+
+async fn publish_with_webhooks(config: WebhooksConfig) -> Arc<ServiceState> {
+    let state = ServiceStateBuilder::default()
+        .auth_provider(MockAuthProvider {
+            valid_tokens: [TOKEN.to_owned()].into(),
+        })
+        .webhooks(config)
+        .build();
+
+    let payload = generate_crate_payload(
+        "example-lib",
+        "1.0.1",
+        &crate_tarball("example-lib", "1.0.1"),
+        &[],
+    );
+
+    let response = api::api_router()
+        .with_state(state.clone())
+        .oneshot(
+            Request::builder()
+                .uri("/new")
+                .method("PUT")
+                .header(AUTHORIZATION, TOKEN)
+                .body(Body::from(payload))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    state
+}
+
+#[tokio::test]
+async fn publish_sends_signed_webhook() {
+    let (url, receiver) = start_receiver(0).await;
+    let state = publish_with_webhooks(webhooks_config(url, None)).await;
+    assert_eq!(state.webhooks.pending(), 1);
+
+    state
+        .webhooks
+        .deliver(&state.config.webhooks, &reqwest::Client::new())
+        .await;
+    assert_eq!(state.webhooks.pending(), 0);
+
+    let requests = receiver.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    let (headers, body) = &requests[0];
+    assert_eq!(headers["x-freighter-event"], "publish");
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(body);
+    let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+    assert_eq!(headers["x-freighter-signature"], signature.as_str());
+
+    let event: serde_json::Value = serde_json::from_slice(body).unwrap();
+    assert_eq!(event["event"], "publish");
+    assert_eq!(event["crate"], "example-lib");
+    assert_eq!(event["version"], "1.0.1");
+    assert_eq!(event["actor"], TOKEN);
+}
+
+#[tokio::test]
+async fn webhook_targets_filter_events() {
+    let (url, _receiver) = start_receiver(0).await;
+    let state = publish_with_webhooks(webhooks_config(url, Some(vec![WebhookEventKind::Yank]))).await;
+
+    assert_eq!(state.webhooks.pending(), 0);
+}
+
+#[tokio::test]
+async fn failed_webhook_is_retried_after_restart() {
+    let queue = tempfile::tempdir().unwrap();
+    let (url, receiver) = start_receiver(1).await;
+    let config = WebhooksConfig {
+        queue_path: Some(queue.path().to_owned()),
+        retry_delay_secs: 0,
+        ..webhooks_config(url, None)
+    };
+    let client = reqwest::Client::new();
+
+    let state = publish_with_webhooks(config.clone()).await;
+    state.webhooks.deliver(&config, &client).await;
+    assert_eq!(state.webhooks.pending(), 1);
+    assert_eq!(std::fs::read_dir(queue.path()).unwrap().count(), 1);
+
+    // a new queue picks up the persisted delivery
+    let restarted = Webhooks::default();
+    restarted.deliver(&config, &client).await;
+    assert_eq!(restarted.pending(), 0);
+    assert_eq!(std::fs::read_dir(queue.path()).unwrap().count(), 0);
+
+    let requests = receiver.requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[0].0["x-freighter-delivery"],
+        requests[1].0["x-freighter-delivery"]
+    );
+    assert_eq!(requests[0].1, requests[1].1);
+}
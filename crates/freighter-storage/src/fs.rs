@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use freighter_api_types::storage::{
-    Bytes, FileResponse, Metadata, MetadataStorageProvider, StorageError, StorageResult,
+    Bytes, FileResponse, Metadata, MetadataStorageProvider, StorageError, StorageProvider,
+    StorageResult,
 };
 use std::fs::File;
 use std::io::{self, Read, Write};
@@ -77,14 +78,55 @@ impl MetadataStorageProvider for FsStorageProvider {
     }
 }
 
+/// Crates are stored like in S3, under `crates/`
+#[async_trait]
+impl StorageProvider for FsStorageProvider {
+    async fn pull_crate(
+        &self,
+        name: &str,
+        version: &str,
+        tarball_checksum: [u8; 32],
+    ) -> StorageResult<FileResponse> {
+        self.pull_file(&crate_path(name, version, tarball_checksum)).await
+    }
+
+    async fn put_crate(
+        &self,
+        name: &str,
+        version: &str,
+        crate_bytes: Bytes,
+        tarball_checksum: [u8; 32],
+    ) -> StorageResult<()> {
+        let path = crate_path(name, version, tarball_checksum);
+        self.put_file(&path, crate_bytes, Metadata::default()).await
+    }
+
+    async fn delete_crate(
+        &self,
+        name: &str,
+        version: &str,
+        tarball_checksum: [u8; 32],
+    ) -> StorageResult<()> {
+        self.delete_file(&crate_path(name, version, tarball_checksum)).await
+    }
+
+    async fn healthcheck(&self) -> anyhow::Result<()> {
+        MetadataStorageProvider::healthcheck(self).await
+    }
+}
+
+fn crate_path(name: &str, version: &str, tarball_checksum: [u8; 32]) -> String {
+    format!("crates/{name}-{version}_{}.tar.gz", hex::encode(tarball_checksum))
+}
+
 fn append_dir(path: &Path, out: &mut Vec<String>) -> StorageResult<()> {
     for e in std::fs::read_dir(path)? {
         let e = e?;
         let ty = e.file_type()?;
+        // like S3, lists only files
         if ty.is_dir() {
             append_dir(&e.path(), out)?;
-        }
-        if let Ok(p) = e.path().into_os_string().into_string() {
+        } else if let Ok(p) = e.path().into_os_string().into_string() {
             out.push(p);
         }
     }
@@ -5,29 +5,44 @@
 //! This client should do connection pooling, however the HTTP connection pool parameters not not
 //! well tuned at the moment.
 //!
-//! One performance limitation of this implementation is that the current simplistic API of this
-//! crate ([`StorageProvider`]) does not allow for streamed uploads or downloads, increasing both
-//! memory usage and latency, as the entire body of data must be received before transmission can
-//! start.
-//! This means that when uploading, the server must wait for and store the entirety of the HTTP
-//! request before it can begin uploading the body to the bucket, and, when downloading, the
-//! entirety of the response from the bucket must be received before transmission of the crate
-//! bytes to the eyeball.
-//! It is perfectly possible to perform both streaming uploads and streaming downloads, however
-//! doing so has been left to the future.
+//! Crate downloads are streamed from the bucket to the eyeball via
+//! [`StorageProvider::stream_crate`], so the first bytes can be sent before the whole object has
+//! been received, and memory use doesn't grow with the size of the crate.
+//!
+//! Downloads can also skip freighter entirely, by redirecting clients to a URL made by
+//! [`StorageProvider::presigned_crate_url`], which is valid only for a limited time.
+//!
+//! Uploads via [`StorageProvider::put_crate_stream`] are buffered only if they're small. Larger
+//! crates are sent as a multipart upload to a temporary object under `uploads/`, and then copied
+//! to their final path once their checksum is known. If freighter is killed mid-upload, the
+//! bucket may be left with incomplete multipart uploads, so an expiry lifecycle rule for them
+//! is recommended.
 
 use anyhow::{bail, Context};
 use async_trait::async_trait;
 use aws_credential_types::Credentials;
 use aws_sdk_s3::config::{AppName, BehaviorVersion, Config, Region};
 use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::presigning::PresigningConfig;
 use aws_sdk_s3::primitives::ByteStream;
-use bytes::Bytes;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use bytes::{Bytes, BytesMut};
 use freighter_api_types::storage::{
-    FileResponse, Metadata, MetadataStorageProvider, StorageError, StorageProvider, StorageResult,
+    BodyStream, FileResponse, Metadata, MetadataStorageProvider, StorageError, StorageProvider,
+    StorageResult, StreamedFileResponse,
 };
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use tracing::debug;
+use std::io;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error};
+
+/// Crates larger than this are uploaded in parts of this size, instead of being buffered whole.
+///
+/// Must be at least 5MiB, which is the smallest part size allowed by S3.
+const MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
 
 /// Storage client for working with S3-compatible APIs.
 ///
@@ -66,7 +81,7 @@ impl S3StorageProvider {
         }
     }
 
-    async fn pull_object(&self, path: String) -> StorageResult<FileResponse> {
+    async fn get_object(&self, path: String) -> StorageResult<GetObjectOutput> {
         let resp = self
             .client
             .get_object()
@@ -82,8 +97,12 @@ impl S3StorageProvider {
             return Err(StorageError::NotFound);
         }
 
-        let resp = resp.context("Storage response error")?;
-        let last_modified = resp.last_modified().and_then(|d| chrono::DateTime::from_timestamp(d.secs(), 0));
+        Ok(resp.context("Storage response error")?)
+    }
+
+    async fn pull_object(&self, path: String) -> StorageResult<FileResponse> {
+        let resp = self.get_object(path).await?;
+        let last_modified = last_modified(&resp);
 
         let crate_bytes = resp
             .body
@@ -98,6 +117,58 @@ impl S3StorageProvider {
         })
     }
 
+    /// Get a crate object, falling back to the path used by older versions of freighter
+    async fn get_crate_object(
+        &self,
+        name: &str,
+        version: &str,
+        tarball_checksum: [u8; 32],
+    ) -> StorageResult<GetObjectOutput> {
+        let [old_path, new_path] = construct_paths(name, version, tarball_checksum);
+        match self.get_object(new_path).await {
+            Ok(res) => Ok(res),
+            Err(first_err) => {
+                debug!(
+                    name,
+                    version,
+                    path = old_path,
+                    "Falling back to using old path"
+                );
+                let Ok(res) = self.get_object(old_path).await else {
+                    return Err(first_err);
+                };
+
+                Ok(res)
+            }
+        }
+    }
+
+    /// Finds which of the crate's paths exists, without downloading it
+    async fn crate_object_path(
+        &self,
+        name: &str,
+        version: &str,
+        tarball_checksum: [u8; 32],
+    ) -> StorageResult<String> {
+        let [old_path, new_path] = construct_paths(name, version, tarball_checksum);
+        for path in [new_path, old_path] {
+            let resp = self
+                .client
+                .head_object()
+                .bucket(self.bucket_name.clone())
+                .key(&path)
+                .send()
+                .await;
+
+            match resp {
+                Ok(_) => return Ok(path),
+                Err(SdkError::ServiceError(e)) if e.err().is_not_found() => {}
+                Err(e) => return Err(anyhow::Error::new(e).context("Storage response error").into()),
+            }
+        }
+        Err(StorageError::NotFound)
+    }
+
     async fn put_object(
         &self,
         path: String,
@@ -131,6 +202,122 @@ impl S3StorageProvider {
         Ok(())
     }
 
+    /// Sends the body as parts of a multipart upload, returning the parts and the body's checksum
+    async fn upload_parts(
+        &self,
+        path: &str,
+        upload_id: &str,
+        mut body: BodyStream,
+    ) -> StorageResult<(Vec<CompletedPart>, [u8; 32])> {
+        let mut hasher = Sha256::new();
+        let mut parts = Vec::new();
+        let mut buf = BytesMut::with_capacity(MULTIPART_PART_SIZE);
+        let mut body_done = false;
+
+        while !body_done {
+            match body.next().await {
+                Some(chunk) => {
+                    let chunk = chunk.context("Error while receiving the crate")?;
+                    hasher.update(&chunk);
+                    buf.extend_from_slice(&chunk);
+                    if buf.len() < MULTIPART_PART_SIZE {
+                        continue;
+                    }
+                }
+                None => body_done = true,
+            }
+
+            if buf.is_empty() {
+                continue;
+            }
+
+            let part_number = i32::try_from(parts.len() + 1).context("Too many parts")?;
+            let part = buf.split().freeze();
+            let resp = self
+                .client
+                .upload_part()
+                .bucket(self.bucket_name.clone())
+                .key(path)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .content_length(part.len() as _)
+                .body(part.into())
+                .send()
+                .await
+                .context("Failed to upload part")?;
+
+            parts.push(
+                CompletedPart::builder()
+                    .set_e_tag(resp.e_tag)
+                    .part_number(part_number)
+                    .build(),
+            );
+        }
+
+        Ok((parts, hasher.finalize().into()))
+    }
+
+    /// Multipart upload of a body to a temporary path, returning the body's checksum
+    async fn put_multipart(&self, path: &str, body: BodyStream) -> StorageResult<[u8; 32]> {
+        let upload = self
+            .client
+            .create_multipart_upload()
+            .bucket(self.bucket_name.clone())
+            .key(path)
+            .content_type("application/x-tar")
+            .cache_control("public,immutable")
+            .send()
+            .await
+            .context("Failed to start multipart upload")?;
+        let upload_id = upload.upload_id().context("Missing multipart upload id")?;
+
+        let res = async {
+            let (parts, checksum) = self.upload_parts(path, upload_id, body).await?;
+            self.client
+                .complete_multipart_upload()
+                .bucket(self.bucket_name.clone())
+                .key(path)
+                .upload_id(upload_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts))
+                        .build(),
+                )
+                .send()
+                .await
+                .context("Failed to complete multipart upload")?;
+            Ok(checksum)
+        }
+        .await;
+
+        if res.is_err() {
+            let abort = self
+                .client
+                .abort_multipart_upload()
+                .bucket(self.bucket_name.clone())
+                .key(path)
+                .upload_id(upload_id)
+                .send()
+                .await;
+            if let Err(e) = abort {
+                error!(path, "Failed to abort multipart upload: {e}");
+            }
+        }
+        res
+    }
+
+    async fn copy_object(&self, from_path: &str, to_path: String) -> StorageResult<()> {
+        self.client
+            .copy_object()
+            .bucket(self.bucket_name.clone())
+            .copy_source(format!("{}/{from_path}", self.bucket_name))
+            .key(to_path)
+            .send()
+            .await
+            .context("Failed to copy file")?;
+        Ok(())
+    }
+
     async fn delete_object(&self, path: String) -> StorageResult<()> {
         self.client
             .delete_object()
@@ -236,23 +423,67 @@ impl StorageProvider for S3StorageProvider {
         version: &str,
         tarball_checksum: [u8; 32],
     ) -> StorageResult<FileResponse> {
-        let [old_path, new_path] = construct_paths(name, version, tarball_checksum);
-        match self.pull_object(new_path.clone()).await {
-            Ok(res) => Ok(res),
-            Err(first_err) => {
-                debug!(
-                    name,
-                    version,
-                    path = old_path,
-                    "Falling back to using old path"
-                );
-                let Ok(res) = self.pull_object(old_path.clone()).await else {
-                    return Err(first_err);
-                };
+        let resp = self.get_crate_object(name, version, tarball_checksum).await?;
+        let last_modified = last_modified(&resp);
 
-                Ok(res)
-            }
-        }
+        let crate_bytes = resp
+            .body
+            .collect()
+            .await
+            .context("Error while retrieving body")?
+            .into_bytes();
+
+        Ok(FileResponse {
+            last_modified,
+            data: crate_bytes,
+        })
+    }
+
+    async fn stream_crate(
+        &self,
+        name: &str,
+        version: &str,
+        tarball_checksum: [u8; 32],
+    ) -> StorageResult<StreamedFileResponse> {
+        let resp = self.get_crate_object(name, version, tarball_checksum).await?;
+        let last_modified = last_modified(&resp);
+        let content_length = resp.content_length().and_then(|len| u64::try_from(len).ok());
+
+        let body = futures_util::stream::unfold(resp.body, |mut body| async move {
+            let chunk = body.next().await?.map_err(io::Error::other);
+            Some((chunk, body))
+        });
+
+        Ok(StreamedFileResponse {
+            last_modified,
+            content_length,
+            body: Box::pin(body),
+        })
+    }
+
+    async fn presigned_crate_url(
+        &self,
+        name: &str,
+        version: &str,
+        tarball_checksum: [u8; 32],
+        expires_in: Duration,
+    ) -> StorageResult<Option<String>> {
+        let path = self
+            .crate_object_path(name, version, tarball_checksum)
+            .await?;
+        let presigning_config =
+            PresigningConfig::expires_in(expires_in).context("Invalid presigned URL expiry")?;
+
+        let request = self
+            .client
+            .get_object()
+            .bucket(self.bucket_name.clone())
+            .key(path)
+            .presigned(presigning_config)
+            .await
+            .context("Failed to presign crate download")?;
+
+        Ok(Some(request.uri().to_owned()))
     }
 
     async fn put_crate(
@@ -279,6 +510,44 @@ impl StorageProvider for S3StorageProvider {
         .await
     }
 
+    async fn put_crate_stream(
+        &self,
+        name: &str,
+        version: &str,
+        crate_len: u64,
+        mut crate_body: BodyStream,
+    ) -> StorageResult<[u8; 32]> {
+        if let Ok(crate_len) = usize::try_from(crate_len)
+            && crate_len <= MULTIPART_PART_SIZE
+        {
+            let mut crate_bytes = BytesMut::with_capacity(crate_len);
+            while let Some(chunk) = crate_body.next().await {
+                crate_bytes.extend_from_slice(&chunk.context("Error while receiving the crate")?);
+            }
+            let tarball_checksum: [u8; 32] = Sha256::digest(&crate_bytes).into();
+            self.put_crate(name, version, crate_bytes.freeze(), tarball_checksum)
+                .await?;
+            return Ok(tarball_checksum);
+        }
+
+        // The final path depends on the checksum, which isn't known until the upload is done.
+        // The crate name is used only for debugging, and doesn't need escaping in the copy source.
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let upload_path = format!("uploads/{name}-{nanos:x}.tar.gz");
+
+        let tarball_checksum = self.put_multipart(&upload_path, crate_body).await?;
+        let [_, new_path] = construct_paths(name, version, tarball_checksum);
+        let res = self.copy_object(&upload_path, new_path).await;
+        if let Err(e) = self.delete_object(upload_path).await {
+            error!("Failed to delete uploaded crate after copying: {e}");
+        }
+        res?;
+        Ok(tarball_checksum)
+    }
+
     async fn delete_crate(
         &self,
         name: &str,
@@ -296,6 +565,10 @@ impl StorageProvider for S3StorageProvider {
     }
 }
 
+fn last_modified(resp: &GetObjectOutput) -> Option<chrono::DateTime<chrono::Utc>> {
+    resp.last_modified().and_then(|d| chrono::DateTime::from_timestamp(d.secs(), 0))
+}
+
 #[inline(always)]
 fn construct_paths(name: &str, version: &str, tarball_checksum: [u8; 32]) -> [String; 2] {
     [
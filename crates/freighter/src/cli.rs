@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -7,4 +7,38 @@ pub struct FreighterArgs {
     /// Path to the config file.
     #[arg(short, long)]
     pub config: PathBuf,
+
+    /// Runs the server if not given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Write the index, tarballs, owners and tokens to a backup archive.
+    Export {
+        /// Path of the archive to create.
+        path: PathBuf,
+    },
+    /// Restore a backup archive made by `export` into the configured backends.
+    Import {
+        /// Path of the archive to restore.
+        path: PathBuf,
+    },
+    /// Copy every crate from one index backend into the other, and verify that the index entries
+    /// are the same afterwards.
+    ///
+    /// The config file needs the settings of both backends.
+    MigrateIndex {
+        #[arg(long, value_enum)]
+        from: IndexBackend,
+        #[arg(long, value_enum)]
+        to: IndexBackend,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexBackend {
+    Filesystem,
+    Postgresql,
 }
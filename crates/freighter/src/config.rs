@@ -1,23 +1,195 @@
+use anyhow::Context;
 use freighter_api_types::index::IndexProvider;
+use freighter_api_types::storage::StorageProvider;
 use freighter_auth::AuthProvider;
 use freighter_server::ServiceConfig;
+use freighter_storage::fs::FsStorageProvider;
+use freighter_storage::s3_client::S3StorageProvider;
 use serde::Deserialize;
+use std::path::PathBuf;
 
 #[derive(Deserialize)]
-pub struct Config<I: IndexProvider, A: AuthProvider> {
+pub struct Config {
     pub service: ServiceConfig,
-    #[serde(flatten)]
-    pub index_config: I::Config,
-    #[serde(flatten)]
-    pub auth_config: A::Config,
+    pub index: IndexConfig,
+    pub auth: AuthConfig,
     pub store: StoreConfig,
+    #[serde(default)]
+    pub audit: Option<AuditConfig>,
+}
+
+/// Backends left out of the build are still known, so that they're reported as disabled rather than unknown
+#[allow(dead_code)]
+type Disabled = serde::de::IgnoredAny;
+
+#[allow(dead_code)]
+fn disabled<T>(feature: &str) -> anyhow::Result<T> {
+    anyhow::bail!("This backend isn't enabled in this build of freighter, it needs the {feature} feature")
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IndexConfig {
+    #[cfg(feature = "filesystem-index-backend")]
+    Filesystem(freighter_fs_index::Config),
+    #[cfg(not(feature = "filesystem-index-backend"))]
+    Filesystem(Disabled),
+    #[cfg(feature = "postgresql-index-backend")]
+    Postgresql(Box<freighter_pg_index::Config>),
+    #[cfg(not(feature = "postgresql-index-backend"))]
+    Postgresql(Disabled),
+}
+
+impl IndexConfig {
+    pub fn build(self) -> anyhow::Result<Box<dyn IndexProvider + Send + Sync>> {
+        match self {
+            #[cfg(feature = "filesystem-index-backend")]
+            Self::Filesystem(config) => Ok(Box::new(freighter_fs_index::FsIndexProvider::new(config)?)),
+            #[cfg(not(feature = "filesystem-index-backend"))]
+            Self::Filesystem(_) => disabled("filesystem-index-backend"),
+            #[cfg(feature = "postgresql-index-backend")]
+            Self::Postgresql(config) => Ok(Box::new(freighter_pg_index::PgIndexProvider::new(*config)?)),
+            #[cfg(not(feature = "postgresql-index-backend"))]
+            Self::Postgresql(_) => disabled("postgresql-index-backend"),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Filesystem(_) => "filesystem",
+            Self::Postgresql(_) => "postgresql",
+        }
+    }
 }
 
 #[derive(Deserialize)]
-pub struct StoreConfig {
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthConfig {
+    #[cfg(feature = "filesystem-auth-backend")]
+    Filesystem(freighter_auth::fs_backend::Config),
+    #[cfg(not(feature = "filesystem-auth-backend"))]
+    Filesystem(Disabled),
+    #[cfg(feature = "postgresql-auth-backend")]
+    Postgresql(freighter_auth::pg_backend::Config),
+    #[cfg(not(feature = "postgresql-auth-backend"))]
+    Postgresql(Disabled),
+    #[cfg(feature = "cloudflare-auth-backend")]
+    Cloudflare(freighter_auth::cf_backend::Config),
+    #[cfg(not(feature = "cloudflare-auth-backend"))]
+    Cloudflare(Disabled),
+    #[cfg(feature = "oidc-auth-backend")]
+    Oidc(freighter_auth::oidc_backend::Config),
+    #[cfg(not(feature = "oidc-auth-backend"))]
+    Oidc(Disabled),
+    #[cfg(feature = "yes-auth-backend")]
+    Yes(freighter_auth::yes_backend::Config),
+    #[cfg(not(feature = "yes-auth-backend"))]
+    Yes(Disabled),
+    /// Nobody can do anything that needs auth
+    None,
+}
+
+impl AuthConfig {
+    pub fn build(self) -> anyhow::Result<Box<dyn AuthProvider + Send + Sync>> {
+        match self {
+            #[cfg(feature = "filesystem-auth-backend")]
+            Self::Filesystem(config) => Ok(Box::new(freighter_auth::fs_backend::FsAuthProvider::new(config)?)),
+            #[cfg(not(feature = "filesystem-auth-backend"))]
+            Self::Filesystem(_) => disabled("filesystem-auth-backend"),
+            #[cfg(feature = "postgresql-auth-backend")]
+            Self::Postgresql(config) => Ok(Box::new(freighter_auth::pg_backend::PgAuthProvider::new(config)?)),
+            #[cfg(not(feature = "postgresql-auth-backend"))]
+            Self::Postgresql(_) => disabled("postgresql-auth-backend"),
+            #[cfg(feature = "cloudflare-auth-backend")]
+            Self::Cloudflare(config) => Ok(Box::new(freighter_auth::cf_backend::CfAuthProvider::new(config)?)),
+            #[cfg(not(feature = "cloudflare-auth-backend"))]
+            Self::Cloudflare(_) => disabled("cloudflare-auth-backend"),
+            #[cfg(feature = "oidc-auth-backend")]
+            Self::Oidc(config) => Ok(Box::new(freighter_auth::oidc_backend::OidcAuthProvider::new(config)?)),
+            #[cfg(not(feature = "oidc-auth-backend"))]
+            Self::Oidc(_) => disabled("oidc-auth-backend"),
+            #[cfg(feature = "yes-auth-backend")]
+            Self::Yes(config) => Ok(Box::new(freighter_auth::yes_backend::YesAuthProvider::new(config)?)),
+            #[cfg(not(feature = "yes-auth-backend"))]
+            Self::Yes(_) => disabled("yes-auth-backend"),
+            Self::None => Ok(Box::new(freighter_auth::no_backend::NoAuthProvider::new(())?)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Filesystem(_) => "filesystem",
+            Self::Postgresql(_) => "postgresql",
+            Self::Cloudflare(_) => "cloudflare",
+            Self::Oidc(_) => "oidc",
+            Self::Yes(_) => "yes",
+            Self::None => "none",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StoreConfig {
+    S3(S3StoreConfig),
+    /// Tarballs in a local directory
+    Filesystem { path: PathBuf },
+}
+
+#[derive(Deserialize)]
+pub struct S3StoreConfig {
     pub name: String,
     pub endpoint_url: String,
     pub region: String,
     pub access_key_id: Option<String>,
     pub access_key_secret: Option<String>,
 }
+
+impl StoreConfig {
+    pub fn build(self) -> anyhow::Result<Box<dyn StorageProvider + Send + Sync>> {
+        Ok(match self {
+            Self::S3(store) => Box::new(S3StorageProvider::new(
+                &store.name,
+                &store.endpoint_url,
+                &store.region,
+                &store.access_key_id.map_or_else(
+                    || std::env::var("FREIGHTER_STORE_BUCKET_KEY_ID"),
+                    Ok,
+                ).context("Failed to find store bucket key id in environment variable or config")?,
+                &store.access_key_secret.map_or_else(
+                    || std::env::var("FREIGHTER_STORE_BUCKET_KEY_SECRET"),
+                    Ok,
+                ).context("Failed to find store bucket key secret in environment variable or config")?,
+            )),
+            Self::Filesystem { path } => Box::new(
+                FsStorageProvider::new(path).context("Failed to create the storage directory")?,
+            ),
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::S3(_) => "s3",
+            Self::Filesystem { .. } => "filesystem",
+        }
+    }
+}
+
+/// Where to keep the audit log of operations that change the registry
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditConfig {
+    /// Appends JSON lines to a file
+    File { path: PathBuf },
+    /// The `audit_log` table in a postgres database
+    #[cfg(feature = "postgresql-index-backend")]
+    Postgres { db: Box<deadpool_postgres::Config> },
+}
+
+/// Settings of both index backends, for migrating between them
+#[cfg(all(feature = "filesystem-index-backend", feature = "postgresql-index-backend"))]
+#[derive(Deserialize)]
+pub struct MigrationConfig {
+    pub filesystem_index: freighter_fs_index::Config,
+    pub postgresql_index: freighter_pg_index::Config,
+}
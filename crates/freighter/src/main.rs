@@ -1,29 +1,15 @@
 use anyhow::Context;
 use clap::Parser;
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "filesystem-index-backend")] {
-        use freighter_fs_index::FsIndexProvider as SelectedIndexProvider;
-    } else if #[cfg(feature = "postgresql-index-backend")] {
-        use freighter_pg_index::PgIndexProvider as SelectedIndexProvider;
-    } else {
-        compile_error!("Use cargo features to select an index backend");
-    }
-}
-cfg_if::cfg_if! {
-    if #[cfg(feature = "filesystem-auth-backend")] {
-        use freighter_auth::fs_backend::FsAuthProvider as SelectedAuthProvider;
-    } else if #[cfg(feature = "cloudflare-auth-backend")] {
-        use freighter_auth::cf_backend::CfAuthProvider as SelectedAuthProvider;
-    } else if #[cfg(feature = "yes-auth-backend")] {
-        use freighter_auth::yes_backend::YesAuthProvider as SelectedAuthProvider;
-    } else {
-        use freighter_auth::no_backend::NoAuthProvider as SelectedAuthProvider;
-    }
-}
-use freighter_storage::s3_client::S3StorageProvider;
+use freighter_api_types::audit::AuditSink;
+use freighter_server::audit::FileAuditSink;
+use freighter_server::backup;
+use freighter_server::ServiceState;
 use metrics_exporter_prometheus::PrometheusBuilder;
-use std::fs::read_to_string;
+use std::fs::{read_to_string, File};
+use std::io::{BufReader, BufWriter};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::net::TcpListener;
 
 mod cli;
@@ -35,19 +21,52 @@ async fn main() -> anyhow::Result<()> {
 
     let args = cli::FreighterArgs::parse();
 
-    let config: config::Config<SelectedIndexProvider, SelectedAuthProvider> = serde_yaml::from_str(
-        &read_to_string(args.config)
-            .context("Failed to read config file from disk, is it present?")?,
-    )
-    .context("Failed to deserialize config file, please make sure its in the right format")?;
+    let config_file = read_to_string(args.config)
+        .context("Failed to read config file from disk, is it present?")?;
+
+    if let Some(cli::Command::MigrateIndex { from, to }) = args.command {
+        return migrate_index(&config_file, from, to).await;
+    }
+
+    let config: config::Config = serde_yaml::from_str(&config_file).context(
+        "Failed to deserialize config file, please make sure its in the right format",
+    )?;
 
     let config::Config {
         service,
-        index_config,
-        auth_config,
+        index,
+        auth,
         store,
+        audit,
     } = config;
 
+    let backend_names = (index.name(), auth.name(), store.name());
+    let index_client = index.build().context("Failed to construct index client")?;
+    let storage_client = store.build().context("Failed to construct storage client")?;
+    let auth_client = auth.build().context("Failed to initialize auth client")?;
+
+    match args.command {
+        None | Some(cli::Command::MigrateIndex { .. }) => {}
+        Some(cli::Command::Export { path }) => {
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let stats = backup::export(&*index_client, &*storage_client, &*auth_client, BufWriter::new(file))
+                .await
+                .context("Failed to export the registry")?;
+            tracing::info!(?stats, "Exported the registry to {}", path.display());
+            return Ok(());
+        }
+        Some(cli::Command::Import { path }) => {
+            let file = File::open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            let stats = backup::import(&*index_client, &*storage_client, &*auth_client, BufReader::new(file))
+                .await
+                .context("Failed to import the registry")?;
+            tracing::info!(?stats, "Imported the registry from {}", path.display());
+            return Ok(());
+        }
+    }
+
     PrometheusBuilder::new()
         .with_http_listener(service.metrics_address)
         .set_buckets(&[
@@ -60,50 +79,92 @@ async fn main() -> anyhow::Result<()> {
 
     let addr = service.address;
 
-    let index_client =
-        SelectedIndexProvider::new(index_config).context("Failed to construct index client")?;
-
-    let storage_client = S3StorageProvider::new(
-        &store.name,
-        &store.endpoint_url,
-        &store.region,
-        &store.access_key_id.unwrap_or_else(|| {
-            std::env::var("FREIGHTER_STORE_BUCKET_KEY_ID")
-                .expect("Failed to find store bucket key id in environment variable or config")
-        }),
-        &store.access_key_secret.unwrap_or_else(|| {
-            std::env::var("FREIGHTER_STORE_BUCKET_KEY_SECRET")
-                .expect("Failed to find store bucket key secret in environment variable or config")
-        }),
-    );
-    let auth_client =
-        SelectedAuthProvider::new(auth_config).context("Failed to initialize auth client")?;
+    let audit_sink = match audit {
+        None => None,
+        Some(config::AuditConfig::File { path }) => Some(Box::new(
+            FileAuditSink::new(path).context("Failed to initialize audit log")?,
+        ) as Box<dyn AuditSink + Send + Sync>),
+        #[cfg(feature = "postgresql-index-backend")]
+        Some(config::AuditConfig::Postgres { db }) => Some(Box::new(
+            freighter_pg_index::PgAuditSink::new(&db).context("Failed to initialize audit log")?,
+        ) as Box<dyn AuditSink + Send + Sync>),
+    };
 
-    let router = freighter_server::router(
+    let state = Arc::new(ServiceState::new(
         service,
-        Box::new(index_client),
-        Box::new(storage_client),
-        Box::new(auth_client),
-    );
+        index_client,
+        storage_client,
+        auth_client,
+        audit_sink,
+    ));
+    let router = freighter_server::router_with_state(state.clone());
 
     tracing::info!(
         ?addr,
-        "Starting freighter instance with {} index and {} auth",
-        std::any::type_name::<SelectedIndexProvider>(),
-        std::any::type_name::<SelectedAuthProvider>()
+        "Starting freighter instance with {} index, {} auth and {} storage",
+        backend_names.0,
+        backend_names.1,
+        backend_names.2
     );
 
     let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, router.into_make_service())
+    let res = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
         .with_graceful_shutdown(shutdown_signal())
-        .await
-        .context("Freighter server exited with error")?;
+        .await;
+
+    // downloads counted since the last periodic flush
+    state.download_counts.flush(&*state.index).await;
+    res.context("Freighter server exited with error")?;
 
     tracing::info!("Completed graceful shutdown");
 
     Ok(())
 }
 
+#[cfg(all(feature = "filesystem-index-backend", feature = "postgresql-index-backend"))]
+async fn migrate_index(
+    config_file: &str,
+    from: cli::IndexBackend,
+    to: cli::IndexBackend,
+) -> anyhow::Result<()> {
+    use freighter_api_types::index::IndexProvider;
+
+    anyhow::ensure!(from != to, "The index can only be migrated to a different backend");
+
+    let config: config::MigrationConfig = serde_yaml::from_str(config_file)
+        .context("Failed to deserialize config file, it needs filesystem_index and postgresql_index settings")?;
+    let fs_index = freighter_fs_index::FsIndexProvider::new(config.filesystem_index)
+        .context("Failed to construct filesystem index client")?;
+    let pg_index = freighter_pg_index::PgIndexProvider::new(config.postgresql_index)
+        .context("Failed to construct postgresql index client")?;
+
+    let (from_index, to_index): (&(dyn IndexProvider + Send + Sync), &(dyn IndexProvider + Send + Sync)) =
+        match from {
+            cli::IndexBackend::Filesystem => (&fs_index, &pg_index),
+            cli::IndexBackend::Postgresql => (&pg_index, &fs_index),
+        };
+    let stats = backup::migrate_index(from_index, to_index)
+        .await
+        .context("Failed to migrate the index")?;
+    tracing::info!(?stats, "Migrated the index from {from:?} to {to:?}");
+    Ok(())
+}
+
+#[cfg(not(all(feature = "filesystem-index-backend", feature = "postgresql-index-backend")))]
+#[allow(clippy::unused_async)]
+async fn migrate_index(
+    _config_file: &str,
+    _from: cli::IndexBackend,
+    _to: cli::IndexBackend,
+) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "Migrating the index requires both the filesystem-index-backend and postgresql-index-backend features"
+    )
+}
+
 // Based on: https://github.com/tokio-rs/axum/blob/main/examples/graceful-shutdown/src/main.rs
 async fn shutdown_signal() {
     #[cfg(unix)]
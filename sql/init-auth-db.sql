@@ -10,7 +10,7 @@ create table freighter_users
 
 drop table if exists freighter_tokens cascade;
 
--- bf hash of token
+-- hex sha256 of token
 create table freighter_tokens
 (
     id         integer not null primary key generated always as identity,
@@ -24,11 +24,23 @@ create table freighter_crate_owners
 (
     id      integer not null primary key generated always as identity,
     user_id integer not null references freighter_users (id),
-    crate   text    not null,
-    unique (user_id, crate)
+    crate   text    not null
+);
+
+drop table if exists freighter_public_keys cascade;
+
+-- keys of cargo's asymmetric tokens, by their k3.pid PASERK
+create table freighter_public_keys
+(
+    id         integer not null primary key generated always as identity,
+    user_id    integer not null references freighter_users (id),
+    key_id     text    not null unique,
+    public_key text    not null
 );
 
 create index freighter_tokens_user_index on freighter_tokens (user_id);
 create index freighter_tokens_hash_index on freighter_tokens (token_hash);
-create index freighter_crate_owners_crates_index on freighter_crate_owners (crate);
+-- names that differ only in case or - vs _ are the same crate, like on crates.io
+create unique index freighter_crate_owners_canonical_index on freighter_crate_owners (user_id, lower(replace(crate, '-', '_')));
+create index freighter_crate_owners_crates_index on freighter_crate_owners (lower(replace(crate, '-', '_')));
 create index freighter_crate_owners_users_index on freighter_crate_owners (user_id);
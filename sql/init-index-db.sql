@@ -52,6 +52,16 @@ create table crate_versions
     cksum   text    not null,
     yanked  bool    not null default false,
     links   text,
+    -- the crate's metadata, as it was when this version was published
+    description   text,
+    license       text,
+    license_file  text,
+    homepage      text,
+    documentation text,
+    repository    text,
+    crate_size    bigint,
+    published_by  text,
+    created_at    timestamptz,
     unique (crate, version)
 );
 
@@ -83,11 +93,46 @@ create table dependencies
     package          text
 );
 
+drop table if exists version_downloads cascade;
+create table version_downloads
+(
+    crate_version integer not null references crate_versions (id),
+    date          date    not null,
+    downloads     bigint  not null,
+    primary key (crate_version, date)
+);
+
+drop type if exists audit_operation cascade;
+create type audit_operation as enum ('publish', 'yank', 'unyank', 'add_owners', 'remove_owners', 'register');
+drop type if exists audit_outcome cascade;
+create type audit_outcome as enum ('success', 'failure');
+
+-- not referencing other tables, so that the log outlives what it's about
+drop table if exists audit_log cascade;
+create table audit_log
+(
+    id         bigint primary key generated always as identity,
+    timestamp  timestamptz     not null,
+    operation  audit_operation not null,
+    actor      text,
+    token_id   text,
+    crate_name text,
+    version    text,
+    source_ip  inet,
+    outcome    audit_outcome   not null,
+    status     integer         not null,
+    details    text
+);
+
 create index crate_keyword_crate on crate_keywords (crate);
 create index crate_keyword_keyword on crate_keywords (keyword);
 create index crate_categories_crate on crate_keywords (crate);
 create index crate_categories_category on crate_categories (category);
 create index crates_name_index on crates (name);
+-- names that differ only in case or - vs _ are the same crate, like on crates.io
+create unique index crates_canonical_name_index on crates (lower(replace(name, '-', '_')), registry);
 create index crate_versions_crate_index on crate_versions (crate);
 create index features_index on features (crate_version);
 create index dependencies_dependent_index on dependencies (dependent);
+create index dependencies_dependency_index on dependencies (dependency);
+create index audit_log_crate_name_index on audit_log (lower(replace(crate_name, '-', '_')));
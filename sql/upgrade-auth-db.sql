@@ -0,0 +1,22 @@
+-- Adds what newer versions of init-auth-db.sql create to an existing database, keeping its data.
+-- Safe to run more than once.
+
+create table if not exists freighter_public_keys
+(
+    id         integer not null primary key generated always as identity,
+    user_id    integer not null references freighter_users (id),
+    key_id     text    not null unique,
+    public_key text    not null
+);
+
+-- owners are per crate, whichever spelling of its name was used
+alter table freighter_crate_owners drop constraint if exists freighter_crate_owners_user_id_crate_key;
+delete
+from freighter_crate_owners o
+    using freighter_crate_owners other
+where o.user_id = other.user_id
+  and lower(replace(o.crate, '-', '_')) = lower(replace(other.crate, '-', '_'))
+  and o.id > other.id;
+create unique index if not exists freighter_crate_owners_canonical_index on freighter_crate_owners (user_id, lower(replace(crate, '-', '_')));
+drop index if exists freighter_crate_owners_crates_index;
+create index freighter_crate_owners_crates_index on freighter_crate_owners (lower(replace(crate, '-', '_')));
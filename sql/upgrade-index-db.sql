@@ -0,0 +1,61 @@
+-- Adds what newer versions of init-index-db.sql create to an existing database, keeping its data.
+-- Safe to run more than once.
+
+-- Crate names that differ only in case or - vs _ have to be renamed or removed before the
+-- crates_canonical_name_index can be created. They're listed by:
+--   select lower(replace(name, '-', '_')), registry, array_agg(name)
+--   from crates group by 1, 2 having count(*) > 1;
+
+alter table crate_versions add column if not exists description text;
+alter table crate_versions add column if not exists license text;
+alter table crate_versions add column if not exists license_file text;
+alter table crate_versions add column if not exists homepage text;
+alter table crate_versions add column if not exists documentation text;
+alter table crate_versions add column if not exists repository text;
+alter table crate_versions add column if not exists crate_size bigint;
+alter table crate_versions add column if not exists published_by text;
+alter table crate_versions add column if not exists created_at timestamptz;
+
+create table if not exists version_downloads
+(
+    crate_version integer not null references crate_versions (id),
+    date          date    not null,
+    downloads     bigint  not null,
+    primary key (crate_version, date)
+);
+
+do
+$$
+    begin
+        create type audit_operation as enum ('publish', 'yank', 'unyank', 'add_owners', 'remove_owners', 'register');
+    exception
+        when duplicate_object then null;
+    end
+$$;
+do
+$$
+    begin
+        create type audit_outcome as enum ('success', 'failure');
+    exception
+        when duplicate_object then null;
+    end
+$$;
+
+create table if not exists audit_log
+(
+    id         bigint primary key generated always as identity,
+    timestamp  timestamptz     not null,
+    operation  audit_operation not null,
+    actor      text,
+    token_id   text,
+    crate_name text,
+    version    text,
+    source_ip  inet,
+    outcome    audit_outcome   not null,
+    status     integer         not null,
+    details    text
+);
+
+create unique index if not exists crates_canonical_name_index on crates (lower(replace(name, '-', '_')), registry);
+create index if not exists dependencies_dependency_index on dependencies (dependency);
+create index if not exists audit_log_crate_name_index on audit_log (lower(replace(crate_name, '-', '_')));